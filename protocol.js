@@ -5,7 +5,7 @@ const protocol = {
                 // this is just an implementation of what we have over in the rust program
                 encode(string) { // THANKS, STACKOVERFLOW
                     var utf8 = unescape(encodeURIComponent(string));
-                    var arr = [Math.floor(string.length / 256), string.length % 256]; // convert length to a big endian (network order) byte array
+                    var arr = [Math.floor(utf8.length / 256), utf8.length % 256]; // convert length to a big endian (network order) byte array. it has to be the UTF-8 byte count, not the character count
                     for (var i = 0; i < utf8.length; i++) {
                         arr.push(utf8.charCodeAt(i)); // push in the actual data
                     }
@@ -63,6 +63,18 @@ const protocol = {
             }
         }
     },
+    validate(op, index, value) { // mirrors the range and max_len checks the server runs on decode, so a bad value fails here instead of getting us kicked
+        var arg = op.args[index];
+        if (arg.range) {
+            var r = arg.range;
+            if ((r.min !== undefined && value < r.min) || (r.max !== undefined && (r.inclusive ? value > r.max : value >= r.max))) {
+                throw new RangeError("Argument " + index + " of " + op.name + " is out of range");
+            }
+        }
+        if (arg.max_len !== undefined && new TextEncoder().encode(value).length > arg.max_len) {
+            throw new RangeError("Argument " + index + " of " + op.name + " is longer than " + arg.max_len + " bytes");
+        }
+    },
     async connectV3(config, uri, secure = false) { // TODO: make this handle URIs better, right now it makes a lot of assumptions
         let manifest = await (await fetch(secure ? "https" : "http" + "://" + uri + "/manifest")).json();
         console.log(manifest);
@@ -82,7 +94,8 @@ const protocol = {
                 return (...args) => {
                     var out = [op.opcode];
                     for (var i = 0; i < op.args.length; i++) {
-                        out.push(...config.types[op.args[i]].encode(args[i]));
+                        protocol.validate(op, i, args[i]);
+                        out.push(...config.types[op.args[i].type].encode(args[i]));
                     }
                     socket.send(new Uint8Array(out));
                 }
@@ -99,8 +112,8 @@ const protocol = {
                     });
                     if (type) {
                         var retProps = [];
-                        type.args.forEach(rtype => {
-                            retProps.push(config.types[rtype].decode(bytearray));
+                        type.args.forEach(arg => {
+                            retProps.push(config.types[arg.type].decode(bytearray));
                         });
                         listener(type.name, retProps);
                     }
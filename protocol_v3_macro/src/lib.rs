@@ -1,129 +1,598 @@
-use proc_macro::*;
-use quote::quote;
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned, format_ident};
+use syn::spanned::Spanned;
+use syn::ext::IdentExt;
 
 
-#[proc_macro_derive(ProtocolFrame)]
-pub fn protocol_frame_derive(input : TokenStream) -> TokenStream {
-    let ast : syn::DeriveInput = syn::parse(input).unwrap();
-    let name = ast.ident;
-    match ast.data {
-        syn::Data::Enum (enumdata) => {
-            let mut encoder = vec![];
-            let mut decoder = vec![];
-            let mut identi : u8 = 0;
-            for variant in &enumdata.variants {
-                let ident = &variant.ident;
-                let mut argnames = vec![];
-                let mut i = 0;
-                for _ in &variant.fields {
-                    argnames.push(format!("a{}", i));
-                    i += 1;
-                };
-                let thang = if variant.fields.len() == 0 { quote!{} } else {
-                    let argstring = argnames.join(", ");
-                    (format!("({})", argstring)).parse().unwrap()
-                };
-                let argnames_tss = argnames.into_iter().map(|x| {
-                    x.parse::<proc_macro2::TokenStream>().unwrap()
-                });
-                encoder.push(quote! {
-                    #name::#ident #thang => {
-                        ret.push(#identi);
-                        #(
-                            let mut x = protocol_v3::protocol::protocol_encode(#argnames_tss.clone());
-                            ret.append(&mut x);
-                        )*
-                        ret
-                    }
-                });
-                let thang = if variant.fields.len() == 0 { quote!{} } else {
-                    let mut stuff = vec![];
-                    for field in &variant.fields {
-                        match &field.ty {
-                            syn::Type::Path (p) => {
-                                stuff.push(p.path.segments[0].ident.to_string().parse::<proc_macro2::TokenStream>().unwrap());
-                            }
-                            _ => {}
+const MAX_OPERATIONS : usize = 256; // opcodes are a single byte
+
+
+fn snake_case(ident : &str) -> String { // PlayerMove -> player_move, for the handler method names
+    let mut ret = String::new();
+    for (i, c) in ident.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                ret.push('_');
+            }
+            ret.extend(c.to_lowercase());
+        }
+        else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+
+fn json_string(s : &str) -> String { // a JSON string literal, quotes and all, for text that goes into the manifest as-is
+    let mut ret = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => ret += "\\\"",
+            '\\' => ret += "\\\\",
+            '\n' => ret += "\\n",
+            '\r' => ret += "\\r",
+            '\t' => ret += "\\t",
+            c if (c as u32) < 0x20 => ret += &format!("\\u{:04x}", c as u32),
+            c => ret.push(c)
+        }
+    }
+    ret + "\""
+}
+
+
+fn doc_comment(attrs : &[syn::Attribute]) -> Option<String> { // the /// lines on an item, joined up, without the space after the slashes
+    let mut lines = vec![];
+    for attr in attrs {
+        if let syn::Meta::NameValue (nv) = &attr.meta {
+            if nv.path.is_ident("doc") {
+                if let syn::Expr::Lit (syn::ExprLit { lit : syn::Lit::Str (s), .. }) = &nv.value {
+                    let line = s.value();
+                    lines.push(line.strip_prefix(' ').unwrap_or(&line).trim_end().to_string());
+                }
+            }
+        }
+    }
+    let doc = lines.join("\n").trim().to_string();
+    if doc.is_empty() { None } else { Some(doc) }
+}
+
+
+fn push_error(errors : &mut Option<syn::Error>, error : syn::Error) { // collect everything so the user sees every problem in one go, not one per build
+    match errors {
+        Some (e) => e.combine(error),
+        None => *errors = Some(error)
+    }
+}
+
+
+type Meta = Vec<(String, String)>; // #[protocol(meta(key = value, ...))], as keys and JSON values, in the order they were written
+
+
+fn parse_meta(meta : &syn::meta::ParseNestedMeta, into : &mut Meta) -> syn::Result<()> {
+    meta.parse_nested_meta(|entry| {
+        let key = match entry.path.get_ident() {
+            Some (ident) => ident.unraw().to_string(),
+            None => return Err(entry.error("meta keys are plain identifiers"))
+        };
+        if into.iter().any(|(k, _)| *k == key) {
+            return Err(entry.error(format!("duplicate meta key `{}`", key)));
+        }
+        let value = if entry.input.peek(syn::Token![=]) {
+            let input = entry.value()?;
+            let sign = if input.parse::<Option<syn::Token![-]>>()?.is_some() { "-" } else { "" };
+            match input.parse()? {
+                syn::Lit::Str (s) if sign.is_empty() => json_string(&s.value()),
+                syn::Lit::Int (i) => format!("{}{}", sign, i.base10_digits()),
+                syn::Lit::Float (f) => format!("{}{}", sign, f.base10_digits()),
+                syn::Lit::Bool (b) if sign.is_empty() => b.value.to_string(),
+                other => return Err(syn::Error::new_spanned(other, "meta values are strings, numbers or bools"))
+            }
+        }
+        else {
+            "true".to_string() // meta(deprecated) is meta(deprecated = true)
+        };
+        into.push((key, value));
+        Ok(())
+    })
+}
+
+
+fn manifest_extras(doc : Option<String>, meta : &Meta) -> String { // the ,"doc":...,"meta":{...} that enums, variants and fields all carry
+    let mut ret = String::new();
+    if let Some (doc) = doc {
+        ret += &format!(",\"doc\":{}", json_string(&doc));
+    }
+    if !meta.is_empty() {
+        let entries : Vec<String> = meta.iter().map(|(key, value)| format!("{}:{}", json_string(key), value)).collect();
+        ret += &format!(",\"meta\":{{{}}}", entries.join(","));
+    }
+    ret
+}
+
+
+struct FrameOptions {
+    handler : bool, // #[protocol(handler)] asks for a FooHandler trait and a Dispatch impl
+    meta    : Meta
+}
+
+
+fn parse_options(attrs : &[syn::Attribute]) -> syn::Result<FrameOptions> {
+    let mut ret = FrameOptions { handler : false, meta : vec![] };
+    for attr in attrs {
+        if attr.path().is_ident("protocol") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("handler") {
+                    ret.handler = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("meta") {
+                    parse_meta(&meta, &mut ret.meta)
+                }
+                else {
+                    Err(meta.error("unknown protocol attribute; expected `handler` or `meta(...)`"))
+                }
+            })?;
+        }
+    }
+    Ok(ret)
+}
+
+
+fn parse_variant_options(attrs : &[syn::Attribute]) -> syn::Result<Meta> { // variants only take meta(...) so far
+    let mut ret = vec![];
+    for attr in attrs {
+        if attr.path().is_ident("protocol") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("meta") {
+                    parse_meta(&meta, &mut ret)
+                }
+                else {
+                    Err(meta.error("unknown protocol variant attribute; expected `meta(...)`"))
+                }
+            })?;
+        }
+    }
+    Ok(ret)
+}
+
+
+struct FieldOptions {
+    skip : bool, // #[protocol(skip)]: never goes on the wire, and comes back as Default::default() on decode
+    default : Option<syn::Expr>, // #[protocol(default = "expr")]: skipped, and comes back as expr instead
+    range : Option<syn::ExprRange>, // #[protocol(range = 0..=100)]
+    max_len : Option<syn::Expr>, // #[protocol(max_len = 32)], a usize, in bytes for strings
+    validate : Option<syn::Path>, // #[protocol(validate = "path::to::fn")], fn(&T) -> Result<(), String>
+    meta : Meta
+}
+
+
+impl FieldOptions {
+    fn validated(&self) -> bool {
+        self.range.is_some() || self.max_len.is_some() || self.validate.is_some()
+    }
+}
+
+
+fn parse_field_options(field : &syn::Field) -> syn::Result<FieldOptions> {
+    let mut ret = FieldOptions { skip : false, default : None, range : None, max_len : None, validate : None, meta : vec![] };
+    for attr in &field.attrs {
+        if attr.path().is_ident("protocol") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    ret.skip = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("default") {
+                    let expr : syn::LitStr = meta.value()?.parse()?;
+                    ret.default = Some(expr.parse()?);
+                    ret.skip = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("range") {
+                    match meta.value()?.parse()? {
+                        syn::Expr::Range (range) => {
+                            ret.range = Some(range);
+                            Ok(())
                         }
+                        other => Err(syn::Error::new_spanned(other, "expected a range, like `0..=100`"))
                     }
-                    quote!{
-                        (
-                            #(
-                                protocol_v3::protocol::protocol_decode::<#stuff>(&mut data)?,
-                            )*
-                        )
-                    }
-                };
-                decoder.push(quote! {
-                    Some(#identi) => {
-                        Ok(#name::#ident #thang)
-                    }
-                });
-                if identi == 255 {
-                    panic!("At the moment, there is a hard cap of 255 frame types!");
-                }
-                identi += 1;
-            }
-            let mut manifest = "{\"protocol\":\"".to_string();
-            manifest += &name.to_string();
-            manifest += "\",\"operations\":[";
-            let mut identi : u8 = 0;
-            for variant in &enumdata.variants {
-                manifest += "{\"name\": \"";
-                manifest += &variant.ident.to_string();
-                manifest += "\",\"opcode\":";
-                manifest += &identi.to_string();
-                manifest += ",\"args\":[";
-                let mut j = 0;
-                for field in &variant.fields {
-                    manifest += "\"";
-                    manifest += &match &field.ty {
-                        syn::Type::Path (p) => p.path.segments[0].ident.to_string(),
-                        _ => String::new()
-                    };
-                    manifest += "\"";
-                    if j < variant.fields.len() - 1 {
-                        manifest += ",";
-                    }
-                    j += 1;
                 }
-                manifest += "]}";
-                if (identi as usize) < enumdata.variants.len() - 1 {
-                    manifest += ",";
+                else if meta.path.is_ident("max_len") {
+                    ret.max_len = Some(meta.value()?.parse()?);
+                    Ok(())
+                }
+                else if meta.path.is_ident("validate") {
+                    let path : syn::LitStr = meta.value()?.parse()?;
+                    ret.validate = Some(path.parse()?);
+                    Ok(())
                 }
-                identi += 1;
+                else if meta.path.is_ident("meta") {
+                    parse_meta(&meta, &mut ret.meta)
+                }
+                else {
+                    Err(meta.error("unknown protocol field attribute; expected `skip`, `default = \"...\"`, `range = ...`, `max_len = ...`, `validate = \"...\"` or `meta(...)`"))
+                }
+            })?;
+        }
+    }
+    if ret.skip && ret.validated() {
+        return Err(syn::Error::new_spanned(field, "skipped fields are never decoded, so they can't be validated"));
+    }
+    Ok(ret)
+}
+
+
+fn validation(operation : &syn::Ident, index : usize, arg : &syn::Ident, options : &FieldOptions) -> proc_macro2::TokenStream { // checks run on a freshly decoded field, bailing out with a ValidationError
+    let operation = operation.to_string();
+    let mut ret = vec![];
+    if let Some (range) = &options.range {
+        ret.push(quote_spanned! {range.span()=>
+            if !(#range).contains(&#arg) {
+                return Err(protocol_v3::protocol::ValidationError::new(#operation, #index, protocol_v3::protocol::ValidationFailure::OutOfRange).into());
             }
-            manifest += "]}";
-            quote! {
-                impl ProtocolFrame for #name {
-                    fn encode(&self) -> Vec<u8> {
-                        let mut ret : Vec<u8> = Vec::new();
-                        match self {
-                            #(
-                                #encoder
-                            )*
+        });
+    }
+    if let Some (max_len) = &options.max_len {
+        ret.push(quote_spanned! {max_len.span()=>
+            let max : usize = #max_len;
+            if #arg.len() > max {
+                return Err(protocol_v3::protocol::ValidationError::new(#operation, #index, protocol_v3::protocol::ValidationFailure::TooLong { max, len : #arg.len() }).into());
+            }
+        });
+    }
+    if let Some (validate) = &options.validate {
+        ret.push(quote_spanned! {validate.span()=>
+            let check : Result<(), String> = #validate(&#arg);
+            if let Err(reason) = check {
+                return Err(protocol_v3::protocol::ValidationError::new(#operation, #index, protocol_v3::protocol::ValidationFailure::Rejected (reason)).into());
+            }
+        });
+    }
+    quote! { #(#ret)* }
+}
+
+
+fn manifest_constraints(options : &FieldOptions) -> Vec<proc_macro2::TokenStream> { // the same rules, exported so clients can check before sending
+    let mut ret = vec![];
+    if let Some (range) = &options.range {
+        ret.push(quote! { manifest += ",\"range\":{"; });
+        let mut parts = vec![];
+        if let Some (start) = &range.start {
+            parts.push(quote! { manifest += "\"min\":"; manifest += &(#start).to_string(); });
+        }
+        if let Some (end) = &range.end {
+            parts.push(quote! { manifest += "\"max\":"; manifest += &(#end).to_string(); });
+        }
+        let inclusive = if matches!(range.limits, syn::RangeLimits::Closed (_)) { "\"inclusive\":true" } else { "\"inclusive\":false" };
+        parts.push(quote! { manifest += #inclusive; });
+        for (i, part) in parts.into_iter().enumerate() {
+            if i > 0 {
+                ret.push(quote! { manifest += ","; });
+            }
+            ret.push(part);
+        }
+        ret.push(quote! { manifest += "}"; });
+    }
+    if let Some (max_len) = &options.max_len {
+        ret.push(quote! { manifest += ",\"max_len\":"; manifest += &(#max_len).to_string(); });
+    }
+    if let Some (validate) = &options.validate {
+        let name = quote!(#validate).to_string().replace(' ', "");
+        let entry = format!(",\"validate\":\"{}\"", name);
+        ret.push(quote! { manifest += #entry; });
+    }
+    ret
+}
+
+
+fn check_field_type(ty : &syn::Type) -> syn::Result<()> {
+    match ty {
+        syn::Type::Path (_) => Ok(()),
+        syn::Type::Group (g) => check_field_type(&g.elem),
+        syn::Type::Paren (p) => check_field_type(&p.elem),
+        _ => Err(syn::Error::new_spanned(ty, "unsupported field type: protocol frame fields must be named types implementing ProtocolSegment (no references, tuples, arrays, slices or pointers)"))
+    }
+}
+
+
+fn parse_variants(enumdata : &syn::DataEnum) -> syn::Result<(Vec<Meta>, Vec<Vec<FieldOptions>>)> { // each variant's meta, and its fields' options
+    let mut errors = None;
+    let mut metas = vec![];
+    let mut ret = vec![];
+    for (index, variant) in enumdata.variants.iter().enumerate() {
+        if index == MAX_OPERATIONS {
+            push_error(&mut errors, syn::Error::new_spanned(&variant.ident, format!("too many variants: a protocol frame can have at most {} operations, because the opcode is a single byte", MAX_OPERATIONS)));
+        }
+        if let Some ((_, discriminant)) = &variant.discriminant {
+            push_error(&mut errors, syn::Error::new_spanned(discriminant, "explicit discriminants are not supported: opcodes are assigned in declaration order"));
+        }
+        match parse_variant_options(&variant.attrs) {
+            Ok (meta) => metas.push(meta),
+            Err (e) => push_error(&mut errors, e)
+        }
+        let mut fields = vec![];
+        for field in &variant.fields {
+            match parse_field_options(field) {
+                Ok (options) => {
+                    if !options.skip { // skipped fields never touch ProtocolSegment, so they can be whatever they like
+                        if let Err(e) = check_field_type(&field.ty) {
+                            push_error(&mut errors, e);
                         }
                     }
-                    fn decode(mut data : std::collections::VecDeque<u8>) -> Result<#name, protocol_v3::protocol::DecodeError> {
-                        match data.pop_front() {
-                            #(
-                                #decoder
-                            )*
-                            _ => {
-                                Err(protocol_v3::protocol::DecodeError{})
-                            }
-                        }
+                    fields.push(options);
+                }
+                Err (e) => push_error(&mut errors, e)
+            }
+        }
+        ret.push(fields);
+    }
+    match errors {
+        Some (e) => Err(e),
+        None => Ok((metas, ret))
+    }
+}
+
+
+fn bound_generics(generics : &syn::Generics) -> syn::Result<syn::Generics> { // every type parameter has to be encodable for the frame to be
+    let mut ret = generics.clone();
+    if let Some (lifetime) = ret.lifetimes().next() {
+        return Err(syn::Error::new_spanned(lifetime, "lifetime parameters are not supported: protocol frames are decoded into owned data"));
+    }
+    for param in ret.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(protocol_v3::protocol::ProtocolSegment));
+        param.bounds.push(syn::parse_quote!(Clone));
+    }
+    Ok(ret)
+}
+
+
+fn manifest_name(name : &syn::Ident, generics : &syn::Generics) -> Vec<proc_macro2::TokenStream> { // Msg<T> shows up as Msg<u32> (or whatever it was instantiated with) in the manifest
+    let mut ret = vec![];
+    let name = name.to_string();
+    ret.push(quote! { manifest += #name; });
+    if !generics.params.is_empty() {
+        ret.push(quote! { manifest += "<"; });
+        for (i, param) in generics.params.iter().enumerate() {
+            if i > 0 {
+                ret.push(quote! { manifest += ","; });
+            }
+            match param {
+                syn::GenericParam::Type (t) => {
+                    let ident = &t.ident;
+                    ret.push(quote! { manifest += &<#ident as protocol_v3::protocol::ProtocolSegment>::type_name(); });
+                }
+                syn::GenericParam::Const (c) => {
+                    let ident = &c.ident;
+                    ret.push(quote! { manifest += &#ident.to_string(); });
+                }
+                syn::GenericParam::Lifetime (_) => {} // rejected in bound_generics
+            }
+        }
+        ret.push(quote! { manifest += ">"; });
+    }
+    ret
+}
+
+
+fn bind(fields : &syn::Fields, bindings : &[proc_macro2::TokenStream]) -> proc_macro2::TokenStream { // the (a0, a1) or { x : a0, y : a1 } part of a variant pattern or constructor
+    match fields {
+        syn::Fields::Named (named) => {
+            let names = named.named.iter().map(|field| &field.ident);
+            quote!{ { #(#names : #bindings),* } }
+        }
+        syn::Fields::Unnamed (_) => quote!{ (#(#bindings),*) },
+        syn::Fields::Unit => quote!{}
+    }
+}
+
+
+fn expand(ast : syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let options = parse_options(&ast.attrs)?;
+    let frame_extras = manifest_extras(doc_comment(&ast.attrs), &options.meta);
+    let name = ast.ident;
+    let vis = ast.vis;
+    let generics = bound_generics(&ast.generics)?;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let enumdata = match ast.data {
+        syn::Data::Enum (enumdata) => enumdata,
+        syn::Data::Struct (s) => {
+            return Err(syn::Error::new(s.struct_token.span, "only enums (not structs!) can be protocol frames"));
+        }
+        syn::Data::Union (u) => {
+            return Err(syn::Error::new(u.union_token.span, "only enums (not unions!) can be protocol frames"));
+        }
+    };
+    let (variant_meta, field_options) = parse_variants(&enumdata)?;
+    let mut encoder = vec![];
+    let mut encodable = vec![];
+    let mut decoder = vec![];
+    let mut handler_methods = vec![];
+    let mut dispatcher = vec![];
+    for ((identi, variant), options) in (0u8..).zip(&enumdata.variants).zip(&field_options) {
+        let ident = &variant.ident;
+        let argnames : Vec<syn::Ident> = (0..variant.fields.len()).map(|i| format_ident!("a{}", i)).collect();
+        let argtypes : Vec<&syn::Type> = variant.fields.iter().map(|field| &field.ty).collect();
+        // skipped fields are bound to _ when matching, and left out of the handler methods (incoming frames only ever have the default in them)
+        let patterns : Vec<proc_macro2::TokenStream> = argnames.iter().zip(options).map(|(arg, option)| if option.skip { quote!{ _ } } else { quote!{ #arg } }).collect();
+        let thang = bind(&variant.fields, &patterns);
+        let wire : Vec<(&syn::Ident, &&syn::Type)> = argnames.iter().zip(&argtypes).zip(options).filter(|(_, option)| !option.skip).map(|(field, _)| field).collect();
+        let params = variant.fields.iter().zip(&argnames).zip(options).filter(|(_, option)| !option.skip).map(|((field, arg), _)| field.ident.as_ref().unwrap_or(arg)); // named fields keep their names in the handler
+        let wiretypes = wire.iter().map(|(_, ty)| ty);
+        let method = format_ident!("on_{}", snake_case(&ident.unraw().to_string()));
+        let docs = variant.attrs.iter().filter(|attr| attr.path().is_ident("doc"));
+        handler_methods.push(quote! {
+            #(#docs)*
+            async fn #method(&mut self, #(#params : #wiretypes),*) {}
+        });
+        let wirenames = wire.iter().map(|(arg, _)| arg);
+        dispatcher.push(quote! {
+            #name::#ident #thang => handler.#method(#(#wirenames),*).await,
+        });
+        // the per-field calls are spanned to the field type, so a missing ProtocolSegment (or Clone) impl gets reported right where the field is declared
+        let encodes = wire.iter().map(|(arg, ty)| quote_spanned! {ty.span()=>
+            ret.append(&mut protocol_v3::protocol::protocol_encode::<#ty>(<#ty as Clone>::clone(#arg)));
+        });
+        encoder.push(quote! {
+            #name::#ident #thang => {
+                ret.push(#identi);
+                #(
+                    #encodes
+                )*
+                ret
+            }
+        });
+        let operation = ident.unraw().to_string();
+        let checks = wire.iter().enumerate().map(|(position, (arg, ty))| quote_spanned! {ty.span()=>
+            if let Err(reason) = <#ty as protocol_v3::protocol::ProtocolSegment>::encodable(#arg) {
+                return Err(protocol_v3::protocol::EncodeError { operation : #operation, field : #position, reason });
+            }
+        });
+        encodable.push(quote! {
+            #name::#ident #thang => {
+                #(
+                    #checks
+                )*
+                Ok(())
+            }
+        });
+        let decodes = argnames.iter().zip(&argtypes).zip(options).enumerate().map(|(index, ((arg, ty), option))| {
+            let value = match (&option.default, option.skip) {
+                (Some (expr), _) => quote_spanned! {expr.span()=> #expr },
+                (None, true) => quote_spanned! {ty.span()=> <#ty as Default>::default() },
+                (None, false) => quote_spanned! {ty.span()=> protocol_v3::protocol::protocol_decode::<#ty>(&mut data)? }
+            };
+            let position = options[..index].iter().filter(|option| !option.skip).count(); // index among the wire fields, which is what the manifest's args are numbered by
+            let validation = validation(ident, position, arg, option);
+            quote! {
+                let #arg : #ty = #value;
+                #validation
+            }
+        });
+        let thang = bind(&variant.fields, &argnames.iter().map(|arg| quote!{ #arg }).collect::<Vec<_>>());
+        decoder.push(quote! {
+            Some(#identi) => {
+                #(
+                    #decodes
+                )*
+                Ok(#name::#ident #thang)
+            }
+        });
+    }
+    let mut manifest = vec![]; // statements appending to the manifest string, since generic frames can only be named at runtime
+    manifest.push(quote! { manifest += "{\"protocol\":\""; });
+    manifest.append(&mut manifest_name(&name, &generics));
+    let operations = format!("\"{},\"operations\":[", frame_extras);
+    manifest.push(quote! { manifest += #operations; });
+    for (((identi, variant), options), meta) in enumdata.variants.iter().enumerate().zip(&field_options).zip(&variant_meta) {
+        let head = format!("{{\"name\": {},\"opcode\":{}{},\"args\":[", json_string(&variant.ident.unraw().to_string()), identi, manifest_extras(doc_comment(&variant.attrs), meta));
+        manifest.push(quote! { manifest += #head; });
+        let wire : Vec<(&syn::Field, &FieldOptions)> = variant.fields.iter().zip(options).filter(|(_, option)| !option.skip).collect();
+        for (j, (field, option)) in wire.iter().enumerate() {
+            let ty = &field.ty;
+            let constraints = manifest_constraints(option);
+            let extras = manifest_extras(doc_comment(&field.attrs), &option.meta);
+            let open = match &field.ident {
+                Some (ident) => format!("{{\"name\":{},\"type\":\"", json_string(&ident.unraw().to_string())),
+                None => "{\"type\":\"".to_string()
+            };
+            manifest.push(quote! {
+                manifest += #open;
+                manifest += &<#ty as protocol_v3::protocol::ProtocolSegment>::type_name();
+                manifest += "\"";
+                if let Some(schema) = <#ty as protocol_v3::protocol::ProtocolSegment>::schema() {
+                    manifest += ",\"schema\":";
+                    manifest += &schema;
+                }
+                #(
+                    #constraints
+                )*
+                manifest += #extras;
+                manifest += "}";
+            });
+            if j < wire.len() - 1 {
+                manifest.push(quote! { manifest += ","; });
+            }
+        }
+        manifest.push(quote! { manifest += "]}"; });
+        if identi < enumdata.variants.len() - 1 {
+            manifest.push(quote! { manifest += ","; });
+        }
+    }
+    manifest.push(quote! { manifest += "]}"; });
+    let handler_impl = if options.handler {
+        let handler_name = format_ident!("{}Handler", name);
+        let mut dispatch_generics = generics.clone();
+        dispatch_generics.params.push(syn::parse_quote!(H : #handler_name #ty_generics));
+        let (dispatch_impl_generics, _, _) = dispatch_generics.split_for_impl();
+        let doc = format!("Handler for incoming [`{}`] frames: override the methods for the operations you care about, the rest are no-ops.", name);
+        quote! {
+            #[doc = #doc]
+            #[allow(async_fn_in_trait, unused_variables)]
+            #vis trait #handler_name #impl_generics #where_clause {
+                #(
+                    #handler_methods
+                )*
+            }
+
+            #[automatically_derived]
+            impl #dispatch_impl_generics protocol_v3::protocol::Dispatch<H> for #name #ty_generics #where_clause {
+                async fn dispatch(self, handler : &mut H) {
+                    match self {
+                        #(
+                            #dispatcher
+                        )*
                     }
-                    fn manifest() -> &'static str {
-                        #manifest
+                }
+            }
+        }
+    } else { quote!{} };
+    Ok(quote! {
+        #handler_impl
+        #[automatically_derived]
+        impl #impl_generics protocol_v3::protocol::ProtocolFrame for #name #ty_generics #where_clause {
+            fn encode(&self) -> Vec<u8> {
+                let mut ret : Vec<u8> = Vec::new();
+                match self {
+                    #(
+                        #encoder
+                    )*
+                }
+            }
+            fn encodable(&self) -> Result<(), protocol_v3::protocol::EncodeError> {
+                match self {
+                    #(
+                        #encodable
+                    )*
+                }
+            }
+            fn decode(mut data : std::collections::VecDeque<u8>) -> Result<Self, protocol_v3::protocol::DecodeError> {
+                match data.pop_front() {
+                    #(
+                        #decoder
+                    )*
+                    _ => {
+                        Err(protocol_v3::protocol::DecodeError::Malformed)
                     }
                 }
             }
-        },
-        _ => {
-            quote! {
-                compile_error!("Only enums (not structs!) can be protocol frames")
+            fn manifest() -> String {
+                let mut manifest = String::new();
+                #(
+                    #manifest
+                )*
+                manifest
             }
-        },
+        }
+    })
+}
+
+
+#[proc_macro_derive(ProtocolFrame, attributes(protocol))]
+pub fn protocol_frame_derive(input : TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    match expand(ast) {
+        Ok (tokens) => tokens,
+        Err (e) => e.to_compile_error()
     }.into()
-}
\ No newline at end of file
+}
@@ -0,0 +1,394 @@
+// tokio_util codecs for the wire. WebSocketCodec is RFC 6455 framing, for either end of the connection; ProtocolCodec puts messages back
+// together on top of it and turns them into ProtocolFrames. the server reads through the very same decoder, and with these the protocol goes
+// over any AsyncRead + AsyncWrite with tokio_util::codec::Framed - a test harness, a bot, a proxy - no WebSocketServer involved.
+
+use crate::protocol::ProtocolFrame;
+use crate::dynamic::DynamicError;
+use crate::server::{CloseCode, Disconnect};
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::marker::PhantomData;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Server, // reads masked frames, writes unmasked ones
+    Client // the other way around
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebSocketFrame {
+    Binary { fin : bool, payload : Vec<u8> },
+    Text { fin : bool, payload : Vec<u8> },
+    Continuation { fin : bool, payload : Vec<u8> }, // whether these are allowed depends on what came before, which is the message layer's business
+    Ping (Vec<u8>),
+    Pong (Vec<u8>),
+    Close (Vec<u8>)
+}
+
+
+impl WebSocketFrame {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            WebSocketFrame::Continuation { .. } => 0x0,
+            WebSocketFrame::Text { .. } => 0x1,
+            WebSocketFrame::Binary { .. } => 0x2,
+            WebSocketFrame::Close (_) => 0x8,
+            WebSocketFrame::Ping (_) => 0x9,
+            WebSocketFrame::Pong (_) => 0xA
+        }
+    }
+
+    pub fn fin(&self) -> bool {
+        match self {
+            WebSocketFrame::Binary { fin, .. } | WebSocketFrame::Text { fin, .. } | WebSocketFrame::Continuation { fin, .. } => *fin,
+            _ => true // control frames are never fragmented
+        }
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        match self {
+            WebSocketFrame::Binary { payload, .. } | WebSocketFrame::Text { payload, .. } | WebSocketFrame::Continuation { payload, .. } => payload,
+            WebSocketFrame::Ping (payload) | WebSocketFrame::Pong (payload) | WebSocketFrame::Close (payload) => payload
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct WebSocketCodec {
+    role           : Role,
+    max_frame_size : u64, // biggest payload decode will take, same as ServerConfig's
+    masks          : u64 // xorshift state for client masking keys. they only have to be unpredictable to whatever's between us and the server, not to the server
+}
+
+
+impl WebSocketCodec {
+    pub fn new(role : Role) -> Self {
+        Self { role, max_frame_size : 1 << 20, masks : RandomState::new().hash_one(0u8) | 1 }
+    }
+
+    pub fn max_frame_size(mut self, bytes : u64) -> Self {
+        self.max_frame_size = bytes;
+        self
+    }
+
+    fn next_mask(&mut self) -> [u8; 4] {
+        self.masks ^= self.masks << 13;
+        self.masks ^= self.masks >> 7;
+        self.masks ^= self.masks << 17;
+        (self.masks as u32).to_be_bytes()
+    }
+}
+
+
+pub(crate) fn header(opcode : u8, fin : bool, len : usize, mask : Option<[u8; 4]>) -> Vec<u8> { // everything that goes before the payload. RSV always 0
+    let mut ret = vec![if fin { 0b10000000 } else { 0 } | opcode];
+    let mask_bit = if mask.is_some() { 0b10000000 } else { 0 };
+    if len > 65535 {
+        ret.push(mask_bit | 127);
+        ret.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    else if len > 125 {
+        ret.push(mask_bit | 126);
+        ret.extend_from_slice(&(len as u16).to_be_bytes());
+    }
+    else {
+        ret.push(mask_bit | len as u8);
+    }
+    if let Some (mask) = mask {
+        ret.extend_from_slice(&mask);
+    }
+    ret
+}
+
+
+impl Decoder for WebSocketCodec {
+    type Item = WebSocketFrame;
+    type Error = Disconnect;
+
+    fn decode(&mut self, src : &mut BytesMut) -> Result<Option<WebSocketFrame>, Disconnect> { // checks everything it can as soon as the bytes for it are in, so a bad frame fails before its payload arrives
+        if src.len() < 2 {
+            return Ok(None);
+        }
+        let opcode = src[0] & 0b00001111;
+        let fin = src[0] & 0b10000000 != 0;
+        if src[0] & 0b01110000 != 0 { // RSV1-3: we never negotiate an extension, so they have to be 0
+            return Err(Disconnect::Protocol ("reserved bits set".to_string()));
+        }
+        if !matches!(opcode, 0x0 | 0x1 | 0x2 | 0x8 | 0x9 | 0xA) {
+            return Err(Disconnect::Protocol (format!("reserved opcode {:#x}", opcode)));
+        }
+        let masked = src[1] & 0b10000000 != 0;
+        match (self.role, masked) {
+            (Role::Server, false) => return Err(Disconnect::Protocol ("unmasked frame".to_string())), // clients always mask
+            (Role::Client, true) => return Err(Disconnect::Protocol ("masked frame from the server".to_string())), // and servers never do
+            _ => {}
+        }
+        let short_len = src[1] & 0b01111111;
+        if opcode & 0x8 != 0 && (!fin || short_len > 125) { // control frames can't be fragmented, and carry at most 125 bytes
+            return Err(Disconnect::Protocol ("fragmented or oversized control frame".to_string()));
+        }
+        let ext_len = match short_len {
+            126 => 2,
+            127 => 8,
+            _ => 0
+        };
+        let header_len = 2 + ext_len + if masked { 4 } else { 0 };
+        if src.len() < header_len {
+            return Ok(None);
+        }
+        let payload_len = match ext_len {
+            2 => u16::from_be_bytes([src[2], src[3]]) as u64,
+            8 => u64::from_be_bytes(src[2..10].try_into().unwrap()),
+            _ => short_len as u64
+        };
+        if payload_len >> 63 != 0 {
+            return Err(Disconnect::Protocol ("64 bit length with the high bit set".to_string()));
+        }
+        if payload_len > self.max_frame_size {
+            return Err(Disconnect::Oversize);
+        }
+        let frame_len = header_len + payload_len as usize;
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+        let mask = if masked { Some([src[header_len - 4], src[header_len - 3], src[header_len - 2], src[header_len - 1]]) } else { None };
+        src.advance(header_len);
+        let mut payload = src.split_to(payload_len as usize).to_vec();
+        if let Some (mask) = mask {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        Ok(Some(match opcode {
+            0x0 => WebSocketFrame::Continuation { fin, payload },
+            0x1 => WebSocketFrame::Text { fin, payload },
+            0x2 => WebSocketFrame::Binary { fin, payload },
+            0x8 => WebSocketFrame::Close (payload),
+            0x9 => WebSocketFrame::Ping (payload),
+            _ => WebSocketFrame::Pong (payload)
+        }))
+    }
+}
+
+
+impl Encoder<WebSocketFrame> for WebSocketCodec {
+    type Error = Disconnect;
+
+    fn encode(&mut self, frame : WebSocketFrame, dst : &mut BytesMut) -> Result<(), Disconnect> {
+        let payload = frame.payload();
+        if frame.opcode() & 0x8 != 0 && payload.len() > 125 {
+            return Err(Disconnect::Protocol ("control frame payload over 125 bytes".to_string()));
+        }
+        let mask = match self.role {
+            Role::Client => Some(self.next_mask()),
+            Role::Server => None
+        };
+        dst.extend_from_slice(&header(frame.opcode(), frame.fin(), payload.len(), mask));
+        match mask {
+            Some (mask) => dst.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4])),
+            None => dst.extend_from_slice(payload)
+        }
+        Ok(())
+    }
+}
+
+
+pub(crate) fn close_payload(code : CloseCode, reason : &str) -> Vec<u8> { // a code that may not go on the wire (1005, 1006, 0, ...) goes out as NORMAL, since the other end would take it as a protocol error
+    let code = if code.is_valid() { code } else { CloseCode::NORMAL };
+    let mut end = reason.len().min(123); // control frames carry at most 125 bytes, two of which are the code
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut ret = code.0.to_be_bytes().to_vec();
+    ret.extend_from_slice(&reason.as_bytes()[..end]);
+    ret
+}
+
+
+pub(crate) fn parse_close(payload : &[u8]) -> Result<(Option<CloseCode>, String), Disconnect> {
+    match payload {
+        [] => Ok((None, String::new())),
+        [_] => Err(Disconnect::Protocol ("one byte close payload".to_string())),
+        [high, low, reason @ ..] => {
+            let code = CloseCode (u16::from_be_bytes([*high, *low]));
+            if !code.is_valid() {
+                return Err(Disconnect::Protocol (format!("close code {} can't be sent", code.0)));
+            }
+            let reason = String::from_utf8(reason.to_vec()).map_err(|_| Disconnect::Protocol ("close reason isn't UTF-8".to_string()))?;
+            Ok((Some(code), reason))
+        }
+    }
+}
+
+
+pub(crate) enum Assembled {
+    Partial, // a fragment, with more to come
+    Message (bool, Vec<u8>), // a whole message, and whether it was text
+    Control (WebSocketFrame) // control frames can come in between the fragments of a message, and are the caller's to deal with on the spot
+}
+
+
+#[derive(Debug, Clone)]
+pub(crate) struct Assembler { // fragments in, messages out
+    data             : Vec<u8>,
+    text             : bool, // continuation frames don't say, so the first frame of the message decides
+    started          : bool, // whether we're partway through a fragmented message
+    max_message_size : usize
+}
+
+
+impl Assembler {
+    pub(crate) fn new(max_message_size : usize) -> Self {
+        Self { data : vec![], text : false, started : false, max_message_size }
+    }
+
+    pub(crate) fn push(&mut self, frame : WebSocketFrame) -> Result<Assembled, Disconnect> {
+        let (mut data, fin) = match frame {
+            WebSocketFrame::Binary { .. } | WebSocketFrame::Text { .. } if self.started => {
+                return Err(Disconnect::Protocol ("new message before the last one finished".to_string()));
+            }
+            WebSocketFrame::Continuation { .. } if !self.started => {
+                return Err(Disconnect::Protocol ("continuation frame with nothing to continue".to_string()));
+            }
+            WebSocketFrame::Binary { fin, payload } | WebSocketFrame::Continuation { fin, payload } => (payload, fin),
+            WebSocketFrame::Text { fin, payload } => {
+                self.text = true;
+                (payload, fin)
+            }
+            control => return Ok(Assembled::Control (control))
+        };
+        self.started = true;
+        self.data.append(&mut data);
+        if self.data.len() > self.max_message_size {
+            return Err(Disconnect::Oversize);
+        }
+        if !fin {
+            return Ok(Assembled::Partial);
+        }
+        self.started = false;
+        Ok(Assembled::Message (std::mem::take(&mut self.text), std::mem::take(&mut self.data)))
+    }
+}
+
+
+pub(crate) fn decode_message<Protocol : ProtocolFrame>(text : bool, data : Vec<u8>, json : bool) -> Result<Protocol, Disconnect> {
+    if text {
+        if !json {
+            return Err(Disconnect::Unsupported ("text messages aren't enabled".to_string()));
+        }
+        let json = String::from_utf8(data).map_err(|_| Disconnect::Poison (DynamicError::Json ("text message isn't UTF-8".to_string())))?;
+        return Protocol::from_json(&json).map_err(Disconnect::Poison);
+    }
+    ProtocolFrame::decode(data.into()).map_err(|e| Disconnect::Poison (e.into()))
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message<Protocol> { // what ProtocolCodec reads and writes: frames, plus the control traffic, which is left to whoever holds the Framed to answer
+    Frame (Protocol),
+    Ping (Vec<u8>),
+    Pong (Vec<u8>),
+    Close (Option<CloseCode>, String) // echo it back (a server should hang up after), then stop writing
+}
+
+
+pub struct ProtocolCodec<InProtocol, OutProtocol> { // In is what this end reads and Out what it writes, so a client's codec has the server's types the other way around
+    frames        : WebSocketCodec,
+    assembler     : Assembler,
+    fragment_size : Option<usize>,
+    json          : bool,
+    protocols     : PhantomData<fn(OutProtocol) -> InProtocol>
+}
+
+
+impl<InProtocol, OutProtocol> ProtocolCodec<InProtocol, OutProtocol> {
+    pub fn new(role : Role) -> Self { // same defaults as ServerConfig
+        Self { frames : WebSocketCodec::new(role), assembler : Assembler::new(4 << 20), fragment_size : Some(64 << 10), json : false, protocols : PhantomData }
+    }
+
+    pub fn max_frame_size(mut self, bytes : u64) -> Self {
+        self.frames = self.frames.max_frame_size(bytes);
+        self
+    }
+
+    pub fn max_message_size(mut self, bytes : usize) -> Self {
+        self.assembler.max_message_size = bytes;
+        self
+    }
+
+    pub fn fragment_size(mut self, bytes : Option<usize>) -> Self { // None writes every message as a single frame
+        self.fragment_size = bytes.map(|bytes| bytes.max(1));
+        self
+    }
+
+    pub fn json(mut self, json : bool) -> Self { // accept JSON text messages as well as binary ones
+        self.json = json;
+        self
+    }
+}
+
+
+impl<InProtocol : ProtocolFrame, OutProtocol> Decoder for ProtocolCodec<InProtocol, OutProtocol> {
+    type Item = Message<InProtocol>;
+    type Error = Disconnect;
+
+    fn decode(&mut self, src : &mut BytesMut) -> Result<Option<Message<InProtocol>>, Disconnect> {
+        while let Some (frame) = self.frames.decode(src)? {
+            match self.assembler.push(frame)? {
+                Assembled::Partial => {}
+                Assembled::Message (text, data) => return Ok(Some(Message::Frame (decode_message(text, data, self.json)?))),
+                Assembled::Control (WebSocketFrame::Ping (payload)) => return Ok(Some(Message::Ping (payload))),
+                Assembled::Control (WebSocketFrame::Pong (payload)) => return Ok(Some(Message::Pong (payload))),
+                Assembled::Control (frame) => {
+                    let (code, reason) = parse_close(frame.payload())?;
+                    return Ok(Some(Message::Close (code, reason)));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+
+pub(crate) fn fragments(data : &[u8], fragment_size : Option<usize>) -> impl Iterator<Item = (bool, bool, &[u8])> { // (first, fin, payload) for each frame of a message. an empty message is still one frame
+    let size = fragment_size.unwrap_or(usize::MAX).max(1);
+    let count = data.len().div_ceil(size).max(1);
+    (0..count).map(move |i| (i == 0, i == count - 1, &data[i * size..data.len().min((i + 1).saturating_mul(size))]))
+}
+
+
+impl<InProtocol, OutProtocol : ProtocolFrame> Encoder<Message<OutProtocol>> for ProtocolCodec<InProtocol, OutProtocol> {
+    type Error = Disconnect;
+
+    fn encode(&mut self, message : Message<OutProtocol>, dst : &mut BytesMut) -> Result<(), Disconnect> {
+        match message {
+            Message::Frame (frame) => {
+                frame.encodable()?;
+                let data = frame.encode();
+                for (first, fin, fragment) in fragments(&data, self.fragment_size) {
+                    let payload = fragment.to_vec();
+                    self.frames.encode(if first { WebSocketFrame::Binary { fin, payload } } else { WebSocketFrame::Continuation { fin, payload } }, dst)?;
+                }
+                Ok(())
+            }
+            Message::Ping (payload) => self.frames.encode(WebSocketFrame::Ping (payload), dst),
+            Message::Pong (payload) => self.frames.encode(WebSocketFrame::Pong (payload), dst),
+            Message::Close (code, reason) => self.frames.encode(WebSocketFrame::Close (code.map(|code| close_payload(code, &reason)).unwrap_or_default()), dst)
+        }
+    }
+}
+
+
+impl<InProtocol, OutProtocol : ProtocolFrame> Encoder<OutProtocol> for ProtocolCodec<InProtocol, OutProtocol> {
+    type Error = Disconnect;
+
+    fn encode(&mut self, frame : OutProtocol, dst : &mut BytesMut) -> Result<(), Disconnect> {
+        self.encode(Message::Frame (frame), dst)
+    }
+}
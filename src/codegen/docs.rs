@@ -0,0 +1,239 @@
+// protocol reference docs: Markdown (or a standalone HTML page) listing every operation with its opcode, its doc comment, and a byte layout
+// table. works for any manifest, since types it doesn't know the size of just make the offsets after them symbolic.
+
+use crate::manifest::{Argument, Manifest, Meta, Operation, ServerManifest};
+use super::clauses;
+
+
+const PREAMBLE : &str = "Every frame is one binary WebSocket message: an opcode byte, then the arguments in order. Numbers are big-endian, \
+bools are one byte (1 is true), and strings are a u16 byte length followed by that many bytes of UTF-8.";
+
+
+struct Row { // one line of a byte layout table
+    offset : String,
+    size   : String,
+    field  : String,
+    ty     : String,
+    notes  : String
+}
+
+
+fn fixed_size(ty : &str) -> Option<usize> {
+    match ty {
+        "u8" | "bool" => Some(1),
+        "u16" => Some(2),
+        "u32" | "i32" | "f32" => Some(4),
+        "u64" => Some(8),
+        _ => None
+    }
+}
+
+
+fn constraints(arg : &Argument) -> Vec<String> {
+    let mut ret = vec![];
+    if let Some (range) = &arg.range {
+        let min = range.min.map(|m| m.to_string()).unwrap_or_default();
+        let max = range.max.map(|m| m.to_string()).unwrap_or_default();
+        ret.push(format!("range `{}{}{}`", min, if range.inclusive { "..=" } else { ".." }, max));
+    }
+    if let Some (max_len) = arg.max_len {
+        ret.push(format!("at most {} bytes", max_len));
+    }
+    if let Some (validate) = &arg.validate {
+        ret.push(format!("checked by `{}` on the server", validate));
+    }
+    ret
+}
+
+
+fn meta_list(meta : &Meta) -> String { // `key` = value, ...
+    meta.iter().map(|(key, value)| format!("`{}` = {}", key, value)).collect::<Vec<_>>().join(", ")
+}
+
+
+struct Offset { // a running offset: so many bytes, plus the lengths of whatever variable-size fields came before
+    bytes    : usize,
+    variable : Vec<String>
+}
+
+
+impl Offset {
+    fn show(&self) -> String {
+        let mut parts = vec![self.bytes.to_string()];
+        parts.extend(self.variable.iter().cloned());
+        parts.join(" + ")
+    }
+}
+
+
+fn layout(op : &Operation) -> (Vec<Row>, String) { // the rows, and the total size
+    let mut rows = vec![Row { offset : "0".to_string(), size : "1".to_string(), field : "opcode".to_string(), ty : "u8".to_string(), notes : format!("always {}", op.opcode) }];
+    let mut at = Offset { bytes : 1, variable : vec![] };
+    for (i, arg) in op.args.iter().enumerate() {
+        let name = arg.display_name(i);
+        let mut notes = vec![];
+        if let Some (doc) = &arg.doc {
+            notes.push(doc.replace('\n', " "));
+        }
+        notes.extend(constraints(arg));
+        if !arg.meta.is_empty() {
+            notes.push(meta_list(&arg.meta));
+        }
+        match &arg.schema {
+            Some (schema) if schema.get("opaque").is_some() => notes.push("layout not described (no schema)".to_string()),
+            Some (_) => notes.push("layout in the manifest's schema".to_string()),
+            None => ()
+        }
+        let notes = clauses(&notes, ", ");
+        if let Some (size) = fixed_size(&arg.ty) {
+            rows.push(Row { offset : at.show(), size : size.to_string(), field : name, ty : arg.ty.clone(), notes });
+            at.bytes += size;
+        }
+        else if arg.ty == "String" {
+            rows.push(Row { offset : at.show(), size : "2".to_string(), field : format!("{} length", name), ty : "u16".to_string(), notes : String::new() });
+            at.bytes += 2;
+            let len = format!("len({})", name);
+            rows.push(Row { offset : at.show(), size : len.clone(), field : name, ty : "UTF-8".to_string(), notes });
+            at.variable.push(len);
+        }
+        else { // a custom ProtocolSegment: its layout is whatever the schema (if there is one) says
+            let size = format!("size({})", name);
+            rows.push(Row { offset : at.show(), size : size.clone(), field : name, ty : arg.ty.clone(), notes });
+            at.variable.push(size);
+        }
+    }
+    let total = at.show();
+    (rows, total)
+}
+
+
+fn signature(op : &Operation) -> String {
+    op.args.iter().enumerate().map(|(i, arg)| format!("{}: {}", arg.display_name(i), arg.ty)).collect::<Vec<_>>().join(", ")
+}
+
+
+fn cell(text : &str) -> String { // a | would end the table cell early
+    text.replace('|', "\\|")
+}
+
+
+fn markdown_protocol(title : &str, description : &str, manifest : &Manifest) -> String {
+    let mut ret = format!("## {}: `{}`\n\n{}\n\n", title, manifest.protocol, description);
+    if let Some (doc) = &manifest.doc {
+        ret += doc;
+        ret += "\n\n";
+    }
+    if !manifest.meta.is_empty() {
+        ret += &format!("Meta: {}\n\n", meta_list(&manifest.meta));
+    }
+    ret += "| Opcode | Operation | Arguments |\n|---:|---|---|\n";
+    for op in &manifest.operations {
+        ret += &format!("| {} | `{}` | {} |\n", op.opcode, cell(&op.name), cell(&signature(op)));
+    }
+    for op in &manifest.operations {
+        ret += &format!("\n### `{}` (opcode {})\n\n", op.name, op.opcode);
+        if let Some (doc) = &op.doc {
+            ret += doc;
+            ret += "\n\n";
+        }
+        if !op.meta.is_empty() {
+            ret += &format!("Meta: {}\n\n", meta_list(&op.meta));
+        }
+        let (rows, total) = layout(op);
+        ret += "| Offset | Size | Field | Type | Notes |\n|---:|---:|---|---|---|\n";
+        for row in rows {
+            ret += &format!("| {} | {} | {} | `{}` | {} |\n", cell(&row.offset), cell(&row.size), cell(&row.field), cell(&row.ty), cell(&row.notes));
+        }
+        ret += &format!("\nTotal: {} bytes.\n", total);
+    }
+    ret
+}
+
+
+pub fn generate_protocol(manifest : &Manifest) -> String {
+    let mut ret = format!("# {} protocol reference\n\n", manifest.protocol);
+    ret += &format!("Generated by protocol_v3 from the {} manifest. {}\n\n", manifest.protocol, PREAMBLE);
+    ret += &markdown_protocol("Protocol", "Every operation, in opcode order.", manifest);
+    ret
+}
+
+
+pub fn generate(manifest : &ServerManifest) -> String {
+    let mut ret = format!("# {} protocol reference\n\n", manifest.application_name);
+    ret += &format!("Generated by protocol_v3 from the {} manifest. {}\n\n", manifest.application_name, PREAMBLE);
+    ret += &markdown_protocol("Incoming", "Frames clients send to the server.", &manifest.incoming_protocol);
+    ret += "\n";
+    ret += &markdown_protocol("Outgoing", "Frames the server sends to clients.", &manifest.outgoing_protocol);
+    ret
+}
+
+
+fn escape(text : &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+fn html_doc(doc : &str) -> String { // blank lines split paragraphs; no other Markdown is rendered
+    doc.split("\n\n").map(|paragraph| format!("<p>{}</p>\n", escape(paragraph).replace('\n', "<br>\n"))).collect()
+}
+
+
+fn html_code(text : &str) -> String { // escapes, and turns `backticked` spans into <code>
+    escape(text).split('`').enumerate().map(|(i, part)| if i % 2 == 1 { format!("<code>{}</code>", part) } else { part.to_string() }).collect()
+}
+
+
+fn html_protocol(title : &str, description : &str, manifest : &Manifest) -> String {
+    let mut ret = format!("<h2>{}: <code>{}</code></h2>\n<p>{}</p>\n", title, escape(&manifest.protocol), description);
+    if let Some (doc) = &manifest.doc {
+        ret += &html_doc(doc);
+    }
+    if !manifest.meta.is_empty() {
+        ret += &format!("<p>Meta: {}</p>\n", html_code(&meta_list(&manifest.meta)));
+    }
+    ret += "<table>\n<tr><th>Opcode</th><th>Operation</th><th>Arguments</th></tr>\n";
+    for op in &manifest.operations {
+        ret += &format!("<tr><td>{}</td><td><a href=\"#{}-{}\"><code>{}</code></a></td><td>{}</td></tr>\n", op.opcode, title.to_lowercase(), op.opcode, escape(&op.name), escape(&signature(op)));
+    }
+    ret += "</table>\n";
+    for op in &manifest.operations {
+        ret += &format!("<h3 id=\"{}-{}\"><code>{}</code> (opcode {})</h3>\n", title.to_lowercase(), op.opcode, escape(&op.name), op.opcode);
+        if let Some (doc) = &op.doc {
+            ret += &html_doc(doc);
+        }
+        if !op.meta.is_empty() {
+            ret += &format!("<p>Meta: {}</p>\n", html_code(&meta_list(&op.meta)));
+        }
+        let (rows, total) = layout(op);
+        ret += "<table>\n<tr><th>Offset</th><th>Size</th><th>Field</th><th>Type</th><th>Notes</th></tr>\n";
+        for row in rows {
+            let notes = html_code(&row.notes);
+            ret += &format!("<tr><td>{}</td><td>{}</td><td>{}</td><td><code>{}</code></td><td>{}</td></tr>\n", escape(&row.offset), escape(&row.size), escape(&row.field), escape(&row.ty), notes);
+        }
+        ret += &format!("</table>\n<p>Total: {} bytes.</p>\n", escape(&total));
+    }
+    ret
+}
+
+
+fn html_page(title : &str, body : &str) -> String {
+    let mut ret = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    ret += &format!("<title>{} protocol reference</title>\n", escape(title));
+    ret += "<style>\nbody { font-family: sans-serif; max-width: 60em; margin: auto; padding: 1em; }\ntable { border-collapse: collapse; margin: 1em 0; }\nth, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }\n</style>\n";
+    ret += "</head>\n<body>\n";
+    ret += &format!("<h1>{} protocol reference</h1>\n", escape(title));
+    ret += &format!("<p>Generated by protocol_v3 from the {} manifest. {}</p>\n", escape(title), PREAMBLE);
+    ret += body;
+    ret + "</body>\n</html>\n"
+}
+
+
+pub fn generate_html_protocol(manifest : &Manifest) -> String {
+    html_page(&manifest.protocol, &html_protocol("Protocol", "Every operation, in opcode order.", manifest))
+}
+
+
+pub fn generate_html(manifest : &ServerManifest) -> String {
+    let body = html_protocol("Incoming", "Frames clients send to the server.", &manifest.incoming_protocol) + &html_protocol("Outgoing", "Frames the server sends to clients.", &manifest.outgoing_protocol);
+    html_page(&manifest.application_name, &body)
+}
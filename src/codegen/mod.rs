@@ -0,0 +1,110 @@
+// code generators: each one turns a manifest into a client for some other language or tool, so nobody has to hand-write the wire format again.
+
+use crate::manifest::{Argument, Meta};
+
+pub mod typescript;
+pub mod python;
+pub mod vectors;
+pub mod docs;
+pub mod wireshark;
+
+
+#[derive(Debug)]
+pub enum CodegenError {
+    UnsupportedType (String), // the manifest names a type the generator doesn't know how to encode
+    Opaque (String), // a Serde<T> argument whose Schema impl never spelled out its layout
+    SchemaType (String), // a Serde<T> argument, which this generator can't encode even with a schema
+    Unsatisfiable (String) // the manifest's validation rules leave no sample value that passes
+}
+
+
+impl std::error::Error for CodegenError {
+    fn description(&self) -> &str {
+        "The manifest can't be turned into code!"
+    }
+}
+
+
+impl std::fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CodegenError::UnsupportedType (ty) => write!(f, "Unsupported type in manifest: {}", ty),
+            CodegenError::Opaque (ty) => write!(f, "{} has no schema; override Schema::schema for it to describe its fields", ty),
+            CodegenError::SchemaType (ty) => write!(f, "{} is a Serde<T> argument, which this generator doesn't support", ty),
+            CodegenError::Unsatisfiable (what) => write!(f, "No sample value satisfies the validation rules for the {}", what)
+        }
+    }
+}
+
+
+pub(crate) fn identifier(name : &str) -> String { // Msg<u32,String> -> Msg_u32_String, for languages that don't like angle brackets in their names
+    let mut ret = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            ret.push(c);
+        }
+        else if !ret.ends_with('_') {
+            ret.push('_');
+        }
+    }
+    ret.trim_end_matches('_').to_string()
+}
+
+
+pub(crate) fn unsupported(arg : &Argument) -> CodegenError { // for generators that only know the built-in types: says so plainly when it's a Serde<T> argument
+    match arg.schema {
+        Some (_) => CodegenError::SchemaType (arg.ty.clone()),
+        None => CodegenError::UnsupportedType (arg.ty.clone())
+    }
+}
+
+
+pub(crate) fn checks(arg : &Argument, value : &str) -> Vec<(String, String)> { // range checks as (condition that must hold, what went wrong); plain comparisons, so most target languages can paste them in as-is
+    let mut ret = vec![];
+    if let Some (range) = &arg.range {
+        if let Some (min) = range.min {
+            ret.push((format!("{} >= {}", value, min), format!("must be at least {}", min)));
+        }
+        if let Some (max) = range.max {
+            if range.inclusive {
+                ret.push((format!("{} <= {}", value, max), format!("must be at most {}", max)));
+            }
+            else {
+                ret.push((format!("{} < {}", value, max), format!("must be less than {}", max)));
+            }
+        }
+    }
+    ret
+}
+
+
+pub(crate) fn describe(doc : Option<&str>, meta : &Meta) -> Vec<String> { // doc comment lines for generated code: the doc itself, then a `key: value` line per meta entry
+    let mut ret : Vec<String> = doc.map(|doc| doc.lines().map(|line| line.to_string()).collect()).unwrap_or_default();
+    for (key, value) in meta {
+        ret.push(format!("{}: {}", key, value));
+    }
+    ret
+}
+
+
+pub(crate) fn clauses(parts : &[String], separator : &str) -> String { // "What to say." and "at most 200 bytes" -> "What to say, at most 200 bytes": each part loses its closing punctuation but the last
+    let last = parts.len().saturating_sub(1);
+    parts.iter().enumerate().map(|(i, part)| if i < last { part.trim_end_matches(['.', ',', ';', ':']) } else { part.as_str() }).collect::<Vec<_>>().join(separator)
+}
+
+
+pub(crate) fn describe_args(args : &[Argument], prefix : &str) -> Vec<String> { // one line per documented argument, named the way the generated code names it
+    let mut ret = vec![];
+    for (i, arg) in args.iter().enumerate() {
+        let lines = describe(arg.doc.as_deref(), &arg.meta);
+        if !lines.is_empty() {
+            let position = prefix.replace("{}", &i.to_string()); // args[{}] in TypeScript, a{} in Python
+            let name = match &arg.name {
+                Some (name) => format!("{} ({})", position, name),
+                None => position
+            };
+            ret.push(format!("{}: {}", name, clauses(&lines, "; ")));
+        }
+    }
+    ret
+}
@@ -0,0 +1,168 @@
+// Python codec generator, for bots and tooling. produces one standalone module (just the standard library: struct and dataclasses) with a
+// dataclass per operation, each knowing how to encode itself, plus a decode function per protocol that hands back the right dataclass.
+
+use crate::manifest::{Manifest, Operation, ServerManifest};
+use super::{CodegenError, identifier, checks, describe, describe_args, unsupported};
+
+
+const RUNTIME : &str = r#"import struct
+from dataclasses import dataclass
+from typing import ClassVar, Union
+
+
+class DecodeError(ValueError):
+    pass
+
+
+class _Reader:
+    def __init__(self, data):
+        self.data = bytes(data)
+        self.offset = 0
+
+    def take(self, length):
+        if self.offset + length > len(self.data):
+            raise DecodeError("frame is truncated")
+        at = self.offset
+        self.offset += length
+        return at
+
+    def unpack(self, fmt):
+        return struct.unpack_from(fmt, self.data, self.take(struct.calcsize(fmt)))[0]
+
+    def bool(self):
+        return self.unpack(">B") == 1
+
+    def string(self):
+        length = self.unpack(">H")
+        at = self.take(length)
+        try:
+            return self.data[at:at + length].decode("utf-8")
+        except UnicodeDecodeError as e:
+            raise DecodeError("string is not valid UTF-8") from e
+
+
+def _pack_bool(value):
+    return struct.pack(">B", 1 if value else 0)
+
+
+def _pack_string(value):
+    utf8 = value.encode("utf-8")
+    if len(utf8) > 0xffff:
+        raise ValueError("strings are limited to 65535 bytes")
+    return struct.pack(">H", len(utf8)) + utf8
+"#;
+
+
+fn wire_type(ty : &str) -> Result<(&'static str, String, String), CodegenError> { // (Python type, expression packing {}, expression reading from r)
+    let number = |fmt : &str| (format!("struct.pack(\">{}\", {{}})", fmt), format!("r.unpack(\">{}\")", fmt));
+    let (python, (pack, read)) = match ty {
+        "u8" => ("int", number("B")),
+        "u16" => ("int", number("H")),
+        "u32" => ("int", number("I")),
+        "u64" => ("int", number("Q")),
+        "i32" => ("int", number("i")),
+        "f32" => ("float", number("f")),
+        "bool" => ("bool", ("_pack_bool({})".to_string(), "r.bool()".to_string())),
+        "String" => ("str", ("_pack_string({})".to_string(), "r.string()".to_string())),
+        _ => return Err(CodegenError::UnsupportedType (ty.to_string()))
+    };
+    Ok((python, pack, read))
+}
+
+
+fn class_name(protocol : &str, op : &Operation) -> String {
+    format!("{}{}", protocol, op.name)
+}
+
+
+fn operation(protocol : &str, op : &Operation) -> Result<String, CodegenError> {
+    let mut ret = String::from("@dataclass\n");
+    ret += &format!("class {}:\n", class_name(protocol, op));
+    let mut lines = describe(op.doc.as_deref(), &op.meta);
+    lines.extend(describe_args(&op.args, "a{}"));
+    if !lines.is_empty() {
+        let body : Vec<String> = lines.iter().map(|line| if line.is_empty() { String::new() } else { format!("    {}", line.replace('\\', "\\\\").replace("\"\"\"", "\\\"\"\"")) }).collect();
+        ret += &format!("    \"\"\"\n{}\n    \"\"\"\n\n", body.join("\n"));
+    }
+    ret += &format!("    OPCODE : ClassVar[int] = {}\n", op.opcode);
+    for (i, arg) in op.args.iter().enumerate() {
+        ret += &format!("    a{} : {}\n", i, wire_type(&arg.ty).map_err(|_| unsupported(arg))?.0);
+    }
+    ret += "\n";
+    ret += "    def encode(self):\n";
+    ret += &format!("        out = bytearray([{}])\n", op.opcode);
+    for (i, arg) in op.args.iter().enumerate() {
+        let value = format!("self.a{}", i);
+        for (condition, problem) in checks(arg, &value) {
+            ret += &format!("        if not ({}):\n", condition);
+            ret += &format!("            raise ValueError(\"{} argument {} {}\")\n", op.name, i, problem);
+        }
+        if let Some (max_len) = arg.max_len {
+            ret += &format!("        if len({}.encode(\"utf-8\")) > {}:\n", value, max_len);
+            ret += &format!("            raise ValueError(\"{} argument {} must be at most {} bytes\")\n", op.name, i, max_len);
+        }
+        ret += &format!("        out += {}\n", wire_type(&arg.ty).map_err(|_| unsupported(arg))?.1.replace("{}", &value));
+    }
+    ret += "        return bytes(out)\n";
+    Ok(ret)
+}
+
+
+fn protocol(manifest : &Manifest) -> Result<String, CodegenError> {
+    let name = identifier(&manifest.protocol);
+    let mut ret = String::new();
+    for op in &manifest.operations {
+        ret += &operation(&name, op)?;
+        ret += "\n\n";
+    }
+    let classes : Vec<String> = manifest.operations.iter().map(|op| class_name(&name, op)).collect();
+    for line in describe(manifest.doc.as_deref(), &manifest.meta) {
+        ret += format!("# {}", line).trim_end();
+        ret += "\n";
+    }
+    if classes.is_empty() {
+        ret += &format!("{} = None  # no operations\n", name);
+    }
+    else {
+        ret += &format!("{} = Union[{}]\n", name, classes.join(", "));
+    }
+    ret += "\n\n";
+    ret += &format!("def encode_{}(frame):\n", name.to_lowercase());
+    ret += "    return frame.encode()\n";
+    ret += "\n\n";
+    ret += &format!("def decode_{}(data):\n", name.to_lowercase());
+    ret += "    r = _Reader(data)\n";
+    ret += "    opcode = r.unpack(\">B\")\n";
+    for op in &manifest.operations {
+        let mut reads = vec![];
+        for arg in &op.args {
+            reads.push(wire_type(&arg.ty).map_err(|_| unsupported(arg))?.2);
+        }
+        ret += &format!("    if opcode == {}:\n", op.opcode);
+        ret += &format!("        return {}({})\n", class_name(&name, op), reads.join(", "));
+    }
+    ret += &format!("    raise DecodeError(\"unknown {} opcode %d\" % opcode)\n", name);
+    Ok(ret)
+}
+
+
+pub fn generate_protocol(manifest : &Manifest) -> Result<String, CodegenError> {
+    let mut ret = format!("# generated by protocol_v3 from the {} manifest. don't edit this by hand, regenerate it.\n\n", manifest.protocol);
+    ret += RUNTIME;
+    ret += "\n\n";
+    ret += &protocol(manifest)?;
+    Ok(ret)
+}
+
+
+pub fn generate(manifest : &ServerManifest) -> Result<String, CodegenError> { // both directions in one module: a bot encodes incoming_protocol frames and decodes outgoing_protocol ones
+    let mut ret = format!("# generated by protocol_v3 from the {} manifest. don't edit this by hand, regenerate it.\n\n", manifest.application_name);
+    ret += RUNTIME;
+    ret += "\n\n";
+    ret += &protocol(&manifest.incoming_protocol)?;
+    if manifest.outgoing_protocol.protocol != manifest.incoming_protocol.protocol { // same enum both ways only gets declared once
+        ret += "\n\n";
+        ret += &protocol(&manifest.outgoing_protocol)?;
+    }
+    Ok(ret)
+}
@@ -0,0 +1,606 @@
+// TypeScript client generator. produces one self-contained module: a reader and writer for the wire types, a discriminated union per protocol
+// ({ op : "Move", args : [x, y] }, same shape as the JSON form), an encoder and decoder for each, and a Connection class tying it all to a WebSocket.
+// Serde<T> arguments get a type alias per struct and enum in their schema, and are read and written by walking the schema at runtime.
+
+use std::collections::BTreeMap;
+use serde_json::Value;
+use crate::manifest::{Argument, Manifest, Operation, ServerManifest};
+use super::{CodegenError, identifier, checks, describe, describe_args};
+
+
+const RUNTIME : &str = r#"export class Writer {
+    private bytes : number[] = [];
+
+    private push(view : DataView) {
+        for (let i = 0; i < view.byteLength; i++) {
+            this.bytes.push(view.getUint8(i));
+        }
+    }
+
+    u8(value : number) {
+        this.bytes.push(value & 0xff);
+    }
+
+    u16(value : number) {
+        this.bytes.push((value >> 8) & 0xff, value & 0xff);
+    }
+
+    u32(value : number) {
+        const view = new DataView(new ArrayBuffer(4));
+        view.setUint32(0, value);
+        this.push(view);
+    }
+
+    u64(value : bigint) {
+        const view = new DataView(new ArrayBuffer(8));
+        view.setBigUint64(0, value);
+        this.push(view);
+    }
+
+    i8(value : number) {
+        this.bytes.push(value & 0xff);
+    }
+
+    i16(value : number) {
+        this.u16(value & 0xffff);
+    }
+
+    i32(value : number) {
+        const view = new DataView(new ArrayBuffer(4));
+        view.setInt32(0, value);
+        this.push(view);
+    }
+
+    i64(value : bigint) {
+        const view = new DataView(new ArrayBuffer(8));
+        view.setBigInt64(0, value);
+        this.push(view);
+    }
+
+    f32(value : number) {
+        const view = new DataView(new ArrayBuffer(4));
+        view.setFloat32(0, value);
+        this.push(view);
+    }
+
+    f64(value : number) {
+        const view = new DataView(new ArrayBuffer(8));
+        view.setFloat64(0, value);
+        this.push(view);
+    }
+
+    bool(value : boolean) {
+        this.bytes.push(value ? 1 : 0);
+    }
+
+    count(length : number) { // how many items a list or map has
+        if (length > 0xffff) {
+            throw new RangeError("lists and maps are limited to 65535 items");
+        }
+        this.u16(length);
+    }
+
+    string(value : string) { // length prefix is the UTF-8 byte count, not the character count
+        const utf8 = new TextEncoder().encode(value);
+        if (utf8.length > 0xffff) {
+            throw new RangeError("strings are limited to 65535 bytes");
+        }
+        this.u16(utf8.length);
+        utf8.forEach(byte => this.bytes.push(byte));
+    }
+
+    finish() : Uint8Array {
+        return new Uint8Array(this.bytes);
+    }
+}
+
+export class Reader {
+    private offset = 0;
+    private view : DataView;
+
+    constructor(private data : Uint8Array) {
+        this.view = new DataView(data.buffer, data.byteOffset, data.byteLength);
+    }
+
+    private take(length : number) : number {
+        if (this.offset + length > this.data.length) {
+            throw new RangeError("frame is truncated");
+        }
+        const at = this.offset;
+        this.offset += length;
+        return at;
+    }
+
+    u8() : number {
+        return this.view.getUint8(this.take(1));
+    }
+
+    u16() : number {
+        return this.view.getUint16(this.take(2));
+    }
+
+    u32() : number {
+        return this.view.getUint32(this.take(4));
+    }
+
+    u64() : bigint {
+        return this.view.getBigUint64(this.take(8));
+    }
+
+    i8() : number {
+        return this.view.getInt8(this.take(1));
+    }
+
+    i16() : number {
+        return this.view.getInt16(this.take(2));
+    }
+
+    i32() : number {
+        return this.view.getInt32(this.take(4));
+    }
+
+    i64() : bigint {
+        return this.view.getBigInt64(this.take(8));
+    }
+
+    f32() : number {
+        return this.view.getFloat32(this.take(4));
+    }
+
+    f64() : number {
+        return this.view.getFloat64(this.take(8));
+    }
+
+    bool() : boolean {
+        return this.view.getUint8(this.take(1)) == 1;
+    }
+
+    string() : string {
+        const length = this.u16();
+        const at = this.take(length);
+        return new TextDecoder("utf-8", { fatal : true }).decode(this.data.subarray(at, at + length));
+    }
+}
+"#;
+
+
+const SCHEMA_RUNTIME : &str = r#"type Schema = any; // the manifest's description of a Serde<T> argument
+
+function scalar(schema : string) : never {
+    throw new TypeError("unknown type " + schema);
+}
+
+function outerEnum(schema : Schema, enums : Schema[]) : Schema { // {"enum":name} on its own refers back to the enclosing enum of that name
+    return schema.variants ? schema : enums.filter(outer => outer.enum == schema.enum).pop();
+}
+
+export function writeSchema(w : Writer, schema : Schema, value : any, enums : Schema[] = []) : void {
+    if (typeof schema == "string") {
+        switch (schema) {
+            case "u8": return w.u8(value);
+            case "u16": return w.u16(value);
+            case "u32": return w.u32(value);
+            case "u64": return w.u64(value);
+            case "i8": return w.i8(value);
+            case "i16": return w.i16(value);
+            case "i32": return w.i32(value);
+            case "i64": return w.i64(value);
+            case "f32": return w.f32(value);
+            case "f64": return w.f64(value);
+            case "bool": return w.bool(value);
+            case "char": return w.u32(value.codePointAt(0));
+            case "String": return w.string(value);
+        }
+        return scalar(schema);
+    }
+    if ("seq" in schema) {
+        w.count(value.length);
+        value.forEach((item : any) => writeSchema(w, schema.seq, item, enums));
+    }
+    else if ("option" in schema) {
+        w.bool(value !== null);
+        if (value !== null) {
+            writeSchema(w, schema.option, value, enums);
+        }
+    }
+    else if ("map" in schema) { // an array of [key, value] pairs
+        w.count(value.length);
+        value.forEach(([k, v] : [any, any]) => {
+            writeSchema(w, schema.map[0], k, enums);
+            writeSchema(w, schema.map[1], v, enums);
+        });
+    }
+    else if ("struct" in schema) {
+        writeFields(w, schema.fields, value, enums);
+    }
+    else if ("enum" in schema) { // "Dot", or { Circle : [2.5] }
+        const e = outerEnum(schema, enums);
+        const name = typeof value == "string" ? value : Object.keys(value)[0];
+        const index = e.variants.findIndex((variant : Schema) => variant.name == name);
+        if (index < 0) {
+            throw new RangeError("no " + e.enum + " variant is called " + name);
+        }
+        w.u8(index);
+        if (e.variants[index].fields.length > 0) {
+            writeFields(w, e.variants[index].fields, value[name], enums.concat([e]));
+        }
+    }
+    else {
+        throw new TypeError("unknown schema " + JSON.stringify(schema));
+    }
+}
+
+function writeFields(w : Writer, fields : Schema[], value : any, enums : Schema[]) { // named fields come from an object, unnamed ones from an array
+    fields.forEach((field, i) => writeSchema(w, field.type, field.name === undefined ? value[i] : value[field.name], enums));
+}
+
+export function readSchema(r : Reader, schema : Schema, enums : Schema[] = []) : any {
+    if (typeof schema == "string") {
+        switch (schema) {
+            case "u8": return r.u8();
+            case "u16": return r.u16();
+            case "u32": return r.u32();
+            case "u64": return r.u64();
+            case "i8": return r.i8();
+            case "i16": return r.i16();
+            case "i32": return r.i32();
+            case "i64": return r.i64();
+            case "f32": return r.f32();
+            case "f64": return r.f64();
+            case "bool": return r.bool();
+            case "char": return String.fromCodePoint(r.u32());
+            case "String": return r.string();
+        }
+        return scalar(schema);
+    }
+    if ("seq" in schema) {
+        return Array.from({ length : r.u16() }, () => readSchema(r, schema.seq, enums));
+    }
+    if ("option" in schema) {
+        const tag = r.u8();
+        if (tag > 1) {
+            throw new RangeError("bad option tag " + tag);
+        }
+        return tag == 1 ? readSchema(r, schema.option, enums) : null;
+    }
+    if ("map" in schema) {
+        return Array.from({ length : r.u16() }, () => [readSchema(r, schema.map[0], enums), readSchema(r, schema.map[1], enums)]);
+    }
+    if ("struct" in schema) {
+        return readFields(r, schema.fields, enums);
+    }
+    if ("enum" in schema) {
+        const e = outerEnum(schema, enums);
+        const variant = e.variants[r.u8()];
+        if (variant === undefined) {
+            throw new RangeError("unknown " + e.enum + " variant");
+        }
+        if (variant.fields.length == 0) {
+            return variant.name;
+        }
+        return { [variant.name] : readFields(r, variant.fields, enums.concat([e])) };
+    }
+    throw new TypeError("unknown schema " + JSON.stringify(schema));
+}
+
+function readFields(r : Reader, fields : Schema[], enums : Schema[]) : any {
+    const values = fields.map(field => readSchema(r, field.type, enums));
+    if (!fields.some(field => field.name !== undefined)) {
+        return values;
+    }
+    const ret : any = {};
+    fields.forEach((field, i) => ret[field.name] = values[i]);
+    return ret;
+}
+"#;
+
+
+fn wire_type(ty : &str) -> Result<(&'static str, &'static str), CodegenError> { // (TypeScript type, Reader/Writer method)
+    Ok(match ty {
+        "u8" => ("number", "u8"),
+        "u16" => ("number", "u16"),
+        "u32" => ("number", "u32"),
+        "u64" => ("bigint", "u64"),
+        "i32" => ("number", "i32"),
+        "f32" => ("number", "f32"),
+        "bool" => ("boolean", "bool"),
+        "String" => ("string", "string"),
+        _ => return Err(CodegenError::UnsupportedType (ty.to_string()))
+    })
+}
+
+
+#[derive(Default)]
+struct Schemas { // what the Serde<T> arguments of a module need declared alongside them
+    aliases : BTreeMap<String, String>, // struct and enum name -> its TypeScript type
+    used    : bool
+}
+
+
+impl Schemas {
+    fn fields(&mut self, fields : &Value, open : &mut Vec<String>) -> Result<String, CodegenError> { // named fields are an object, unnamed ones a tuple
+        let fields = fields.as_array().ok_or_else(|| CodegenError::UnsupportedType (fields.to_string()))?;
+        let mut named = vec![];
+        let mut unnamed = vec![];
+        for field in fields {
+            let ty = self.ts_type(&field["type"], open)?;
+            match field["name"].as_str() {
+                Some (name) => named.push(format!("{} : {}", Value::String (name.to_string()), ty)),
+                None => unnamed.push(ty)
+            }
+        }
+        Ok(if named.is_empty() { format!("[{}]", unnamed.join(", ")) } else { format!("{{ {} }}", named.join(", ")) })
+    }
+
+    fn alias(&mut self, name : &str, body : String) -> Result<String, CodegenError> {
+        let ident = identifier(name);
+        match self.aliases.get(&ident) {
+            Some (existing) if *existing != body => Err(CodegenError::UnsupportedType (format!("{} (two different schemas have that name)", name))),
+            _ => {
+                self.aliases.insert(ident.clone(), body);
+                Ok(ident)
+            }
+        }
+    }
+
+    fn ts_type(&mut self, schema : &Value, open : &mut Vec<String>) -> Result<String, CodegenError> { // the TypeScript for a schema, in the shape readSchema hands back
+        if let Some (ty) = schema.as_str() {
+            return Ok(match ty {
+                "i8" | "i16" | "f64" => "number", // the scalars only schemas have
+                "i64" => "bigint",
+                "char" => "string",
+                ty => wire_type(ty)?.0
+            }.to_string());
+        }
+        if let Some (item) = schema.get("seq") {
+            return Ok(format!("Array<{}>", self.ts_type(item, open)?));
+        }
+        if let Some (inner) = schema.get("option") {
+            return Ok(format!("({} | null)", self.ts_type(inner, open)?));
+        }
+        if let Some (Value::Array (kv)) = schema.get("map") {
+            if let [key, value] = kv.as_slice() {
+                return Ok(format!("Array<[{}, {}]>", self.ts_type(key, open)?, self.ts_type(value, open)?));
+            }
+        }
+        if let Some (name) = schema["struct"].as_str() {
+            let body = self.fields(&schema["fields"], open)?;
+            return self.alias(name, body);
+        }
+        if let Some (name) = schema["enum"].as_str() {
+            let Some (variants) = schema["variants"].as_array() else {
+                return match open.iter().any(|outer| outer == name) {
+                    true => Ok(identifier(name)), // a reference back to an enum we're inside of
+                    false => Err(CodegenError::UnsupportedType (schema.to_string()))
+                };
+            };
+            open.push(name.to_string());
+            let mut members = vec![];
+            for variant in variants {
+                let variant_name = Value::String (variant["name"].as_str().unwrap_or_default().to_string());
+                match variant["fields"].as_array().is_some_and(Vec::is_empty) {
+                    true => members.push(variant_name.to_string()),
+                    false => members.push(format!("{{ {} : {} }}", variant_name, self.fields(&variant["fields"], open)?))
+                }
+            }
+            open.pop();
+            let body = if members.is_empty() { "never".to_string() } else { members.join(" | ") };
+            return self.alias(name, body);
+        }
+        if let Some (name) = schema["opaque"].as_str() {
+            return Err(CodegenError::Opaque (name.to_string()));
+        }
+        Err(CodegenError::UnsupportedType (schema.to_string()))
+    }
+
+    fn argument(&mut self, arg : &Argument) -> Result<(String, Access), CodegenError> { // the argument's TypeScript type, and how to read and write it
+        if let Ok ((ty, method)) = wire_type(&arg.ty) { // Serde<u8> and the like are the same bytes as plain u8, so they take the short way too
+            return Ok((ty.to_string(), Access::Method (method)));
+        }
+        match &arg.schema {
+            Some (schema) => {
+                self.used = true;
+                Ok((self.ts_type(schema, &mut vec![])?, Access::Schema (schema.to_string())))
+            }
+            None => Err(CodegenError::UnsupportedType (arg.ty.clone()))
+        }
+    }
+
+    fn declarations(&self) -> String {
+        if !self.used {
+            return String::new();
+        }
+        let mut ret = String::from(SCHEMA_RUNTIME);
+        for (name, body) in &self.aliases {
+            ret += &format!("\nexport type {} = {};\n", name, body);
+        }
+        ret + "\n"
+    }
+}
+
+
+enum Access {
+    Method (&'static str), // w.u8(...), r.u8()
+    Schema (String) // writeSchema(w, <schema>, ...), readSchema(r, <schema>)
+}
+
+
+impl Access {
+    fn write(&self, value : &str) -> String {
+        match self {
+            Access::Method (method) => format!("w.{}({})", method, value),
+            Access::Schema (schema) => format!("writeSchema(w, {}, {})", schema, value)
+        }
+    }
+
+    fn read(&self) -> String {
+        match self {
+            Access::Method (method) => format!("r.{}()", method),
+            Access::Schema (schema) => format!("readSchema(r, {})", schema)
+        }
+    }
+}
+
+
+fn jsdoc(lines : &[String], indent : &str) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let mut ret = format!("{}/**\n", indent);
+    for line in lines {
+        ret += format!("{} * {}", indent, line.replace("*/", "*\\/")).trim_end();
+        ret += "\n";
+    }
+    ret + indent + " */\n"
+}
+
+
+fn union_member(op : &Operation, schemas : &mut Schemas) -> Result<String, CodegenError> {
+    let mut args = vec![];
+    for arg in &op.args {
+        args.push(schemas.argument(arg)?.0);
+    }
+    Ok(format!("{{ op : \"{}\", args : [{}] }}", op.name, args.join(", ")))
+}
+
+
+fn encoder(name : &str, manifest : &Manifest, schemas : &mut Schemas) -> Result<String, CodegenError> {
+    let mut ret = format!("export function encode{}(frame : {}) : Uint8Array {{\n", name, name);
+    ret += "    const w = new Writer();\n";
+    ret += "    switch (frame.op) {\n";
+    for op in &manifest.operations {
+        ret += &format!("        case \"{}\": {{\n", op.name);
+        ret += &format!("            w.u8({});\n", op.opcode);
+        for (i, arg) in op.args.iter().enumerate() {
+            let value = format!("frame.args[{}]", i);
+            for (condition, problem) in checks(arg, &value) {
+                ret += &format!("            if (!({})) throw new RangeError(\"{} argument {} {}\");\n", condition, op.name, i, problem);
+            }
+            if let Some (max_len) = arg.max_len {
+                ret += &format!("            if (new TextEncoder().encode({}).length > {}) throw new RangeError(\"{} argument {} must be at most {} bytes\");\n", value, max_len, op.name, i, max_len);
+            }
+            ret += &format!("            {};\n", schemas.argument(arg)?.1.write(&value));
+        }
+        ret += "            break;\n";
+        ret += "        }\n";
+    }
+    ret += "    }\n";
+    ret += "    return w.finish();\n";
+    ret += "}\n";
+    Ok(ret)
+}
+
+
+fn decoder(name : &str, manifest : &Manifest, schemas : &mut Schemas) -> Result<String, CodegenError> {
+    let mut ret = format!("export function decode{}(data : Uint8Array) : {} {{\n", name, name);
+    ret += "    const r = new Reader(data);\n";
+    ret += "    const opcode = r.u8();\n";
+    ret += "    switch (opcode) {\n";
+    for op in &manifest.operations {
+        let mut reads = vec![];
+        for arg in &op.args {
+            reads.push(schemas.argument(arg)?.1.read());
+        }
+        ret += &format!("        case {}: return {{ op : \"{}\", args : [{}] }};\n", op.opcode, op.name, reads.join(", "));
+    }
+    ret += &format!("        default: throw new RangeError(\"unknown {} opcode \" + opcode);\n", name);
+    ret += "    }\n";
+    ret += "}\n";
+    Ok(ret)
+}
+
+
+fn protocol(manifest : &Manifest, schemas : &mut Schemas) -> Result<String, CodegenError> { // the union type, encoder and decoder for one protocol
+    let name = identifier(&manifest.protocol);
+    let mut members = vec![];
+    for op in &manifest.operations {
+        members.push(union_member(op, schemas)?);
+    }
+    let mut ret = jsdoc(&describe(manifest.doc.as_deref(), &manifest.meta), "");
+    ret += &format!("export type {} =\n", name);
+    if members.is_empty() {
+        ret += "    never;\n";
+    }
+    else {
+        for (i, (member, op)) in members.iter().zip(&manifest.operations).enumerate() {
+            let mut lines = describe(op.doc.as_deref(), &op.meta);
+            lines.extend(describe_args(&op.args, "args[{}]"));
+            ret += &jsdoc(&lines, "    ");
+            ret += &format!("    | {}{}\n", member, if i == members.len() - 1 { ";" } else { "" });
+        }
+    }
+    ret += "\n";
+    ret += &encoder(&name, manifest, schemas)?;
+    ret += "\n";
+    ret += &decoder(&name, manifest, schemas)?;
+    Ok(ret)
+}
+
+
+fn connection(manifest : &ServerManifest) -> String {
+    let outgoing = identifier(&manifest.incoming_protocol.protocol); // what we send is what the server takes in
+    let incoming = identifier(&manifest.outgoing_protocol.protocol);
+    format!(r#"export class Connection {{
+    readonly socket : WebSocket;
+
+    constructor(url : string) {{
+        this.socket = new WebSocket(url);
+        this.socket.binaryType = "arraybuffer";
+    }}
+
+    send(frame : {outgoing}) {{
+        this.socket.send(encode{outgoing}(frame));
+    }}
+
+    onMessage(listener : (frame : {incoming}) => void) {{
+        this.socket.addEventListener("message", event => {{
+            if (event.data instanceof ArrayBuffer) {{
+                listener(decode{incoming}(new Uint8Array(event.data)));
+            }}
+        }});
+    }}
+
+    onOpen(listener : () => void) {{
+        this.socket.addEventListener("open", () => listener());
+    }}
+
+    onClose(listener : (code : number, reason : string) => void) {{
+        this.socket.addEventListener("close", event => listener(event.code, event.reason));
+    }}
+
+    close() {{
+        this.socket.close();
+    }}
+}}
+"#)
+}
+
+
+pub fn generate_protocol(manifest : &Manifest) -> Result<String, CodegenError> { // a module for just one protocol: no Connection, since that needs both directions
+    let mut ret = format!("// generated by protocol_v3 from the {} manifest. don't edit this by hand, regenerate it.\n\n", manifest.protocol);
+    ret += RUNTIME;
+    ret += "\n";
+    let mut schemas = Schemas::default();
+    let body = protocol(manifest, &mut schemas)?;
+    ret += &schemas.declarations();
+    ret += &body;
+    Ok(ret)
+}
+
+
+pub fn generate(manifest : &ServerManifest) -> Result<String, CodegenError> {
+    let mut ret = format!("// generated by protocol_v3 from the {} manifest. don't edit this by hand, regenerate it.\n\n", identifier(&manifest.application_name)); // the name is whatever the server was given, newlines and all
+    ret += RUNTIME;
+    ret += "\n";
+    let mut schemas = Schemas::default();
+    let mut body = protocol(&manifest.incoming_protocol, &mut schemas)?;
+    body += "\n";
+    if manifest.outgoing_protocol.protocol != manifest.incoming_protocol.protocol { // same enum both ways (WebSocketServer<Game, Game>) only gets declared once
+        body += &protocol(&manifest.outgoing_protocol, &mut schemas)?;
+        body += "\n";
+    }
+    ret += &schemas.declarations();
+    ret += &body;
+    ret += &connection(manifest);
+    Ok(ret)
+}
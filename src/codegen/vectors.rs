@@ -0,0 +1,285 @@
+// golden test vectors: sample values next to their exact encoded bytes, so any implementation of the wire format (protocol.js, the generated
+// clients, somebody's C# port) can check itself against the Rust one. the file is JSON:
+//
+//     {
+//         "format" : 1,
+//         "segments" : [ { "type" : "u16", "value" : 258, "bytes" : "0102" }, ... ],
+//         "frames" : [ { "protocol" : "Client", "operation" : "Move", "opcode" : 1, "args" : [1.5, -2.0], "bytes" : "013fc00000c0000000" }, ... ]
+//     }
+//
+// bytes are lowercase hex. u64 values are written as decimal strings, because plenty of JSON parsers can't hold them in a number.
+// "segments" always covers every built-in ProtocolSegment impl; "frames" covers every operation of the manifest it was generated from.
+
+use serde_json::Value;
+use crate::manifest::{Argument, Manifest, Fields, ParseError};
+use crate::protocol::{ProtocolFrame, ProtocolSegment};
+use crate::dynamic::DynamicValue;
+use super::{CodegenError, unsupported};
+use std::collections::VecDeque;
+
+
+pub const FORMAT : u32 = 1;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentVector {
+    pub ty    : String, // "type" in the JSON
+    pub value : Value,
+    pub bytes : String
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameVector {
+    pub protocol  : String,
+    pub operation : String,
+    pub opcode    : u8,
+    pub args      : Vec<Value>,
+    pub bytes     : String
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorFile {
+    pub format   : u32,
+    pub segments : Vec<SegmentVector>,
+    pub frames   : Vec<FrameVector>
+}
+
+
+fn pretty(value : &Value) -> String {
+    serde_json::to_string_pretty(value).expect("JSON values are always serializable")
+}
+
+
+fn object(fields : &[(&str, String)]) -> String { // serde_json's pretty layout, with the fields in the order given instead of sorted, so the file reads top down
+    let fields : Vec<String> = fields.iter().map(|(key, value)| format!("  \"{}\": {}", key, value.replace('\n', "\n  "))).collect();
+    format!("{{\n{}\n}}", fields.join(",\n"))
+}
+
+
+fn array(items : &[String]) -> String {
+    if items.is_empty() {
+        return "[]".to_string();
+    }
+    let items : Vec<String> = items.iter().map(|item| format!("  {}", item.replace('\n', "\n  "))).collect();
+    format!("[\n{}\n]", items.join(",\n"))
+}
+
+
+impl SegmentVector {
+    fn from_json(json : &Value) -> Result<Self, ParseError> {
+        let fields = Fields::of(json, "a segment vector")?;
+        Ok(Self { ty : fields.string("type")?, value : fields.required("value")?.clone(), bytes : fields.string("bytes")? })
+    }
+
+    fn to_json(&self) -> String {
+        object(&[("type", pretty(&Value::String (self.ty.clone()))), ("value", pretty(&self.value)), ("bytes", pretty(&Value::String (self.bytes.clone())))])
+    }
+}
+
+
+impl FrameVector {
+    fn from_json(json : &Value) -> Result<Self, ParseError> {
+        let fields = Fields::of(json, "a frame vector")?;
+        Ok(Self {
+            protocol  : fields.string("protocol")?,
+            operation : fields.string("operation")?,
+            opcode    : fields.integer("opcode")?,
+            args      : fields.array("args")?.to_vec(),
+            bytes     : fields.string("bytes")?
+        })
+    }
+
+    fn to_json(&self) -> String {
+        object(&[
+            ("protocol", pretty(&Value::String (self.protocol.clone()))),
+            ("operation", pretty(&Value::String (self.operation.clone()))),
+            ("opcode", self.opcode.to_string()),
+            ("args", pretty(&Value::Array (self.args.clone()))),
+            ("bytes", pretty(&Value::String (self.bytes.clone())))
+        ])
+    }
+}
+
+
+impl VectorFile {
+    pub fn parse(json : &str) -> Result<Self, ParseError> {
+        let json : Value = serde_json::from_str(json)?;
+        let fields = Fields::of(&json, "a vector file")?;
+        Ok(Self {
+            format   : fields.integer("format")?,
+            segments : fields.array("segments")?.iter().map(SegmentVector::from_json).collect::<Result<_, _>>()?,
+            frames   : fields.array("frames")?.iter().map(FrameVector::from_json).collect::<Result<_, _>>()?
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let segments : Vec<String> = self.segments.iter().map(SegmentVector::to_json).collect();
+        let frames : Vec<String> = self.frames.iter().map(FrameVector::to_json).collect();
+        object(&[("format", self.format.to_string()), ("segments", array(&segments)), ("frames", array(&frames))]) + "\n"
+    }
+}
+
+
+pub const TYPES : [&str; 8] = ["u8", "u16", "u32", "u64", "i32", "f32", "bool", "String"]; // every built-in ProtocolSegment impl
+
+
+fn sample<T : ProtocolSegment + Into<Value> + Clone>(values : &[T]) -> Vec<(Value, Vec<u8>)> {
+    values.iter().map(|v| (v.clone().into(), v.clone().encode())).collect()
+}
+
+
+fn samples(ty : &str) -> Option<Vec<(Value, Vec<u8>)>> { // edge cases first, so a frame with one of each gets the interesting ones
+    Some(match ty {
+        "u8" => sample(&[0u8, 1, 127, 128, 255]),
+        "u16" => sample(&[0u16, 1, 258, 32768, 65535]),
+        "u32" => sample(&[0u32, 1, 16909060, 2147483648, 4294967295]),
+        "u64" => sample(&[0u64, 1, 72623859790382856, 9007199254740993, u64::MAX]).into_iter().map(|(v, bytes)| (Value::String(v.to_string()), bytes)).collect(),
+        "i32" => sample(&[0i32, 1, -1, i32::MAX, i32::MIN]),
+        "f32" => sample(&[0.0f32, -0.0, 1.5, -2.25, 0.1, f32::MAX, f32::MIN_POSITIVE, 1e-45]),
+        "bool" => sample(&[false, true]),
+        "String" => sample(&[String::new(), "hello".to_string(), "hé".to_string(), "日本語".to_string(), "🎮 gg".to_string()]),
+        _ => return None
+    })
+}
+
+
+fn allowed(arg : &Argument, value : &Value) -> bool { // keep the frame samples inside the manifest's range and max_len, or the server would reject them
+    if let Some (range) = &arg.range {
+        let number = match value {
+            Value::String (s) => s.parse::<f64>().ok(),
+            v => v.as_f64()
+        };
+        if let Some (number) = number {
+            if !range.contains(number) {
+                return false;
+            }
+        }
+    }
+    if let (Some (max_len), Value::String (s)) = (arg.max_len, value) {
+        if arg.ty == "String" && s.len() as u64 > max_len {
+            return false;
+        }
+    }
+    true
+}
+
+
+pub fn segments() -> Vec<SegmentVector> {
+    let mut ret = vec![];
+    for ty in TYPES {
+        for (value, bytes) in samples(ty).expect("every built-in type has samples") {
+            ret.push(SegmentVector { ty : ty.to_string(), value, bytes : hex::encode(bytes) });
+        }
+    }
+    ret
+}
+
+
+pub fn frames(manifest : &Manifest) -> Result<Vec<FrameVector>, CodegenError> {
+    let mut ret = vec![];
+    for op in &manifest.operations {
+        let mut columns = vec![];
+        for arg in &op.args {
+            let column : Vec<(Value, Vec<u8>)> = samples(&arg.ty).ok_or_else(|| unsupported(arg))?.into_iter().filter(|(value, _)| allowed(arg, value)).collect();
+            if column.is_empty() {
+                return Err(CodegenError::Unsatisfiable (format!("{} argument of type {} in {}", op.name, arg.ty, manifest.protocol)));
+            }
+            columns.push(column);
+        }
+        let count = columns.iter().map(|c| c.len()).max().unwrap_or(1);
+        for i in 0..count { // walk every column in step, wrapping the shorter ones, so every sample shows up in at least one frame
+            let mut args = vec![];
+            let mut bytes = vec![op.opcode];
+            for column in &columns {
+                let (value, encoded) = &column[i % column.len()];
+                args.push(value.clone());
+                bytes.extend_from_slice(encoded);
+            }
+            ret.push(FrameVector { protocol : manifest.protocol.clone(), operation : op.name.clone(), opcode : op.opcode, args, bytes : hex::encode(bytes) });
+        }
+    }
+    Ok(ret)
+}
+
+
+pub fn generate(manifests : &[&Manifest]) -> Result<VectorFile, CodegenError> {
+    let mut file = VectorFile { format : FORMAT, segments : segments(), frames : vec![] };
+    for manifest in manifests {
+        file.frames.append(&mut frames(manifest)?);
+    }
+    Ok(file)
+}
+
+
+fn check_segment(vector : &SegmentVector, bytes : Vec<u8>) -> Result<(), String> { // through the dynamic codec, which sits on the very impls being checked
+    let mut data : VecDeque<u8> = bytes.clone().into();
+    let decoded = DynamicValue::decode(&vector.ty, &mut data).map_err(|e| format!("{} {}: decode failed: {}", vector.ty, vector.bytes, e))?;
+    if !data.is_empty() {
+        return Err(format!("{} {}: decode left {} bytes behind", vector.ty, vector.bytes, data.len()));
+    }
+    let from_value = DynamicValue::from_json(&vector.ty, &vector.value).map_err(|e| format!("{} {}: bad value: {}", vector.ty, vector.value, e))?; // u64s are strings in the file, which from_json takes
+    if decoded != from_value { // compared as the type itself, so a float that's a hair off in the last f64 digit still counts as the same f32
+        return Err(format!("{} {}: decoded to {}, expected {}", vector.ty, vector.bytes, decoded.to_json(), vector.value));
+    }
+    let encoded = from_value.encode();
+    if encoded != bytes {
+        return Err(format!("{} {}: encoded to {}", vector.ty, vector.value, hex::encode(encoded)));
+    }
+    Ok(())
+}
+
+
+pub fn verify_segments(file : &VectorFile) -> Vec<String> { // checks the built-in impls against the file; returns one message per mismatch
+    let mut ret = vec![];
+    for vector in &file.segments {
+        let bytes = match hex::decode(&vector.bytes) {
+            Ok (bytes) => bytes,
+            Err (e) => {
+                ret.push(format!("{} {}: bad hex: {}", vector.ty, vector.bytes, e));
+                continue;
+            }
+        };
+        let result = match TYPES.contains(&vector.ty.as_str()) {
+            true => check_segment(vector, bytes),
+            false => Err(format!("{}: not a built-in type", vector.ty))
+        };
+        if let Err(e) = result {
+            ret.push(e);
+        }
+    }
+    ret
+}
+
+
+pub fn verify_frames<Protocol : ProtocolFrame>(file : &VectorFile) -> Vec<String> { // checks a derived impl against the file's frames for its protocol
+    let manifest = Manifest::of::<Protocol>();
+    let mut ret = vec![];
+    for vector in file.frames.iter().filter(|f| f.protocol == manifest.protocol) {
+        let bytes = match hex::decode(&vector.bytes) {
+            Ok (bytes) => bytes,
+            Err (e) => {
+                ret.push(format!("{} {}: bad hex: {}", vector.operation, vector.bytes, e));
+                continue;
+            }
+        };
+        match manifest.operation(vector.opcode) {
+            Some (op) if op.name == vector.operation => {}
+            _ => {
+                ret.push(format!("{}: opcode {} doesn't match the manifest", vector.operation, vector.opcode));
+                continue;
+            }
+        }
+        match Protocol::decode(bytes.clone().into()) {
+            Ok (frame) => {
+                let encoded = frame.encode();
+                if encoded != bytes {
+                    ret.push(format!("{} {}: re-encoded to {}", vector.operation, vector.bytes, hex::encode(encoded)));
+                }
+            }
+            Err (e) => ret.push(format!("{} {}: decode failed: {}", vector.operation, vector.bytes, e))
+        }
+    }
+    ret
+}
@@ -0,0 +1,196 @@
+// Wireshark dissector generator. produces a Lua plugin that picks protocol_v3 frames out of the WebSocket messages Wireshark has already
+// unmasked and reassembled, and shows each one as its operation, with every argument as its own field (filterable, e.g. game.in.move.a0 > 3)
+// and expert info for malformed frames and values that break the manifest's validation rules.
+// Wireshark only spots the WebSocket upgrade on ports it already treats as HTTP, so servers on other ports need Decode As... HTTP once.
+
+use crate::manifest::{Argument, Manifest, Operation, ServerManifest};
+use super::{CodegenError, identifier, checks, unsupported};
+
+
+const RUNTIME : &str = r#"local SIZES = { u8 = 1, u16 = 2, u32 = 4, u64 = 8, i32 = 4, f32 = 4, bool = 1 }
+
+local function read(range, ty)
+    if ty == "u64" then
+        return range:uint64():tonumber()
+    elseif ty == "i32" then
+        return range:int()
+    elseif ty == "f32" then
+        return range:float()
+    else
+        return range:uint()
+    end
+end
+
+local function dissect_frame(tvb, pinfo, tree, protocol, operations)
+    local subtree = tree:add(proto, tvb(), protocol)
+    if tvb:len() < 1 then
+        subtree:add_proto_expert_info(e_malformed, "empty frame")
+        return
+    end
+    local opcode = tvb(0, 1):uint()
+    subtree:add(f_opcode, tvb(0, 1))
+    local op = operations[opcode]
+    if op == nil then
+        subtree:add_proto_expert_info(e_malformed, "unknown opcode " .. opcode)
+        pinfo.cols.info:set(protocol .. " unknown opcode " .. opcode)
+        return
+    end
+    subtree:append_text(": " .. op.name)
+    pinfo.cols.info:set(protocol .. "." .. op.name)
+    local offset = 1
+    for _, arg in ipairs(op.args) do
+        local size = SIZES[arg.type]
+        local start = offset
+        if arg.type == "String" then
+            if offset + 2 > tvb:len() then
+                subtree:add_proto_expert_info(e_malformed, "frame is truncated")
+                return
+            end
+            size = tvb(offset, 2):uint()
+            subtree:add(f_length, tvb(offset, 2))
+            start = offset + 2
+        end
+        if start + size > tvb:len() then
+            subtree:add_proto_expert_info(e_malformed, "frame is truncated")
+            return
+        end
+        local item = subtree:add(arg.field, tvb(start, size))
+        if arg.check then
+            local value = size
+            if arg.type ~= "String" then
+                value = read(tvb(start, size), arg.type)
+            end
+            local problem = arg.check(value)
+            if problem then
+                item:add_proto_expert_info(e_invalid, problem)
+            end
+        end
+        offset = start + size
+    end
+    if offset < tvb:len() then
+        subtree:add_proto_expert_info(e_malformed, (tvb:len() - offset) .. " trailing bytes")
+    end
+end
+"#;
+
+
+fn field_constructor(ty : &str) -> Result<&'static str, CodegenError> {
+    Ok(match ty {
+        "u8" => "ProtoField.uint8({}, {}, base.DEC)",
+        "u16" => "ProtoField.uint16({}, {}, base.DEC)",
+        "u32" => "ProtoField.uint32({}, {}, base.DEC)",
+        "u64" => "ProtoField.uint64({}, {}, base.DEC)",
+        "i32" => "ProtoField.int32({}, {}, base.DEC)",
+        "f32" => "ProtoField.float({}, {})",
+        "bool" => "ProtoField.bool({}, {})",
+        "String" => "ProtoField.string({}, {})",
+        _ => return Err(CodegenError::UnsupportedType (ty.to_string()))
+    })
+}
+
+
+fn check(arg : &Argument) -> Option<String> { // a Lua function returning what's wrong with a value (a string's byte length, for strings), or nil
+    let mut conditions = checks(arg, "value");
+    if let (Some (max_len), "String") = (arg.max_len, arg.ty.as_str()) {
+        conditions.push((format!("value <= {}", max_len), format!("must be at most {} bytes", max_len)));
+    }
+    if conditions.is_empty() {
+        return None;
+    }
+    let mut ret = String::from("function(value)");
+    for (condition, problem) in conditions {
+        ret += &format!(" if not ({}) then return \"{}\" end", condition, problem);
+    }
+    Some(ret + " end")
+}
+
+
+fn operation(abbrev : &str, op : &Operation) -> Result<String, CodegenError> {
+    let mut ret = format!("    [{}] = {{ name = \"{}\", args = {{\n", op.opcode, op.name);
+    for (i, arg) in op.args.iter().enumerate() {
+        let field_abbrev = format!("\"{}.{}.a{}\"", abbrev, op.name.to_lowercase(), i);
+        let field_name = format!("\"a{} ({})\"", i, arg.ty);
+        let field = field_constructor(&arg.ty).map_err(|_| unsupported(arg))?.replacen("{}", &field_abbrev, 1).replacen("{}", &field_name, 1);
+        ret += &format!("        {{ type = \"{}\", field = {}", arg.ty, field);
+        if let Some (check) = check(arg) {
+            ret += &format!(", check = {}", check);
+        }
+        ret += " },\n";
+    }
+    Ok(ret + "    } },\n")
+}
+
+
+fn operations(table : &str, abbrev : &str, manifest : &Manifest) -> Result<String, CodegenError> { // a Lua table of the protocol's operations by opcode, fields included
+    let mut ret = format!("local {} = {{ -- {}\n", table, manifest.protocol);
+    for op in &manifest.operations {
+        ret += &operation(abbrev, op)?;
+    }
+    Ok(ret + "}\n")
+}
+
+
+fn header(name : &str, abbrev : &str) -> String {
+    let mut ret = format!("-- generated by protocol_v3 from the {} manifest. don't edit this by hand, regenerate it.\n", name);
+    ret += "-- load it with wireshark -X lua_script:this_file.lua, or put it in your personal Lua plugins folder.\n\n";
+    ret += &format!("local proto = Proto(\"{}\", \"{} (protocol_v3)\")\n", abbrev, name);
+    ret += "proto.prefs.port = Pref.uint(\"Server port\", 8080, \"TCP port the protocol_v3 server listens on; frames sent to it are incoming\")\n\n";
+    ret += &format!("local f_opcode = ProtoField.uint8(\"{}.opcode\", \"Opcode\", base.DEC)\n", abbrev);
+    ret += &format!("local f_length = ProtoField.uint16(\"{}.length\", \"String length\", base.DEC)\n", abbrev);
+    ret += &format!("local e_malformed = ProtoExpert.new(\"{}.malformed\", \"Malformed frame\", expert.group.MALFORMED, expert.severity.ERROR)\n", abbrev);
+    ret += &format!("local e_invalid = ProtoExpert.new(\"{}.invalid\", \"Value fails validation\", expert.group.PROTOCOL, expert.severity.WARN)\n\n", abbrev);
+    ret + RUNTIME + "\n"
+}
+
+
+fn footer(name : &str, tables : &[&str], dissect : &str) -> String {
+    let mut ret = String::from("local fields = { f_opcode, f_length }\n");
+    ret += &format!("for _, operations in ipairs({{ {} }}) do\n", tables.join(", "));
+    ret += "    for _, op in pairs(operations) do\n";
+    ret += "        for _, arg in ipairs(op.args) do\n";
+    ret += "            table.insert(fields, arg.field)\n";
+    ret += "        end\n";
+    ret += "    end\n";
+    ret += "end\n";
+    ret += "proto.fields = fields\n";
+    ret += "proto.experts = { e_malformed, e_invalid }\n\n";
+    ret += "function proto.dissector(tvb, pinfo, tree)\n";
+    ret += &format!("    pinfo.cols.protocol:set(\"{}\")\n", name);
+    ret += dissect;
+    ret += "    return tvb:len()\n";
+    ret += "end\n\n";
+    ret += "local ws_port = DissectorTable.get(\"ws.port\")\n";
+    ret += "local registered_port = proto.prefs.port\n";
+    ret += "ws_port:add(registered_port, proto)\n\n";
+    ret += "function proto.prefs_changed()\n";
+    ret += "    ws_port:remove(registered_port, proto)\n";
+    ret += "    registered_port = proto.prefs.port\n";
+    ret += "    ws_port:add(registered_port, proto)\n";
+    ret += "end\n";
+    ret
+}
+
+
+pub fn generate_protocol(manifest : &Manifest) -> Result<String, CodegenError> { // one protocol, used for both directions
+    let abbrev = identifier(&manifest.protocol).to_lowercase();
+    let mut ret = header(&manifest.protocol, &abbrev);
+    ret += "\n";
+    ret += &operations("operations", &abbrev, manifest)?;
+    ret += "\n";
+    ret += &footer(&manifest.protocol, &["operations"], &format!("    dissect_frame(tvb, pinfo, tree, \"{}\", operations)\n", manifest.protocol));
+    Ok(ret)
+}
+
+
+pub fn generate(manifest : &ServerManifest) -> Result<String, CodegenError> { // tells the directions apart by the server port preference
+    let abbrev = identifier(&manifest.application_name).to_lowercase();
+    let mut ret = header(&manifest.application_name, &abbrev);
+    ret += "\n";
+    ret += &operations("incoming", &format!("{}.in", abbrev), &manifest.incoming_protocol)?;
+    ret += "\n";
+    ret += &operations("outgoing", &format!("{}.out", abbrev), &manifest.outgoing_protocol)?;
+    ret += "\n";
+    let dissect = format!("    if pinfo.dst_port == proto.prefs.port then\n        dissect_frame(tvb, pinfo, tree, \"{}\", incoming)\n    else\n        dissect_frame(tvb, pinfo, tree, \"{}\", outgoing)\n    end\n", manifest.incoming_protocol.protocol, manifest.outgoing_protocol.protocol);
+    ret += &footer(&manifest.application_name, &["incoming", "outgoing"], &dissect);
+    Ok(ret)
+}
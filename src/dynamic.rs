@@ -0,0 +1,485 @@
+// the runtime codec: encodes and decodes frames using nothing but a parsed manifest, for tools (proxies, debuggers, the CLI) that can't depend
+// on the game's compile-time enums. it uses the same ProtocolSegment impls underneath, so the bytes are exactly what the derived impls produce,
+// and it enforces the manifest's range and max_len rules the same way derived decoders do (custom validate functions can't be, of course).
+// arguments of other types (Serde<T>) are read and written by following the schema the manifest has for them; see serde_bridge::Schema.
+
+use crate::manifest::{Argument, Manifest, Operation};
+use crate::protocol::{DecodeError, ProtocolSegment, ValidationFailure};
+use std::collections::VecDeque;
+use serde_json::Value;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicValue {
+    U8 (u8),
+    U16 (u16),
+    U32 (u32),
+    U64 (u64),
+    I32 (i32),
+    F32 (f32),
+    Bool (bool),
+    String (String),
+    Described { ty : String, value : Value, bytes : Vec<u8> } // a type the manifest has a schema for: its JSON form and its bytes, kept together so encode can't fail
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicFrame {
+    pub operation : String,
+    pub opcode    : u8,
+    pub args      : Vec<DynamicValue>
+}
+
+
+#[derive(Debug)]
+pub enum DynamicError {
+    Decode (DecodeError), // the bytes don't make sense
+    UnknownOpcode (u8),
+    UnknownOperation (String),
+    UnsupportedType (String), // the manifest names a type the dynamic codec doesn't know
+    WrongArguments { operation : String, expected : Vec<String>, got : Vec<String> }, // arity or types don't match the manifest
+    Invalid { operation : String, field : usize, failure : ValidationFailure },
+    Json (String) // the JSON isn't shaped like a frame
+}
+
+
+impl std::error::Error for DynamicError {
+    fn description(&self) -> &str {
+        "A frame doesn't fit the manifest!"
+    }
+}
+
+
+impl std::fmt::Display for DynamicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DynamicError::Decode (e) => write!(f, "{}", e),
+            DynamicError::UnknownOpcode (opcode) => write!(f, "No operation has opcode {}", opcode),
+            DynamicError::UnknownOperation (name) => write!(f, "No operation is called {}", name),
+            DynamicError::UnsupportedType (ty) => write!(f, "Unsupported type in manifest: {}", ty),
+            DynamicError::WrongArguments { operation, expected, got } => write!(f, "{} takes ({}), got ({})", operation, expected.join(", "), got.join(", ")),
+            DynamicError::Invalid { operation, field, failure } => write!(f, "field {} of {} failed validation: {:?}", field, operation, failure),
+            DynamicError::Json (e) => write!(f, "Bad JSON frame: {}", e)
+        }
+    }
+}
+
+
+impl From<DecodeError> for DynamicError {
+    fn from(e : DecodeError) -> Self {
+        DynamicError::Decode (e)
+    }
+}
+
+
+impl DynamicValue {
+    pub fn type_name(&self) -> &str { // the manifest name of this value's type
+        match self {
+            DynamicValue::U8 (_) => "u8",
+            DynamicValue::U16 (_) => "u16",
+            DynamicValue::U32 (_) => "u32",
+            DynamicValue::U64 (_) => "u64",
+            DynamicValue::I32 (_) => "i32",
+            DynamicValue::F32 (_) => "f32",
+            DynamicValue::Bool (_) => "bool",
+            DynamicValue::String (_) => "String",
+            DynamicValue::Described { ty, .. } => ty
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        match self.clone() {
+            DynamicValue::U8 (v) => v.encode(),
+            DynamicValue::U16 (v) => v.encode(),
+            DynamicValue::U32 (v) => v.encode(),
+            DynamicValue::U64 (v) => v.encode(),
+            DynamicValue::I32 (v) => v.encode(),
+            DynamicValue::F32 (v) => v.encode(),
+            DynamicValue::Bool (v) => v.encode(),
+            DynamicValue::String (v) => v.encode(),
+            DynamicValue::Described { bytes, .. } => bytes
+        }
+    }
+
+    pub fn decode(ty : &str, data : &mut VecDeque<u8>) -> Result<Self, DynamicError> {
+        Ok(match ty {
+            "u8" => DynamicValue::U8 (u8::decode(data)?),
+            "u16" => DynamicValue::U16 (u16::decode(data)?),
+            "u32" => DynamicValue::U32 (u32::decode(data)?),
+            "u64" => DynamicValue::U64 (u64::decode(data)?),
+            "i32" => DynamicValue::I32 (i32::decode(data)?),
+            "f32" => DynamicValue::F32 (f32::decode(data)?),
+            "bool" => DynamicValue::Bool (bool::decode(data)?),
+            "String" => DynamicValue::String (String::decode(data)?),
+            _ => return Err(DynamicError::UnsupportedType (ty.to_string()))
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            DynamicValue::U8 (v) => Value::from(*v),
+            DynamicValue::U16 (v) => Value::from(*v),
+            DynamicValue::U32 (v) => Value::from(*v),
+            DynamicValue::U64 (v) => Value::String(v.to_string()),
+            DynamicValue::I32 (v) => Value::from(*v),
+            DynamicValue::F32 (v) => Value::from(v.to_string().parse::<f64>().unwrap_or(f64::NAN)), // the shortest form that reads back as the same f32, so 0.1 stays 0.1. NaN and infinities become null
+            DynamicValue::Bool (v) => Value::Bool(*v),
+            DynamicValue::String (v) => Value::String(v.clone()),
+            DynamicValue::Described { value, .. } => value.clone()
+        }
+    }
+
+    pub fn from_json(ty : &str, value : &Value) -> Result<Self, DynamicError> {
+        let bad = || DynamicError::Json (format!("{} isn't a {}", value, ty));
+        let int = || value.as_i64().or_else(|| value.as_str().and_then(|s| s.parse().ok())).ok_or_else(bad);
+        Ok(match ty {
+            "u8" => DynamicValue::U8 (int()?.try_into().map_err(|_| bad())?),
+            "u16" => DynamicValue::U16 (int()?.try_into().map_err(|_| bad())?),
+            "u32" => DynamicValue::U32 (int()?.try_into().map_err(|_| bad())?),
+            "u64" => DynamicValue::U64 (value.as_u64().or_else(|| value.as_str().and_then(|s| s.parse().ok())).ok_or_else(bad)?),
+            "i32" => DynamicValue::I32 (int()?.try_into().map_err(|_| bad())?),
+            "f32" => DynamicValue::F32 (value.as_f64().ok_or_else(bad)? as f32),
+            "bool" => DynamicValue::Bool (value.as_bool().ok_or_else(bad)?),
+            "String" => DynamicValue::String (value.as_str().ok_or_else(bad)?.to_string()),
+            _ => return Err(DynamicError::UnsupportedType (ty.to_string()))
+        })
+    }
+
+    pub fn decode_argument(arg : &Argument, data : &mut VecDeque<u8>) -> Result<Self, DynamicError> { // decode, plus the types only the argument's schema can explain
+        if BUILT_IN.contains(&arg.ty.as_str()) {
+            return Self::decode(&arg.ty, data);
+        }
+        let schema = described(arg)?;
+        let before : Vec<u8> = data.iter().copied().collect();
+        let value = read_schema(schema, data, &mut vec![])?;
+        let bytes = before[..before.len() - data.len()].to_vec();
+        Ok(DynamicValue::Described { ty : arg.ty.clone(), value, bytes })
+    }
+
+    pub fn from_json_argument(arg : &Argument, value : &Value) -> Result<Self, DynamicError> {
+        if BUILT_IN.contains(&arg.ty.as_str()) {
+            return Self::from_json(&arg.ty, value);
+        }
+        let schema = described(arg)?;
+        let mut bytes = vec![];
+        write_schema(schema, value, &mut bytes, &mut vec![])?;
+        let value = read_schema(schema, &mut bytes.iter().copied().collect(), &mut vec![])?; // read back, so it's in the same form decode gives: 1.0 for 1, "5" for 5 as an i64...
+        Ok(DynamicValue::Described { ty : arg.ty.clone(), value, bytes })
+    }
+
+    fn as_f64(&self) -> Option<f64> { // for range checks
+        match self {
+            DynamicValue::U8 (v) => Some(*v as f64),
+            DynamicValue::U16 (v) => Some(*v as f64),
+            DynamicValue::U32 (v) => Some(*v as f64),
+            DynamicValue::U64 (v) => Some(*v as f64),
+            DynamicValue::I32 (v) => Some(*v as f64),
+            DynamicValue::F32 (v) => Some(*v as f64),
+            _ => None
+        }
+    }
+
+    fn validate(&self, arg : &Argument) -> Result<(), ValidationFailure> { // the manifest side of the derive's range and max_len checks
+        if let (Some (range), Some (value)) = (&arg.range, self.as_f64()) {
+            if !range.contains(value) {
+                return Err(ValidationFailure::OutOfRange);
+            }
+        }
+        if let (Some (max), DynamicValue::String (s)) = (arg.max_len, self) {
+            if s.len() as u64 > max {
+                return Err(ValidationFailure::TooLong { max : max as usize, len : s.len() });
+            }
+        }
+        Ok(())
+    }
+}
+
+
+impl std::fmt::Display for DynamicValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DynamicValue::U8 (v) => write!(f, "{}", v),
+            DynamicValue::U16 (v) => write!(f, "{}", v),
+            DynamicValue::U32 (v) => write!(f, "{}", v),
+            DynamicValue::U64 (v) => write!(f, "{}", v),
+            DynamicValue::I32 (v) => write!(f, "{}", v),
+            DynamicValue::F32 (v) => write!(f, "{:?}", v),
+            DynamicValue::Bool (v) => write!(f, "{}", v),
+            DynamicValue::String (v) => write!(f, "{:?}", v),
+            DynamicValue::Described { value, .. } => write!(f, "{}", value)
+        }
+    }
+}
+
+
+const BUILT_IN : [&str; 8] = ["u8", "u16", "u32", "u64", "i32", "f32", "bool", "String"];
+
+
+fn described(arg : &Argument) -> Result<&Value, DynamicError> { // the schema, if there's one to go by. {"opaque":...} is a type that never spelled its layout out
+    match &arg.schema {
+        Some (schema) if schema.get("opaque").is_none() => Ok(schema),
+        _ => Err(DynamicError::UnsupportedType (arg.ty.clone()))
+    }
+}
+
+
+fn take<const N : usize>(data : &mut VecDeque<u8>) -> Result<[u8; N], DynamicError> {
+    if data.len() < N {
+        return Err(DecodeError::Malformed.into());
+    }
+    let mut ret = [0; N];
+    for (byte, b) in ret.iter_mut().zip(data.drain(..N)) {
+        *byte = b;
+    }
+    Ok(ret)
+}
+
+
+fn count(data : &mut VecDeque<u8>) -> Result<usize, DynamicError> {
+    Ok(u16::from_be_bytes(take(data)?) as usize)
+}
+
+
+fn unknown(schema : &Value) -> DynamicError {
+    DynamicError::UnsupportedType (schema.to_string())
+}
+
+
+fn enum_schema<'a>(schema : &'a Value, enums : &[&'a Value]) -> Result<&'a Value, DynamicError> { // {"enum":name} on its own refers back to the enclosing enum of that name
+    if schema.get("variants").is_some() {
+        return Ok(schema);
+    }
+    enums.iter().rev().find(|e| e["enum"] == schema["enum"]).copied().ok_or_else(|| unknown(schema))
+}
+
+
+fn fields_of(schema : &Value) -> Result<&Vec<Value>, DynamicError> {
+    schema["fields"].as_array().ok_or_else(|| unknown(schema))
+}
+
+
+fn read_fields<'a>(fields : &'a [Value], data : &mut VecDeque<u8>, enums : &mut Vec<&'a Value>) -> Result<Value, DynamicError> { // named fields make an object, unnamed ones an array
+    let mut object = serde_json::Map::new();
+    let mut array = vec![];
+    for field in fields {
+        let value = read_schema(&field["type"], data, enums)?;
+        match field["name"].as_str() {
+            Some (name) => { object.insert(name.to_string(), value); }
+            None => array.push(value)
+        }
+    }
+    Ok(if object.is_empty() { Value::Array (array) } else { Value::Object (object) })
+}
+
+
+fn read_schema<'a>(schema : &'a Value, data : &mut VecDeque<u8>, enums : &mut Vec<&'a Value>) -> Result<Value, DynamicError> { // the bytes of a schema type, as JSON
+    if let Some (ty) = schema.as_str() {
+        return Ok(match ty {
+            "i8" => Value::from(i8::from_be_bytes(take(data)?)),
+            "i16" => Value::from(i16::from_be_bytes(take(data)?)),
+            "i64" => Value::String (i64::from_be_bytes(take(data)?).to_string()), // strings, like u64, so JavaScript doesn't round them
+            "f64" => Value::from(f64::from_be_bytes(take(data)?)),
+            "char" => Value::String (char::from_u32(u32::from_be_bytes(take(data)?)).ok_or(DecodeError::Malformed)?.to_string()),
+            ty if BUILT_IN.contains(&ty) => DynamicValue::decode(ty, data)?.to_json(),
+            _ => return Err(unknown(schema))
+        });
+    }
+    if let Some (item) = schema.get("seq") {
+        return (0..count(data)?).map(|_| read_schema(item, data, enums)).collect();
+    }
+    if let Some (inner) = schema.get("option") {
+        return match take::<1>(data)?[0] {
+            0 => Ok(Value::Null),
+            1 => read_schema(inner, data, enums),
+            _ => Err(DecodeError::Malformed.into())
+        };
+    }
+    if let Some (Value::Array (kv)) = schema.get("map") {
+        let (key, value) = (kv.first().ok_or_else(|| unknown(schema))?, kv.get(1).ok_or_else(|| unknown(schema))?);
+        return (0..count(data)?).map(|_| Ok(Value::Array (vec![read_schema(key, data, enums)?, read_schema(value, data, enums)?]))).collect(); // [key, value] pairs, since keys needn't be strings
+    }
+    if schema.get("struct").is_some() {
+        return read_fields(fields_of(schema)?, data, enums);
+    }
+    if schema.get("enum").is_some() {
+        let schema = enum_schema(schema, enums)?;
+        let variants = schema["variants"].as_array().ok_or_else(|| unknown(schema))?;
+        let variant = variants.get(take::<1>(data)?[0] as usize).ok_or(DecodeError::Malformed)?;
+        let name = variant["name"].as_str().ok_or_else(|| unknown(schema))?.to_string();
+        let fields = fields_of(variant)?;
+        if fields.is_empty() {
+            return Ok(Value::String (name)); // "Dot", or {"Circle":[2.5]} when there's something in it, the way serde_json does enums
+        }
+        enums.push(schema);
+        let value = read_fields(fields, data, enums);
+        enums.pop();
+        return Ok(serde_json::json!({ name : value? }));
+    }
+    Err(unknown(schema))
+}
+
+
+fn write_fields<'a>(fields : &'a [Value], value : &Value, out : &mut Vec<u8>, enums : &mut Vec<&'a Value>) -> Result<(), DynamicError> {
+    for (i, field) in fields.iter().enumerate() {
+        let item = match field["name"].as_str() {
+            Some (name) => value.get(name),
+            None => value.get(i)
+        };
+        let item = item.ok_or_else(|| DynamicError::Json (format!("{} has no field {}", value, field.get("name").unwrap_or(&Value::from(i)))))?;
+        write_schema(&field["type"], item, out, enums)?;
+    }
+    Ok(())
+}
+
+
+fn write_count(len : usize, out : &mut Vec<u8>) -> Result<(), DynamicError> {
+    let len = u16::try_from(len).map_err(|_| DynamicError::Json (format!("{} items is too many; the limit is 65535", len)))?;
+    out.extend_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+
+fn write_schema<'a>(schema : &'a Value, value : &Value, out : &mut Vec<u8>, enums : &mut Vec<&'a Value>) -> Result<(), DynamicError> { // the other way: JSON in the form read_schema gives, to bytes
+    let bad = || DynamicError::Json (format!("{} doesn't fit {}", value, schema));
+    if let Some (ty) = schema.as_str() {
+        let int = || value.as_i64().or_else(|| value.as_str().and_then(|s| s.parse().ok())).ok_or_else(bad);
+        match ty {
+            "i8" => out.extend_from_slice(&i8::try_from(int()?).map_err(|_| bad())?.to_be_bytes()),
+            "i16" => out.extend_from_slice(&i16::try_from(int()?).map_err(|_| bad())?.to_be_bytes()),
+            "i64" => out.extend_from_slice(&int()?.to_be_bytes()),
+            "f64" => out.extend_from_slice(&value.as_f64().ok_or_else(bad)?.to_be_bytes()),
+            "char" => {
+                let mut chars = value.as_str().ok_or_else(bad)?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some (c), None) => out.extend_from_slice(&(c as u32).to_be_bytes()),
+                    _ => return Err(bad())
+                }
+            }
+            "String" => {
+                let s = value.as_str().ok_or_else(bad)?;
+                write_count(s.len(), out)?;
+                out.extend_from_slice(s.as_bytes());
+            }
+            ty if BUILT_IN.contains(&ty) => out.append(&mut DynamicValue::from_json(ty, value)?.encode()),
+            _ => return Err(unknown(schema))
+        }
+        return Ok(());
+    }
+    if let Some (item) = schema.get("seq") {
+        let items = value.as_array().ok_or_else(bad)?;
+        write_count(items.len(), out)?;
+        return items.iter().try_for_each(|v| write_schema(item, v, out, enums));
+    }
+    if let Some (inner) = schema.get("option") {
+        if value.is_null() {
+            out.push(0);
+            return Ok(());
+        }
+        out.push(1);
+        return write_schema(inner, value, out, enums);
+    }
+    if let Some (Value::Array (kv)) = schema.get("map") {
+        let (key, val) = (kv.first().ok_or_else(|| unknown(schema))?, kv.get(1).ok_or_else(|| unknown(schema))?);
+        let pairs = value.as_array().ok_or_else(bad)?;
+        write_count(pairs.len(), out)?;
+        for pair in pairs {
+            match pair.as_array().map(Vec::as_slice) {
+                Some ([k, v]) => {
+                    write_schema(key, k, out, enums)?;
+                    write_schema(val, v, out, enums)?;
+                }
+                _ => return Err(bad())
+            }
+        }
+        return Ok(());
+    }
+    if schema.get("struct").is_some() {
+        return write_fields(fields_of(schema)?, value, out, enums);
+    }
+    if schema.get("enum").is_some() {
+        let schema = enum_schema(schema, enums)?;
+        let variants = schema["variants"].as_array().ok_or_else(|| unknown(schema))?;
+        let (name, fields) = match value {
+            Value::String (name) => (name.as_str(), None),
+            Value::Object (object) if object.len() == 1 => object.iter().next().map(|(k, v)| (k.as_str(), Some (v))).ok_or_else(bad)?,
+            _ => return Err(bad())
+        };
+        let index = variants.iter().position(|v| v["name"] == name).ok_or_else(bad)?;
+        out.push(u8::try_from(index).map_err(|_| unknown(schema))?);
+        let expected = fields_of(&variants[index])?;
+        return match fields {
+            None if expected.is_empty() => Ok(()),
+            Some (fields) => {
+                enums.push(schema);
+                let ret = write_fields(expected, fields, out, enums);
+                enums.pop();
+                ret
+            }
+            None => Err(bad())
+        };
+    }
+    Err(unknown(schema))
+}
+
+
+fn validate_args(op : &Operation, args : &[DynamicValue]) -> Result<(), DynamicError> {
+    for (field, (value, arg)) in args.iter().zip(&op.args).enumerate() {
+        if let Err(failure) = value.validate(arg) {
+            return Err(DynamicError::Invalid { operation : op.name.clone(), field, failure });
+        }
+    }
+    Ok(())
+}
+
+
+impl DynamicFrame {
+    pub fn new(manifest : &Manifest, operation : &str, args : Vec<DynamicValue>) -> Result<Self, DynamicError> { // checks the arguments against the manifest up front
+        let op = manifest.operation_named(operation).ok_or_else(|| DynamicError::UnknownOperation (operation.to_string()))?;
+        let expected : Vec<String> = op.args.iter().map(|a| a.ty.clone()).collect();
+        let got : Vec<String> = args.iter().map(|a| a.type_name().to_string()).collect();
+        if expected != got {
+            return Err(DynamicError::WrongArguments { operation : op.name.clone(), expected, got });
+        }
+        validate_args(op, &args)?;
+        Ok(Self { operation : op.name.clone(), opcode : op.opcode, args })
+    }
+
+    pub fn decode(manifest : &Manifest, data : &[u8]) -> Result<Self, DynamicError> { // like the derived decode, trailing bytes are ignored
+        let mut data : VecDeque<u8> = data.iter().copied().collect();
+        let opcode = data.pop_front().ok_or(DecodeError::Malformed)?;
+        let op = manifest.operation(opcode).ok_or(DynamicError::UnknownOpcode (opcode))?;
+        let mut args = Vec::with_capacity(op.args.len());
+        for arg in &op.args {
+            args.push(DynamicValue::decode_argument(arg, &mut data)?);
+        }
+        validate_args(op, &args)?;
+        Ok(Self { operation : op.name.clone(), opcode, args })
+    }
+
+    pub fn from_json(manifest : &Manifest, json : &Value) -> Result<Self, DynamicError> {
+        let name = json.get("op").and_then(Value::as_str).ok_or_else(|| DynamicError::Json ("no \"op\" string".to_string()))?;
+        let op = manifest.operation_named(name).ok_or_else(|| DynamicError::UnknownOperation (name.to_string()))?;
+        let args = match json.get("args") {
+            Some (Value::Array (args)) => args.as_slice(),
+            None => &[], // operations without arguments can leave them out
+            Some (_) => return Err(DynamicError::Json ("\"args\" isn't an array".to_string()))
+        };
+        if args.len() != op.args.len() {
+            return Err(DynamicError::Json (format!("{} takes {} arguments, got {}", op.name, op.args.len(), args.len())));
+        }
+        let args = op.args.iter().zip(args).map(|(arg, value)| DynamicValue::from_json_argument(arg, value)).collect::<Result<Vec<_>, _>>()?;
+        Self::new(manifest, name, args)
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({ "op" : self.operation, "args" : self.args.iter().map(DynamicValue::to_json).collect::<Vec<_>>() })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut ret = vec![self.opcode];
+        for arg in &self.args {
+            ret.append(&mut arg.encode());
+        }
+        ret
+    }
+}
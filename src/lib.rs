@@ -1,3 +1,9 @@
 pub mod protocol;
 pub mod server;
+pub mod codec;
+pub mod manifest;
+pub mod dynamic;
+pub mod codegen;
+#[cfg(feature = "serde")]
+pub mod serde_bridge;
 pub extern crate protocol_v3_macro;
\ No newline at end of file
@@ -0,0 +1,248 @@
+// protocol_v3 command line tool. everything it does works off a manifest, either the JSON the /manifest endpoint serves (both directions of an
+// application) or a single protocol's manifest straight out of ProtocolFrame::manifest().
+
+use protocol_v3::manifest::{Manifest, ServerManifest};
+use protocol_v3::dynamic::{DynamicFrame, DynamicValue};
+use protocol_v3::codegen;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+
+const USAGE : &str = "usage: protocol_v3 <command> [arguments]
+
+commands:
+    manifest <manifest> [output]      print a manifest, pretty - mostly useful to save one from a running server
+    decode <manifest> [options] [frame...]
+                                      decode frames given as hex (or hex lines on stdin) into annotated fields
+        --outgoing                    decode with the outgoing protocol of a server manifest, rather than the incoming one
+        --binary <file>               decode the raw bytes in <file> as a single frame
+    typescript <manifest> [output]    generate a TypeScript client module
+    python <manifest> [output]        generate a Python codec module
+    vectors <manifest> [output]       generate golden test vectors (JSON) for every operation
+    wireshark <manifest> [output]     generate a Wireshark dissector (Lua plugin)
+    docs <manifest> [output] [--html] generate protocol reference docs, in Markdown or as an HTML page
+
+<manifest> is a path to a manifest JSON file, - to read it from stdin, or the http:// address of a running server (the /manifest path is
+filled in if there isn't one). output goes to stdout if no output path is given.
+";
+
+
+enum AnyManifest {
+    Server (ServerManifest),
+    Protocol (Manifest)
+}
+
+
+fn fetch(address : &str) -> Result<String, Box<dyn std::error::Error>> { // a bare-bones GET, which is all the server understands anyway
+    let rest = &address["http://".len()..];
+    let (host, path) = match rest.find('/') {
+        Some (slash) if slash + 1 < rest.len() => (&rest[..slash], &rest[slash..]),
+        Some (slash) => (&rest[..slash], "/manifest"),
+        None => (rest, "/manifest")
+    };
+    let port = host.rsplit_once(']').map_or(host, |(_, after)| after).contains(':'); // [::1] is all colons, but has no port
+    let target = if port { host.to_string() } else { format!("{}:80", host) };
+    let mut stream = TcpStream::connect(target)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    stream.write_all(format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host).as_bytes())?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response)?; // the server closes the connection after the manifest
+    let response = String::from_utf8(response)?;
+    let (head, body) = response.split_once("\r\n\r\n").ok_or("the server's response has no body")?;
+    let status = head.lines().next().unwrap_or("");
+    if status.split(' ').nth(1) != Some("200") {
+        return Err(format!("the server said {}", status).into());
+    }
+    Ok(body.to_string())
+}
+
+
+fn read_source(source : &str) -> Result<String, Box<dyn std::error::Error>> {
+    if source == "-" {
+        let mut json = String::new();
+        std::io::stdin().read_to_string(&mut json)?;
+        Ok(json)
+    }
+    else if source.starts_with("http://") {
+        fetch(source)
+    }
+    else {
+        Ok(std::fs::read_to_string(source)?)
+    }
+}
+
+
+fn read_manifest(source : &str) -> Result<AnyManifest, Box<dyn std::error::Error>> {
+    let json = read_source(source)?;
+    match ServerManifest::parse(&json) {
+        Ok (manifest) => Ok(AnyManifest::Server (manifest)),
+        Err (_) => Ok(AnyManifest::Protocol (Manifest::parse(&json)?))
+    }
+}
+
+
+fn write_output(output : Option<&String>, contents : &str) -> Result<(), Box<dyn std::error::Error>> {
+    match output {
+        Some (path) => std::fs::write(path, contents)?,
+        None => print!("{}", contents)
+    }
+    Ok(())
+}
+
+
+fn parse_hex(frame : &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> { // tolerant of the usual dump formats: 0x prefixes, spaces, colons
+    let frame = frame.trim();
+    let frame = frame.strip_prefix("0x").unwrap_or(frame);
+    let digits : String = frame.chars().filter(|c| !c.is_whitespace() && *c != ':' && *c != '-').collect();
+    Ok(hex::decode(digits).map_err(|e| format!("{} isn't hex: {}", frame, e))?)
+}
+
+
+fn hex_bytes(bytes : &[u8]) -> String { // spaced hex, cut short for long fields
+    let mut ret : Vec<String> = bytes.iter().take(16).map(|b| format!("{:02x}", b)).collect();
+    if bytes.len() > 16 {
+        ret.push(format!("... ({} bytes)", bytes.len()));
+    }
+    ret.join(" ")
+}
+
+
+fn row(range : String, label : &str, bytes : &[u8], note : &str) -> String {
+    format!("  {:<8} {:<20} {:<24} {}\n", range, label, hex_bytes(bytes), note).trim_end().to_string() + "\n"
+}
+
+
+fn annotate(manifest : &Manifest, bytes : &[u8]) -> String { // decodes field by field, so a bad frame still shows everything up to the point it broke
+    let Some (&opcode) = bytes.first() else {
+        return "empty frame\n".to_string();
+    };
+    let Some (op) = manifest.operation(opcode) else {
+        return format!("unknown opcode {} in {}, {} bytes\n", opcode, manifest.protocol, bytes.len()) + &row("0..1".to_string(), "opcode", &bytes[..1], "") + &row(format!("1..{}", bytes.len()), "unknown", &bytes[1..], "");
+    };
+    let mut out = format!("{} (opcode {}), {} bytes\n", op.name, opcode, bytes.len());
+    out += &row("0..1".to_string(), "opcode", &bytes[..1], "");
+    let mut data : VecDeque<u8> = bytes[1..].iter().copied().collect();
+    let mut args = vec![];
+    for (i, arg) in op.args.iter().enumerate() {
+        let start = bytes.len() - data.len();
+        let label = format!("{} : {}", arg.display_name(i), arg.ty);
+        match DynamicValue::decode_argument(arg, &mut data) {
+            Ok (value) => {
+                let end = bytes.len() - data.len();
+                out += &row(format!("{}..{}", start, end), &label, &bytes[start..end], &format!("= {}", value));
+                args.push(value);
+            }
+            Err (e) => {
+                out += &row(format!("{}..", start), &label, &bytes[start..], &format!("error: {}", e));
+                return out;
+            }
+        }
+    }
+    if !data.is_empty() {
+        let start = bytes.len() - data.len();
+        out += &row(format!("{}..{}", start, bytes.len()), "trailing", &bytes[start..], "");
+    }
+    if let Err (e) = DynamicFrame::new(manifest, &op.name, args) {
+        out += &format!("  invalid: {}\n", e);
+    }
+    out
+}
+
+
+fn decode(args : &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut outgoing = false;
+    let mut frames = vec![];
+    let mut binary = None;
+    let mut rest = args[1..].iter();
+    while let Some (arg) = rest.next() {
+        match arg.as_str() {
+            "--outgoing" => outgoing = true,
+            "--binary" => binary = Some(rest.next().ok_or("--binary needs a file")?),
+            frame => frames.push(parse_hex(frame)?)
+        }
+    }
+    let manifest = match read_manifest(&args[0])? {
+        AnyManifest::Server (manifest) if outgoing => manifest.outgoing_protocol,
+        AnyManifest::Server (manifest) => manifest.incoming_protocol,
+        AnyManifest::Protocol (manifest) => manifest
+    };
+    if let Some (path) = binary {
+        frames.push(std::fs::read(path)?);
+    }
+    else if frames.is_empty() { // one frame per line
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        for line in input.lines().filter(|l| !l.trim().is_empty()) {
+            frames.push(parse_hex(line)?);
+        }
+    }
+    let annotated : Vec<String> = frames.iter().map(|frame| annotate(&manifest, frame)).collect();
+    print!("{}", annotated.join("\n"));
+    Ok(())
+}
+
+
+fn run(args : &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    match args.first().map(|s| s.as_str()) {
+        Some ("manifest") if args.len() >= 2 => {
+            let json : serde_json::Value = serde_json::from_str(&read_source(&args[1])?)?;
+            write_output(args.get(2), &(serde_json::to_string_pretty(&json)? + "\n"))
+        }
+        Some ("decode") if args.len() >= 2 => decode(&args[1..]),
+        Some ("typescript") if args.len() >= 2 => {
+            let code = match read_manifest(&args[1])? {
+                AnyManifest::Server (manifest) => codegen::typescript::generate(&manifest)?,
+                AnyManifest::Protocol (manifest) => codegen::typescript::generate_protocol(&manifest)?
+            };
+            write_output(args.get(2), &code)
+        }
+        Some ("python") if args.len() >= 2 => {
+            let code = match read_manifest(&args[1])? {
+                AnyManifest::Server (manifest) => codegen::python::generate(&manifest)?,
+                AnyManifest::Protocol (manifest) => codegen::python::generate_protocol(&manifest)?
+            };
+            write_output(args.get(2), &code)
+        }
+        Some ("vectors") if args.len() >= 2 => {
+            let file = match read_manifest(&args[1])? {
+                AnyManifest::Server (manifest) if manifest.outgoing_protocol.protocol == manifest.incoming_protocol.protocol => codegen::vectors::generate(&[&manifest.incoming_protocol])?, // same enum both ways: its frames once, not twice
+                AnyManifest::Server (manifest) => codegen::vectors::generate(&[&manifest.incoming_protocol, &manifest.outgoing_protocol])?,
+                AnyManifest::Protocol (manifest) => codegen::vectors::generate(&[&manifest])?
+            };
+            write_output(args.get(2), &file.to_json())
+        }
+        Some ("docs") if args[1..].iter().any(|a| a != "--html") => { // a manifest besides the flag, or it's the usage message
+            let html = args.iter().any(|a| a == "--html");
+            let rest : Vec<&String> = args[1..].iter().filter(|a| *a != "--html").collect();
+            let docs = match (read_manifest(rest[0])?, html) {
+                (AnyManifest::Server (manifest), false) => codegen::docs::generate(&manifest),
+                (AnyManifest::Server (manifest), true) => codegen::docs::generate_html(&manifest),
+                (AnyManifest::Protocol (manifest), false) => codegen::docs::generate_protocol(&manifest),
+                (AnyManifest::Protocol (manifest), true) => codegen::docs::generate_html_protocol(&manifest)
+            };
+            write_output(rest.get(1).copied(), &docs)
+        }
+        Some ("wireshark") if args.len() >= 2 => {
+            let code = match read_manifest(&args[1])? {
+                AnyManifest::Server (manifest) => codegen::wireshark::generate(&manifest)?,
+                AnyManifest::Protocol (manifest) => codegen::wireshark::generate_protocol(&manifest)?
+            };
+            write_output(args.get(2), &code)
+        }
+        _ => {
+            eprint!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+
+fn main() {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
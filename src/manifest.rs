@@ -0,0 +1,318 @@
+// the manifest, parsed. #[derive(ProtocolFrame)] writes manifests out as JSON (that's what the /manifest endpoint serves and what protocol.js reads),
+// and anything on the Rust side that needs to understand one - the code generators, mostly - reads it back in through these types.
+// they're read and written by hand, through serde_json::Value, so the serde dependency (and its derive) stays behind the serde feature.
+
+use std::collections::BTreeMap;
+use serde_json::{Map, Value};
+use crate::protocol::ProtocolFrame;
+
+
+pub type Meta = BTreeMap<String, Value>; // #[protocol(meta(key = value, ...))] on a frame, variant or field
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    pub min       : Option<f64>,
+    pub max       : Option<f64>,
+    pub inclusive : bool // only applies to max; min is always inclusive, same as a Rust range
+}
+
+
+impl Range {
+    pub fn contains(&self, value : f64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| if self.inclusive { value <= max } else { value < max })
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Argument {
+    pub name     : Option<String>, // only for named fields; tuple fields are known by position
+    pub ty       : String, // "type" in the JSON
+    pub range    : Option<Range>,
+    pub max_len  : Option<u64>,
+    pub validate : Option<String>, // name of the server-side validation function; clients can't run it, but it's worth knowing it's there
+    pub schema   : Option<Value>, // layout of non-built-in types, from ProtocolSegment::schema
+    pub doc      : Option<String>, // the field's doc comment
+    pub meta     : Meta
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub name   : String,
+    pub opcode : u8,
+    pub doc    : Option<String>, // the variant's doc comment
+    pub meta   : Meta,
+    pub args   : Vec<Argument>
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub protocol   : String,
+    pub doc        : Option<String>, // the frame enum's doc comment
+    pub meta       : Meta,
+    pub operations : Vec<Operation>
+}
+
+
+#[derive(Debug)]
+pub enum ParseError {
+    Json (serde_json::Error), // it isn't JSON at all
+    Shape (String) // it's JSON, but not shaped right: where, and what's wrong
+}
+
+
+impl std::error::Error for ParseError {
+    fn description(&self) -> &str {
+        "That JSON isn't what it should be!"
+    }
+}
+
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseError::Json (e) => write!(f, "Bad JSON: {}", e),
+            ParseError::Shape (e) => write!(f, "Bad JSON: {}", e)
+        }
+    }
+}
+
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e : serde_json::Error) -> Self {
+        ParseError::Json (e)
+    }
+}
+
+
+pub(crate) struct Fields<'a> { // one JSON object being read, with errors that say which one
+    object : &'a Map<String, Value>,
+    what   : &'a str
+}
+
+
+impl<'a> Fields<'a> {
+    pub(crate) fn of(value : &'a Value, what : &'a str) -> Result<Self, ParseError> {
+        match value {
+            Value::Object (object) => Ok(Self { object, what }),
+            other => Err(ParseError::Shape (format!("{} should be an object, not {}", what, other)))
+        }
+    }
+
+    fn wrong(&self, key : &str, expected : &str) -> ParseError {
+        ParseError::Shape (format!("\"{}\" in {} should be {}", key, self.what, expected))
+    }
+
+    pub(crate) fn get(&self, key : &str) -> Option<&'a Value> { // null counts as missing, like it did for serde's Option fields
+        self.object.get(key).filter(|value| !value.is_null())
+    }
+
+    pub(crate) fn required(&self, key : &str) -> Result<&'a Value, ParseError> {
+        self.get(key).ok_or_else(|| ParseError::Shape (format!("{} has no \"{}\"", self.what, key)))
+    }
+
+    pub(crate) fn string(&self, key : &str) -> Result<String, ParseError> {
+        self.required(key)?.as_str().map(str::to_string).ok_or_else(|| self.wrong(key, "a string"))
+    }
+
+    pub(crate) fn optional_string(&self, key : &str) -> Result<Option<String>, ParseError> {
+        self.get(key).map(|value| value.as_str().map(str::to_string).ok_or_else(|| self.wrong(key, "a string"))).transpose()
+    }
+
+    pub(crate) fn integer<T : TryFrom<u64>>(&self, key : &str) -> Result<T, ParseError> {
+        self.required(key)?.as_u64().and_then(|n| T::try_from(n).ok()).ok_or_else(|| self.wrong(key, "a whole number in range"))
+    }
+
+    pub(crate) fn optional_u64(&self, key : &str) -> Result<Option<u64>, ParseError> {
+        self.get(key).map(|value| value.as_u64().ok_or_else(|| self.wrong(key, "a whole number"))).transpose()
+    }
+
+    pub(crate) fn optional_f64(&self, key : &str) -> Result<Option<f64>, ParseError> {
+        self.get(key).map(|value| value.as_f64().ok_or_else(|| self.wrong(key, "a number"))).transpose()
+    }
+
+    pub(crate) fn bool(&self, key : &str) -> Result<bool, ParseError> {
+        self.required(key)?.as_bool().ok_or_else(|| self.wrong(key, "true or false"))
+    }
+
+    pub(crate) fn array(&self, key : &str) -> Result<&'a [Value], ParseError> {
+        self.required(key)?.as_array().map(Vec::as_slice).ok_or_else(|| self.wrong(key, "an array"))
+    }
+
+    pub(crate) fn meta(&self) -> Result<Meta, ParseError> {
+        match self.get("meta") {
+            None => Ok(Meta::new()),
+            Some (Value::Object (meta)) => Ok(meta.iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+            Some (_) => Err(self.wrong("meta", "an object"))
+        }
+    }
+}
+
+
+fn put(object : &mut Map<String, Value>, key : &str, value : Option<Value>) { // leaves out what isn't there, the way the derive writes manifests
+    if let Some (value) = value {
+        object.insert(key.to_string(), value);
+    }
+}
+
+
+fn put_meta(object : &mut Map<String, Value>, meta : &Meta) {
+    if !meta.is_empty() {
+        object.insert("meta".to_string(), Value::Object (meta.iter().map(|(k, v)| (k.clone(), v.clone())).collect()));
+    }
+}
+
+
+impl Range {
+    fn from_json(json : &Value) -> Result<Self, ParseError> {
+        let fields = Fields::of(json, "a range")?;
+        Ok(Self { min : fields.optional_f64("min")?, max : fields.optional_f64("max")?, inclusive : fields.bool("inclusive")? })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut ret = Map::new();
+        put(&mut ret, "min", self.min.map(Value::from));
+        put(&mut ret, "max", self.max.map(Value::from));
+        ret.insert("inclusive".to_string(), Value::Bool (self.inclusive));
+        Value::Object (ret)
+    }
+}
+
+
+impl Argument {
+    pub fn display_name(&self, index : usize) -> String { // what docs and tools call it: its name, or a0, a1... like the generated clients do
+        self.name.clone().unwrap_or_else(|| format!("a{}", index))
+    }
+
+    fn from_json(json : &Value) -> Result<Self, ParseError> {
+        let fields = Fields::of(json, "an argument")?;
+        Ok(Self {
+            name     : fields.optional_string("name")?,
+            ty       : fields.string("type")?,
+            range    : fields.get("range").map(Range::from_json).transpose()?,
+            max_len  : fields.optional_u64("max_len")?,
+            validate : fields.optional_string("validate")?,
+            schema   : fields.get("schema").cloned(),
+            doc      : fields.optional_string("doc")?,
+            meta     : fields.meta()?
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut ret = Map::new();
+        put(&mut ret, "name", self.name.clone().map(Value::String));
+        ret.insert("type".to_string(), Value::String (self.ty.clone()));
+        put(&mut ret, "range", self.range.as_ref().map(Range::to_json));
+        put(&mut ret, "max_len", self.max_len.map(Value::from));
+        put(&mut ret, "validate", self.validate.clone().map(Value::String));
+        put(&mut ret, "schema", self.schema.clone());
+        put(&mut ret, "doc", self.doc.clone().map(Value::String));
+        put_meta(&mut ret, &self.meta);
+        Value::Object (ret)
+    }
+}
+
+
+impl Operation {
+    fn from_json(json : &Value) -> Result<Self, ParseError> {
+        let fields = Fields::of(json, "an operation")?;
+        Ok(Self {
+            name   : fields.string("name")?,
+            opcode : fields.integer("opcode")?,
+            doc    : fields.optional_string("doc")?,
+            meta   : fields.meta()?,
+            args   : fields.array("args")?.iter().map(Argument::from_json).collect::<Result<_, _>>()?
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut ret = Map::new();
+        ret.insert("name".to_string(), Value::String (self.name.clone()));
+        ret.insert("opcode".to_string(), Value::from(self.opcode));
+        put(&mut ret, "doc", self.doc.clone().map(Value::String));
+        put_meta(&mut ret, &self.meta);
+        ret.insert("args".to_string(), Value::Array (self.args.iter().map(Argument::to_json).collect()));
+        Value::Object (ret)
+    }
+}
+
+
+impl Manifest {
+    pub fn parse(json : &str) -> Result<Self, ParseError> {
+        Self::from_json(&serde_json::from_str(json)?)
+    }
+
+    pub fn from_json(json : &Value) -> Result<Self, ParseError> {
+        let fields = Fields::of(json, "a manifest")?;
+        Ok(Self {
+            protocol   : fields.string("protocol")?,
+            doc        : fields.optional_string("doc")?,
+            meta       : fields.meta()?,
+            operations : fields.array("operations")?.iter().map(Operation::from_json).collect::<Result<_, _>>()?
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut ret = Map::new();
+        ret.insert("protocol".to_string(), Value::String (self.protocol.clone()));
+        put(&mut ret, "doc", self.doc.clone().map(Value::String));
+        put_meta(&mut ret, &self.meta);
+        ret.insert("operations".to_string(), Value::Array (self.operations.iter().map(Operation::to_json).collect()));
+        Value::Object (ret)
+    }
+
+    pub fn of<Protocol : ProtocolFrame>() -> Self {
+        Self::parse(&Protocol::manifest()).expect("derived manifests are always valid") // if this fails it's a bug in the derive, not in the caller
+    }
+
+    pub fn operation(&self, opcode : u8) -> Option<&Operation> {
+        self.operations.iter().find(|op| op.opcode == opcode)
+    }
+
+    pub fn operation_named(&self, name : &str) -> Option<&Operation> {
+        self.operations.iter().find(|op| op.name == name)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerManifest { // the whole /manifest document: both directions of one application
+    pub application_name  : String,
+    pub incoming_protocol : Manifest, // client -> server
+    pub outgoing_protocol : Manifest // server -> client
+}
+
+
+impl ServerManifest {
+    pub fn parse(json : &str) -> Result<Self, ParseError> {
+        Self::from_json(&serde_json::from_str(json)?)
+    }
+
+    pub fn from_json(json : &Value) -> Result<Self, ParseError> {
+        let fields = Fields::of(json, "a server manifest")?;
+        Ok(Self {
+            application_name  : fields.string("application_name")?,
+            incoming_protocol : Manifest::from_json(fields.required("incoming_protocol")?)?,
+            outgoing_protocol : Manifest::from_json(fields.required("outgoing_protocol")?)?
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut ret = Map::new();
+        ret.insert("application_name".to_string(), Value::String (self.application_name.clone()));
+        ret.insert("incoming_protocol".to_string(), self.incoming_protocol.to_json());
+        ret.insert("outgoing_protocol".to_string(), self.outgoing_protocol.to_json());
+        Value::Object (ret)
+    }
+
+    pub fn of<InProtocol : ProtocolFrame, OutProtocol : ProtocolFrame>(name : &str) -> Self {
+        Self {
+            application_name  : name.to_string(),
+            incoming_protocol : Manifest::of::<InProtocol>(),
+            outgoing_protocol : Manifest::of::<OutProtocol>()
+        }
+    }
+}
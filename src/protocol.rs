@@ -1,8 +1,13 @@
 use std::collections::VecDeque;
+use crate::manifest::Manifest;
+use crate::dynamic::{DynamicFrame, DynamicError};
 
 
 #[derive(Debug)]
-pub struct DecodeError {}
+pub enum DecodeError {
+    Malformed, // truncated data, bad utf-8, unknown opcode: the bytes just don't make sense
+    Invalid (ValidationError) // the bytes made sense, but one of the values broke a #[protocol(range/max_len/validate)] rule
+}
 
 
 impl std::error::Error for DecodeError {
@@ -14,44 +19,162 @@ impl std::error::Error for DecodeError {
 
 impl std::fmt::Display for DecodeError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Protocol Decode Error")
+        match self {
+            DecodeError::Malformed => write!(f, "Protocol Decode Error"),
+            DecodeError::Invalid (e) => write!(f, "Protocol Decode Error: {}", e)
+        }
+    }
+}
+
+
+impl From<ValidationError> for DecodeError {
+    fn from(e : ValidationError) -> Self {
+        DecodeError::Invalid (e)
+    }
+}
+
+
+#[derive(Debug)]
+pub enum ValidationFailure {
+    OutOfRange,
+    TooLong { max : usize, len : usize },
+    Rejected (String) // a #[protocol(validate = "...")] function said no, and this is why
+}
+
+
+#[derive(Debug)]
+pub struct ValidationError {
+    pub operation : &'static str, // variant name
+    pub field     : usize, // index of the field within the variant
+    pub failure   : ValidationFailure
+}
+
+
+impl ValidationError {
+    pub fn new(operation : &'static str, field : usize, failure : ValidationFailure) -> Self {
+        Self { operation, field, failure }
+    }
+}
+
+
+impl std::error::Error for ValidationError {
+    fn description(&self) -> &str {
+        "A client sent a value that failed validation!"
+    }
+}
+
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.failure {
+            ValidationFailure::OutOfRange => write!(f, "field {} of {} is out of range", self.field, self.operation),
+            ValidationFailure::TooLong { max, len } => write!(f, "field {} of {} is {} long, the limit is {}", self.field, self.operation, len, max),
+            ValidationFailure::Rejected (reason) => write!(f, "field {} of {} was rejected: {}", self.field, self.operation, reason)
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct EncodeError { // a frame the wire format has no way of saying: a string over 65535 bytes, a Serde<T> with too many items...
+    pub operation : &'static str, // variant name
+    pub field     : usize, // index of the field among the ones that go on the wire
+    pub reason    : String
+}
+
+
+impl std::error::Error for EncodeError {
+    fn description(&self) -> &str {
+        "A frame can't be encoded!"
+    }
+}
+
+
+impl std::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "field {} of {} can't be encoded: {}", self.field, self.operation, self.reason)
     }
 }
 
 
 pub trait ProtocolFrame : Sized {
     fn encode(&self) -> Vec<u8>;
+    fn encodable(&self) -> Result<(), EncodeError> { // whether encode can say this frame faithfully. the server checks before sending; encode itself never fails
+        Ok(())
+    }
+
     fn decode(data : VecDeque<u8>) -> Result<Self, DecodeError>;
-    fn manifest() -> &'static str; // manifest of this protocol frame type.
+    fn manifest() -> String; // manifest of this protocol frame type. built at runtime, because generic frames need to name their type arguments
+
+    fn to_json(&self) -> Result<String, DynamicError> { // {"op":"Move","args":[...]}, via the dynamic codec, so it fails only if an argument has neither a built-in type nor a schema
+        Ok(DynamicFrame::decode(&Manifest::of::<Self>(), &self.encode())?.to_json().to_string())
+    }
+
+    fn from_json(json : &str) -> Result<Self, DynamicError> { // the way back. goes through the derived decode, so every validation rule applies
+        let json = serde_json::from_str(json).map_err(|e| DynamicError::Json (e.to_string()))?;
+        let frame = DynamicFrame::from_json(&Manifest::of::<Self>(), &json)?;
+        Ok(Self::decode(frame.encode().into())?)
+    }
+}
+
+pub trait Dispatch<Handler> : ProtocolFrame { // implemented by #[derive(ProtocolFrame)] when the enum is marked #[protocol(handler)]
+    fn dispatch(self, handler : &mut Handler) -> impl std::future::Future<Output = ()>;
 }
 
 pub trait ProtocolSegment : Sized {
     fn encode(self) -> Vec<u8>;
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError>;
+    fn encodable(&self) -> Result<(), String> { // why encode can't say this value, if it can't
+        Ok(())
+    }
+
+    fn type_name() -> String { // the name this type goes by in manifests; the default is the Rust type name without module paths, so u8 is "u8" and String is "String"
+        short_type_name(std::any::type_name::<Self>())
+    }
+
+    fn schema() -> Option<String> { // the schema hook: JSON describing the layout of types that aren't plain built-ins, copied into manifests as "schema"
+        None
+    }
+}
+
+
+pub(crate) fn short_type_name(full : &str) -> String { // alloc::string::String -> String, my_game::Pair<core::primitive::u8> -> Pair<u8>
+    let mut ret = String::with_capacity(full.len());
+    let mut segment_start = 0;
+    let mut chars = full.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            ret.truncate(segment_start); // throw away the module path we just wrote
+        }
+        else {
+            ret.push(c);
+            if !(c.is_alphanumeric() || c == '_') {
+                segment_start = ret.len();
+            }
+        }
+    }
+    ret
 }
 
 impl ProtocolSegment for u8 {
     fn encode(self) -> Vec<u8> {
-        let mut v = Vec::with_capacity(1);
-        v.push(self);
-        v
+        vec![self]
     }
 
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
-        data.pop_front().ok_or(DecodeError {})
+        data.pop_front().ok_or(DecodeError::Malformed)
     }
 }
 
 
 impl ProtocolSegment for bool {
     fn encode(self) -> Vec<u8> {
-        let mut v = Vec::with_capacity(1);
-        v.push(if self { 1 } else { 0 });
-        v
+        vec![if self { 1 } else { 0 }]
     }
 
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
-        Ok(if data.pop_front().ok_or(DecodeError {})? == 1 { true } else { false })
+        Ok(data.pop_front().ok_or(DecodeError::Malformed)? == 1)
     }
 }
 
@@ -65,7 +188,7 @@ impl ProtocolSegment for u16 {
     }
 
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
-        let r = [data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?];
+        let r = [data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?];
         Ok(Self::from_be_bytes(r))
     }
 }
@@ -83,7 +206,7 @@ impl ProtocolSegment for u32 {
     }
 
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
-        let r = [data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?];
+        let r = [data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?];
         Ok(Self::from_be_bytes(r))
     }
 }
@@ -105,7 +228,7 @@ impl ProtocolSegment for u64 {
     }
 
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
-        let r = [data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?];
+        let r = [data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?];
         Ok(Self::from_be_bytes(r))
     }
 }
@@ -123,7 +246,7 @@ impl ProtocolSegment for i32 {
     }
 
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
-        let r = [data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?];
+        let r = [data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?];
         Ok(Self::from_be_bytes(r))
     }
 }
@@ -142,31 +265,38 @@ impl ProtocolSegment for f32 {
     }
 
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
-        let r = [data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?];
+        let r = [data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?];
         Ok(Self::from_be_bytes(r))
     }
 }
 
 impl ProtocolSegment for String {
+    fn encodable(&self) -> Result<(), String> {
+        match self.len() {
+            len if len > u16::MAX as usize => Err(format!("{} bytes is too long; strings are limited to 65535", len)),
+            _ => Ok(())
+        }
+    }
+
     fn encode(self) -> Vec<u8> {
         let mut v = Vec::with_capacity(self.len() + 2); // space enough for me and my size information
         v.append(&mut Vec::from((self.len() as u16).to_be_bytes()));
-        v.append(&mut Vec::from(self.clone().into_bytes()));
+        v.append(&mut self.into_bytes());
         v
     }
 
     fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
-        let len : [u8; 2] = [data.pop_front().ok_or(DecodeError {})?, data.pop_front().ok_or(DecodeError {})?];
+        let len : [u8; 2] = [data.pop_front().ok_or(DecodeError::Malformed)?, data.pop_front().ok_or(DecodeError::Malformed)?];
         let len = u16::from_be_bytes(len);
         if data.len() >= len.into() {
             let dat = data.drain(0..len.into()).collect();
             match String::from_utf8(dat) {
                 Ok(str) => Ok(str),
-                Err(_) => {Err(DecodeError{})}
+                Err(_) => {Err(DecodeError::Malformed)}
             }
         }
         else {
-            Err(DecodeError{})
+            Err(DecodeError::Malformed)
         }
     }
 }
@@ -0,0 +1,693 @@
+// the serde bridge (behind the serde feature): a serializer and deserializer for the protocol_v3 wire format, so types that already derive
+// Serialize and Deserialize can be frame arguments by wrapping them in Serde<T>. the encoding extends the built-in segments: numbers are
+// big-endian, bool is one byte, strings and byte arrays are a u16 byte length and then the bytes, Options are a 0/1 byte and then the value,
+// sequences and maps are a u16 count and then the items, enum variants are a u8 index and then the payload, and structs and tuples are
+// just their fields in order. it isn't self-describing, so deserialize_any (and with it #[serde(flatten)], untagged enums etc.) won't work.
+
+use crate::protocol::{short_type_name, DecodeError, ProtocolSegment};
+use serde::{de, ser, Serialize};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+
+#[derive(Debug)]
+pub struct BridgeError (pub String);
+
+
+impl std::error::Error for BridgeError {
+    fn description(&self) -> &str {
+        "A value doesn't fit the protocol_v3 encoding!"
+    }
+}
+
+
+impl std::fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Serde Bridge Error: {}", self.0)
+    }
+}
+
+
+impl ser::Error for BridgeError {
+    fn custom<T : std::fmt::Display>(msg : T) -> Self {
+        BridgeError (msg.to_string())
+    }
+}
+
+
+impl de::Error for BridgeError {
+    fn custom<T : std::fmt::Display>(msg : T) -> Self {
+        BridgeError (msg.to_string())
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Serde<T> (pub T); // any serde type, as a ProtocolSegment: Move(Serde<Position>)
+
+
+impl<T : Serialize + DeserializeOwned + Schema> ProtocolSegment for Serde<T> {
+    fn encodable(&self) -> Result<(), String> { // over 65535 items, over 256 variants, or the type's own Serialize failing
+        to_bytes(&self.0).map(|_| ()).map_err(|e| e.0)
+    }
+
+    fn encode(self) -> Vec<u8> { // nothing at all for a value encodable turns down; the server never gets that far
+        to_bytes(&self.0).unwrap_or_default()
+    }
+
+    fn decode(data : &mut VecDeque<u8>) -> Result<Self, DecodeError> {
+        from_bytes(data).map(Serde).map_err(|_| DecodeError::Malformed)
+    }
+
+    fn type_name() -> String { // Position, not Serde<Position>: the wrapper isn't part of the protocol
+        short_type_name(std::any::type_name::<T>())
+    }
+
+    fn schema() -> Option<String> {
+        Some(T::schema().to_string())
+    }
+}
+
+
+pub fn to_bytes<T : Serialize + ?Sized>(value : &T) -> Result<Vec<u8>, BridgeError> {
+    let mut serializer = Serializer { out : vec![] };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.out)
+}
+
+
+pub fn from_bytes<T : DeserializeOwned>(data : &mut VecDeque<u8>) -> Result<T, BridgeError> { // consumes exactly the bytes of one T, like ProtocolSegment::decode
+    T::deserialize(&mut Deserializer { data })
+}
+
+
+fn length(len : usize) -> Result<[u8; 2], BridgeError> {
+    u16::try_from(len).map(u16::to_be_bytes).map_err(|_| BridgeError (format!("{} items is too many; the limit is 65535", len)))
+}
+
+
+fn variant_index(index : u32) -> Result<u8, BridgeError> {
+    u8::try_from(index).map_err(|_| BridgeError (format!("variant {} is out of range; enums can have at most 256 variants", index)))
+}
+
+
+pub struct Serializer {
+    out : Vec<u8>
+}
+
+
+pub struct Counted<'a> { // sequences and maps, which get their count written in once it's known, so iterators without a size hint work too
+    ser   : &'a mut Serializer,
+    at    : usize,
+    count : usize
+}
+
+
+impl<'a> Counted<'a> {
+    fn new(ser : &'a mut Serializer) -> Self {
+        let at = ser.out.len();
+        ser.out.extend_from_slice(&[0, 0]);
+        Self { ser, at, count : 0 }
+    }
+
+    fn finish(self) -> Result<(), BridgeError> {
+        let len = length(self.count)?;
+        self.ser.out[self.at..self.at + 2].copy_from_slice(&len);
+        Ok(())
+    }
+}
+
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = BridgeError;
+    type SerializeSeq = Counted<'a>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Counted<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v : bool) -> Result<(), BridgeError> {
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v : i8) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i16(self, v : i16) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i32(self, v : i32) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v : i64) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i128(self, v : i128) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v : u8) -> Result<(), BridgeError> {
+        self.out.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v : u16) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v : u32) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v : u64) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u128(self, v : u128) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v : f32) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v : f64) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v : char) -> Result<(), BridgeError> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v : &str) -> Result<(), BridgeError> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v : &[u8]) -> Result<(), BridgeError> {
+        self.out.extend_from_slice(&length(v.len())?);
+        self.out.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), BridgeError> {
+        self.out.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T : Serialize + ?Sized>(self, value : &T) -> Result<(), BridgeError> {
+        self.out.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), BridgeError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name : &'static str) -> Result<(), BridgeError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name : &'static str, index : u32, _variant : &'static str) -> Result<(), BridgeError> {
+        self.out.push(variant_index(index)?);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T : Serialize + ?Sized>(self, _name : &'static str, value : &T) -> Result<(), BridgeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T : Serialize + ?Sized>(self, _name : &'static str, index : u32, _variant : &'static str, value : &T) -> Result<(), BridgeError> {
+        self.out.push(variant_index(index)?);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len : Option<usize>) -> Result<Counted<'a>, BridgeError> {
+        Ok(Counted::new(self))
+    }
+
+    fn serialize_tuple(self, _len : usize) -> Result<Self, BridgeError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name : &'static str, _len : usize) -> Result<Self, BridgeError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(self, _name : &'static str, index : u32, _variant : &'static str, _len : usize) -> Result<Self, BridgeError> {
+        self.out.push(variant_index(index)?);
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len : Option<usize>) -> Result<Counted<'a>, BridgeError> {
+        Ok(Counted::new(self))
+    }
+
+    fn serialize_struct(self, _name : &'static str, _len : usize) -> Result<Self, BridgeError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self, _name : &'static str, index : u32, _variant : &'static str, _len : usize) -> Result<Self, BridgeError> {
+        self.out.push(variant_index(index)?);
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+
+impl ser::SerializeSeq for Counted<'_> {
+    type Ok = ();
+    type Error = BridgeError;
+
+    fn serialize_element<T : Serialize + ?Sized>(&mut self, value : &T) -> Result<(), BridgeError> {
+        self.count += 1;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), BridgeError> {
+        self.finish()
+    }
+}
+
+
+impl ser::SerializeMap for Counted<'_> {
+    type Ok = ();
+    type Error = BridgeError;
+
+    fn serialize_key<T : Serialize + ?Sized>(&mut self, key : &T) -> Result<(), BridgeError> {
+        self.count += 1;
+        key.serialize(&mut *self.ser)
+    }
+
+    fn serialize_value<T : Serialize + ?Sized>(&mut self, value : &T) -> Result<(), BridgeError> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), BridgeError> {
+        self.finish()
+    }
+}
+
+
+impl ser::SerializeTuple for &mut Serializer {
+    type Ok = ();
+    type Error = BridgeError;
+
+    fn serialize_element<T : Serialize + ?Sized>(&mut self, value : &T) -> Result<(), BridgeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), BridgeError> {
+        Ok(())
+    }
+}
+
+
+impl ser::SerializeTupleStruct for &mut Serializer {
+    type Ok = ();
+    type Error = BridgeError;
+
+    fn serialize_field<T : Serialize + ?Sized>(&mut self, value : &T) -> Result<(), BridgeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), BridgeError> {
+        Ok(())
+    }
+}
+
+
+impl ser::SerializeTupleVariant for &mut Serializer {
+    type Ok = ();
+    type Error = BridgeError;
+
+    fn serialize_field<T : Serialize + ?Sized>(&mut self, value : &T) -> Result<(), BridgeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), BridgeError> {
+        Ok(())
+    }
+}
+
+
+impl ser::SerializeStruct for &mut Serializer {
+    type Ok = ();
+    type Error = BridgeError;
+
+    fn serialize_field<T : Serialize + ?Sized>(&mut self, _key : &'static str, value : &T) -> Result<(), BridgeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), BridgeError> {
+        Ok(())
+    }
+}
+
+
+impl ser::SerializeStructVariant for &mut Serializer {
+    type Ok = ();
+    type Error = BridgeError;
+
+    fn serialize_field<T : Serialize + ?Sized>(&mut self, _key : &'static str, value : &T) -> Result<(), BridgeError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), BridgeError> {
+        Ok(())
+    }
+}
+
+
+pub struct Deserializer<'a> {
+    data : &'a mut VecDeque<u8>
+}
+
+
+impl Deserializer<'_> {
+    fn take<const N : usize>(&mut self) -> Result<[u8; N], BridgeError> {
+        if self.data.len() < N {
+            return Err(BridgeError ("ran out of data".to_string()));
+        }
+        let mut ret = [0; N];
+        for (b, d) in ret.iter_mut().zip(self.data.drain(..N)) {
+            *b = d;
+        }
+        Ok(ret)
+    }
+
+    fn len(&mut self) -> Result<usize, BridgeError> {
+        Ok(u16::from_be_bytes(self.take()?) as usize)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, BridgeError> {
+        let len = self.len()?;
+        if self.data.len() < len {
+            return Err(BridgeError ("ran out of data".to_string()));
+        }
+        Ok(self.data.drain(..len).collect())
+    }
+}
+
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'_> {
+    type Error = BridgeError;
+
+    fn deserialize_any<V : de::Visitor<'de>>(self, _visitor : V) -> Result<V::Value, BridgeError> {
+        Err(BridgeError ("the protocol_v3 encoding isn't self-describing".to_string()))
+    }
+
+    fn deserialize_bool<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_bool(self.take::<1>()?[0] == 1) // same as the built-in bool
+    }
+
+    fn deserialize_i8<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_i8(i8::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_i16<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_i16(i16::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_i32<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_i32(i32::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_i64<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_i64(i64::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_i128<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_i128(i128::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_u8<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_u8(self.take::<1>()?[0])
+    }
+
+    fn deserialize_u16<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_u16(u16::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_u32<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_u32(u32::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_u64<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_u64(u64::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_u128<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_u128(u128::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_f32<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_f32(f32::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_f64<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_f64(f64::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_char<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        let c = u32::from_be_bytes(self.take()?);
+        visitor.visit_char(char::from_u32(c).ok_or_else(|| BridgeError (format!("{} isn't a char", c)))?)
+    }
+
+    fn deserialize_str<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_string(String::from_utf8(self.bytes()?).map_err(|_| BridgeError ("bad utf-8".to_string()))?)
+    }
+
+    fn deserialize_bytes<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_byte_buf(self.bytes()?)
+    }
+
+    fn deserialize_option<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        match self.take::<1>()?[0] {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            tag => Err(BridgeError (format!("{} isn't an Option tag", tag)))
+        }
+    }
+
+    fn deserialize_unit<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V : de::Visitor<'de>>(self, _name : &'static str, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V : de::Visitor<'de>>(self, _name : &'static str, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        let left = self.len()?;
+        visitor.visit_seq(Items { de : self, left })
+    }
+
+    fn deserialize_tuple<V : de::Visitor<'de>>(self, len : usize, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_seq(Items { de : self, left : len })
+    }
+
+    fn deserialize_tuple_struct<V : de::Visitor<'de>>(self, _name : &'static str, len : usize, visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_seq(Items { de : self, left : len })
+    }
+
+    fn deserialize_map<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        let left = self.len()?;
+        visitor.visit_map(Items { de : self, left })
+    }
+
+    fn deserialize_struct<V : de::Visitor<'de>>(self, _name : &'static str, fields : &'static [&'static str], visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_seq(Items { de : self, left : fields.len() })
+    }
+
+    fn deserialize_enum<V : de::Visitor<'de>>(self, _name : &'static str, _variants : &'static [&'static str], visitor : V) -> Result<V::Value, BridgeError> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_ignored_any<V : de::Visitor<'de>>(self, visitor : V) -> Result<V::Value, BridgeError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+
+struct Items<'a, 'b> { // a counted run of elements or key-value pairs
+    de   : &'a mut Deserializer<'b>,
+    left : usize
+}
+
+
+impl<'de> de::SeqAccess<'de> for Items<'_, '_> {
+    type Error = BridgeError;
+
+    fn next_element_seed<T : de::DeserializeSeed<'de>>(&mut self, seed : T) -> Result<Option<T::Value>, BridgeError> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+
+impl<'de> de::MapAccess<'de> for Items<'_, '_> {
+    type Error = BridgeError;
+
+    fn next_key_seed<K : de::DeserializeSeed<'de>>(&mut self, seed : K) -> Result<Option<K::Value>, BridgeError> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V : de::DeserializeSeed<'de>>(&mut self, seed : V) -> Result<V::Value, BridgeError> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'_> {
+    type Error = BridgeError;
+    type Variant = Self;
+
+    fn variant_seed<V : de::DeserializeSeed<'de>>(self, seed : V) -> Result<(V::Value, Self), BridgeError> {
+        let index = self.take::<1>()?[0] as u32;
+        let variant = seed.deserialize(IntoDeserializer::<BridgeError>::into_deserializer(index))?;
+        Ok((variant, self))
+    }
+}
+
+
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'_> {
+    type Error = BridgeError;
+
+    fn unit_variant(self) -> Result<(), BridgeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T : de::DeserializeSeed<'de>>(self, seed : T) -> Result<T::Value, BridgeError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V : de::Visitor<'de>>(self, len : usize, visitor : V) -> Result<V::Value, BridgeError> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V : de::Visitor<'de>>(self, fields : &'static [&'static str], visitor : V) -> Result<V::Value, BridgeError> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+
+// the layout of a Serde<T> argument, for the manifest: what the generated clients and the dynamic codec go by, since the wire format doesn't
+// describe itself. it looks like
+//     {"struct":"Position","fields":[{"name":"x","type":"f32"},{"name":"tags","type":{"seq":"String"}}]}
+//     {"enum":"Shape","variants":[{"name":"Dot","fields":[]},{"name":"Circle","fields":[{"type":"f32"}]}]}
+//     {"seq":S}, {"option":S}, {"map":[K,V]}, and the scalars as their names: "u8" ... "i64", "f32", "f64", "bool", "char", "String"
+// an enum inside itself is just {"enum":name}, a reference back to the one it's in. the std building blocks are done here; your own types
+// say `impl Schema for Position {}` and get {"opaque":"Position"}, which the tools refuse by name, or override schema() to spell it out.
+
+pub trait Schema {
+    fn schema() -> Value {
+        json!({ "opaque" : short_type_name(std::any::type_name::<Self>()) })
+    }
+}
+
+
+macro_rules! scalar_schema {
+    ($($t:ty => $name:literal),*) => {
+        $(impl Schema for $t {
+            fn schema() -> Value {
+                json!($name)
+            }
+        })*
+    };
+}
+
+
+scalar_schema!(u8 => "u8", u16 => "u16", u32 => "u32", u64 => "u64", i8 => "i8", i16 => "i16", i32 => "i32", i64 => "i64", f32 => "f32", f64 => "f64", bool => "bool", char => "char", String => "String");
+
+
+impl<T : Schema> Schema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "seq" : T::schema() })
+    }
+}
+
+
+impl<T : Schema> Schema for Option<T> {
+    fn schema() -> Value {
+        json!({ "option" : T::schema() })
+    }
+}
+
+
+impl<T : Schema> Schema for Box<T> { // serde encodes a Box as what's in it
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+
+impl<K : Schema, V : Schema> Schema for BTreeMap<K, V> {
+    fn schema() -> Value {
+        json!({ "map" : [K::schema(), V::schema()] })
+    }
+}
+
+
+impl<K : Schema, V : Schema, S> Schema for HashMap<K, V, S> {
+    fn schema() -> Value {
+        json!({ "map" : [K::schema(), V::schema()] })
+    }
+}
@@ -1,255 +1,933 @@
 // This has a single focus: a websocket server. It is not an HTTP server beyond the upgrade mechanism - all non-Upgrade requests will 418 for the memes.
 /* 
     Access model:
-    create WebSocketServer object with port and address. it's async. call accept on it to get a new client - accept will not return a WebSocketClientStream until
+    create WebSocketServer object from a ServerConfig (addresses to bind, limits, timeouts). it's async. call accept on it to get a new client - accept will not return a WebSocketClientStream until
     the handshake is complete. in a different async "thread", call the WebSocketClientStream's get_message function.
-    WebSocketServers will be generic - the generic is an implementor of ProtocolFrame, and messages will be dumped into that type. if it errors from poison, the client
-    is immediately dropped, no questions asked. Same if the client attempts to broadcast a frame with size more than 65535 bytes. when and only when it's successfully received without
+    WebSocketServers are generic over two ProtocolFrames: what clients send us (In) and what we send them (Out). streams, readers and senders carry the same
+    types, so a frame can't go the wrong way. incoming messages are dumped into In. if it errors from poison, the client
+    is immediately dropped, no questions asked. Same if the client sends a frame or message bigger than the ServerConfig allows. when and only when it's successfully received without
     poison dropping, the get_message function returns with the message.
+    split() a WebSocketClientStream into a ClientReader (stays in the read loop) and a ClientSender, which can be cloned into every task that wants to push frames to
+    that client. all writes go through one writer task per client, so control frames (pongs, pings, the Close) never wait behind a big message.
+    readers (and whole streams) are futures Streams of Result<In, Disconnect> that end after the Disconnect; senders (and whole streams) are Sinks of Out.
 */
 
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, AsyncBufReadExt, BufReader};
 use tokio::select;
-use crate::protocol::ProtocolFrame;
+use crate::protocol::{ProtocolFrame, Dispatch, EncodeError};
+use crate::dynamic::DynamicError;
+use crate::manifest::ServerManifest;
 use tokio::task::JoinSet;
 use std::collections::HashMap;
 use base64::engine::Engine as _;
 use base64::engine::general_purpose::STANDARD as BASE64;
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::future::Future;
+use futures::{Stream, StreamExt, Sink};
+use futures::stream::FusedStream;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio::sync::mpsc;
+use tokio_util::codec::FramedRead;
+use crate::codec::{WebSocketCodec, WebSocketFrame, Role, Assembler, Assembled, header, close_payload, parse_close, decode_message, fragments};
+
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    name              : String,
+    addresses         : Vec<String>,
+    max_frame_size    : u64, // biggest single WebSocket frame a client may send, in payload bytes
+    max_message_size  : usize, // biggest message once continuation frames are put back together
+    handshake_timeout : Duration, // from TCP accept to the 101 (or the manifest) going out
+    max_request_size  : u64, // the upgrade request's head: request line and headers, in bytes
+    max_headers       : usize,
+    idle_timeout      : Option<Duration>, // drop clients that send nothing for this long
+    ping_interval     : Option<Duration>, // how often to ping each client, to keep the connection warm and measure round trips
+    pong_timeout      : Duration, // a client that hasn't answered a ping in this long is dead
+    send_queue        : usize, // messages a client's senders can have waiting on its writer task before send() waits too
+    fragment_size     : Option<usize>, // outgoing messages bigger than this go out in pieces, so control frames can get a word in between
+    json              : bool // whether clients may send JSON text frames instead of binary ones
+}
+
+
+impl ServerConfig {
+    pub fn new(name : impl Into<String>) -> Self { // no addresses yet: call bind at least once
+        Self {
+            name              : name.into(),
+            addresses         : vec![],
+            max_frame_size    : 1 << 20,
+            max_message_size  : 4 << 20,
+            handshake_timeout : Duration::from_secs(10),
+            max_request_size  : 8 << 10,
+            max_headers       : 64,
+            idle_timeout      : None,
+            ping_interval     : Some(Duration::from_secs(30)),
+            pong_timeout      : Duration::from_secs(10),
+            send_queue        : 64,
+            fragment_size     : Some(64 << 10),
+            json              : false
+        }
+    }
 
+    pub fn bind(mut self, address : impl Into<String>) -> Self { // "0.0.0.0:8080", "[::1]:9000", "localhost:0"... call it again to listen on several
+        self.addresses.push(address.into());
+        self
+    }
 
-const PAYLOAD_SIZE_CAP : u64 = 128; // extend to a few mb later, this is very low for testing
+    pub fn max_frame_size(mut self, bytes : u64) -> Self {
+        self.max_frame_size = bytes;
+        self
+    }
+
+    pub fn max_message_size(mut self, bytes : usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
 
+    pub fn handshake_timeout(mut self, timeout : Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    pub fn max_request_size(mut self, bytes : u64) -> Self {
+        self.max_request_size = bytes;
+        self
+    }
+
+    pub fn max_headers(mut self, count : usize) -> Self {
+        self.max_headers = count;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout : Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn ping_interval(mut self, interval : Option<Duration>) -> Self { // None turns pinging (and so dead connection detection) off
+        self.ping_interval = interval;
+        self
+    }
 
-pub struct WebSocketServer {
-    listener : TcpListener,
-    futures  : JoinSet<Option<WebSocketClientStream>>,
-    name     : String
+    pub fn pong_timeout(mut self, timeout : Duration) -> Self {
+        self.pong_timeout = timeout;
+        self
+    }
+
+    pub fn send_queue(mut self, messages : usize) -> Self {
+        self.send_queue = messages.max(1);
+        self
+    }
+
+    pub fn fragment_size(mut self, bytes : Option<usize>) -> Self { // None sends every message as a single frame, however big
+        self.fragment_size = bytes.map(|bytes| bytes.max(1));
+        self
+    }
+
+    pub fn json(mut self, json : bool) -> Self { // let clients send {"op":...,"args":[...]} text frames as well as binary ones. off by default
+        self.json = json;
+        self
+    }
+}
+
+
+pub struct WebSocketServer<InProtocol, OutProtocol> {
+    listeners : Vec<TcpListener>,
+    futures   : JoinSet<ServerEvent<InProtocol, OutProtocol>>,
+    config    : Arc<ServerConfig>
+}
+
+
+pub struct WebSocketClientStream<InProtocol, OutProtocol> { // both halves of a client; split() it to read in one task and send from others
+    pub path : String,
+    pub peer : SocketAddr,
+    reader   : ClientReader<InProtocol>,
+    sender   : ClientSender<OutProtocol>
 }
 
 
-pub struct WebSocketClientStream {
-    rx       : BufReader<OwnedReadHalf>,
-    tx       : OwnedWriteHalf,
+pub struct ClientReader<InProtocol> { // also a Stream of Result<InProtocol, Disconnect>, which ends after the Disconnect
     pub path : String,
-    closed   : bool
+    pub peer : SocketAddr,
+    state    : Option<ReaderState>, // away inside `reading` while a read is in flight
+    reading  : Option<Reading<InProtocol>>, // kept across polls, so a dropped read() (or a Stream poll) never loses half a frame
+    rtt      : Option<Duration>,
+    control  : mpsc::Sender<Control>, // our own handle on the writer task, for closing while the state is away
+    closing  : Option<Reservation<Control>>, // a place for our Close in the control queue, while we wait for one
+    closed   : bool, // our Close is queued (or the connection is already over)
+    ended    : bool // the Disconnect has been handed out
+}
+
+
+struct ReaderState {
+    rx        : FramedRead<OwnedReadHalf, WebSocketCodec>,
+    assembler : Assembler,
+    config    : Arc<ServerConfig>,
+    pings     : Keepalive,
+    control   : mpsc::Sender<Control> // pongs, pings and the Close go to the writer task through here, ahead of any queued messages
+}
+
+
+type Reading<InProtocol> = Pin<Box<dyn Future<Output = (ReaderState, Result<InProtocol, Disconnect>)> + Send + Sync>>;
+
+
+type Reservation<T> = Pin<Box<dyn Future<Output = Result<mpsc::OwnedPermit<T>, mpsc::error::SendError<()>>> + Send + Sync>>;
+
+
+pub struct ClientSender<OutProtocol> { // cheap to clone and hand to any task; every clone feeds the same writer task. also a Sink of OutProtocol
+    pub peer  : SocketAddr,
+    messages  : mpsc::Sender<(u8, Vec<u8>)>, // (opcode, whole message)
+    reserving : Option<Reservation<(u8, Vec<u8>)>>, // the Sink side: waiting on room in the queue...
+    permit    : Option<mpsc::OwnedPermit<(u8, Vec<u8>)>>, // ...and the room, once poll_ready has it
+    protocol  : PhantomData<fn(OutProtocol)>
+}
+
+
+impl<OutProtocol> Clone for ClientSender<OutProtocol> { // by hand, because derive would want OutProtocol : Clone
+    fn clone(&self) -> Self {
+        Self { peer : self.peer, messages : self.messages.clone(), reserving : None, permit : None, protocol : PhantomData }
+    }
+}
+
+
+enum Control {
+    Frame (u8, Vec<u8>),
+    Close (Vec<u8>)
+}
+
+
+struct Writer { // the one task that owns the write half. messages are written whole (in fragments if they're big), with control frames slipped in between fragments
+    tx            : OwnedWriteHalf,
+    control       : mpsc::Receiver<Control>,
+    messages      : mpsc::Receiver<(u8, Vec<u8>)>,
+    fragment_size : Option<usize>,
+    closing       : Option<Vec<u8>> // a Close waiting on the message being written, and whatever was queued before it
+}
+
+
+struct Keepalive { // server-side pings. they only go out while something is waiting in read(), which a client's read loop always is
+    next       : Instant, // when to send the next one
+    sent       : u64, // pings so far; each ping's payload is its number
+    unanswered : Option<(u64, Instant)>, // the ping we're waiting on a pong for, and when it went out
+    rtt        : Option<Duration> // smoothed round trip time, the way TCP does it: 7/8 of the old estimate plus 1/8 of the new sample
+}
+
+
+impl Keepalive {
+    fn new(config : &ServerConfig) -> Self {
+        Self { next : Instant::now() + config.ping_interval.unwrap_or_default(), sent : 0, unanswered : None, rtt : None }
+    }
+
+    fn pong(&mut self, payload : &[u8]) {
+        if let Some ((number, sent_at)) = self.unanswered {
+            if payload == number.to_be_bytes() { // pongs for anything else (or unsolicited ones) are allowed, and mean nothing
+                let sample = sent_at.elapsed();
+                self.rtt = Some(match self.rtt {
+                    Some (rtt) => (rtt * 7 + sample) / 8,
+                    None => sample
+                });
+                self.unanswered = None;
+            }
+        }
+    }
+
+    fn deadline(&self, config : &ServerConfig) -> Option<Instant> { // when read() next needs to wake up for a ping or a pong timeout
+        match (self.unanswered, config.ping_interval) {
+            (Some ((_, sent_at)), _) => Some(sent_at + config.pong_timeout),
+            (None, Some (_)) => Some(self.next),
+            (None, None) => None
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CloseCode (pub u16); // the status code in a Close frame. the common ones are below; 3000-4999 are free for applications
+
+
+impl CloseCode {
+    pub const NORMAL : CloseCode = CloseCode (1000);
+    pub const GOING_AWAY : CloseCode = CloseCode (1001);
+    pub const PROTOCOL_ERROR : CloseCode = CloseCode (1002);
+    pub const UNSUPPORTED_DATA : CloseCode = CloseCode (1003);
+    pub const INVALID_PAYLOAD : CloseCode = CloseCode (1007);
+    pub const POLICY_VIOLATION : CloseCode = CloseCode (1008);
+    pub const MESSAGE_TOO_BIG : CloseCode = CloseCode (1009);
+    pub const INTERNAL_ERROR : CloseCode = CloseCode (1011);
+
+    pub fn is_valid(&self) -> bool { // whether it may appear in a Close frame: the registered codes, minus the ones reserved for reporting locally, plus the 3000-4999 block
+        matches!(self.0, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
 }
 
 
 #[derive(Debug)]
-struct BadFrameError{}
+pub enum Disconnect { // why read() stopped giving out frames. the connection is done with after any of these
+    Closed { code : Option<CloseCode>, reason : String }, // the client closed it; code is None if its Close frame didn't give one
+    Poison (DynamicError), // a message that doesn't decode as the protocol (or its JSON form)
+    Oversize, // a frame or message over ServerConfig's limits
+    Protocol (String), // the client broke RFC 6455
+    Unsupported (String), // a kind of message we don't take, like text frames with JSON turned off
+    Io (std::io::Error), // including the connection dropping without a Close
+    Timeout // idle for longer than ServerConfig::idle_timeout, or stopped answering pings
+}
 
 
-impl std::error::Error for BadFrameError {
+impl std::error::Error for Disconnect {
     fn description(&self) -> &str {
-        "Bad WS frame received from a client!"
+        "A client disconnected!"
     }
 }
 
 
-impl std::fmt::Display for BadFrameError {
+impl std::fmt::Display for Disconnect {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Bad Frame")
+        match self {
+            Disconnect::Closed { code : Some (code), reason } => write!(f, "Closed by the client with code {}: {:?}", code.0, reason),
+            Disconnect::Closed { code : None, .. } => write!(f, "Closed by the client"),
+            Disconnect::Poison (e) => write!(f, "Poisoned: {}", e),
+            Disconnect::Oversize => write!(f, "Frame or message too big"),
+            Disconnect::Protocol (what) => write!(f, "Protocol violation: {}", what),
+            Disconnect::Unsupported (what) => write!(f, "Unsupported: {}", what),
+            Disconnect::Io (e) => write!(f, "IO error: {}", e),
+            Disconnect::Timeout => write!(f, "Timed out")
+        }
     }
 }
 
 
-enum IncomingWebSocketFrame {
-    DataFin (Vec<u8>),
-    DataUnfin (Vec<u8>),
-    Ping,
-    Pong,
-    Close
+impl From<std::io::Error> for Disconnect {
+    fn from(e : std::io::Error) -> Self {
+        Disconnect::Io (e)
+    }
 }
 
 
-impl IncomingWebSocketFrame {
-    async fn read_in(rx : &mut BufReader<OwnedReadHalf>) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut headp1buf : [u8; 2] = [0; 2];
-        let mut maskingkeybuf : [u8; 4] = [0; 4];
-        rx.read_exact(&mut headp1buf).await?;
-        let opcode = headp1buf[0] & 0b00001111;
-        let fin = headp1buf[0] & 0b10000000 != 0; // continuation stuff
-        if headp1buf[1] & 0b10000000 == 0 { // MASK == 0
-            return Err(Box::new(BadFrameError{})); // short circuit: this frame is bad, and probably the client should be dropped.
-        }
-        let mut payload_len : u64 = (headp1buf[1] & 0b01111111) as u64;
-        if payload_len == 126 {
-            let mut payload_ext_buf : [u8; 2] = [0; 2];
-            rx.read_exact(&mut payload_ext_buf).await?;
-            payload_len = u16::from_be_bytes(payload_ext_buf) as u64; // big endian is universally network order, so this should work fine
-        }
-        else if payload_len == 127 {
-            let mut payload_ext_buf : [u8; 8] = [0; 8];
-            rx.read_exact(&mut payload_ext_buf).await?;
-            payload_len = u64::from_be_bytes(payload_ext_buf);
-        }
-        rx.read_exact(&mut maskingkeybuf).await?; // it's guaranteed that the next 4 bytes is the masking key because this would have already failed if it weren't.
-        if payload_len > PAYLOAD_SIZE_CAP {
-            return Err(Box::new(BadFrameError{})); // todo: more specific error stuff
-        }
-        let mut payloadbuf = vec![0; payload_len as usize];
-        rx.read_exact(&mut payloadbuf.as_mut()).await?;
-        for i in 0..payloadbuf.len() {
-            payloadbuf[i] = payloadbuf[i] ^ maskingkeybuf[i % 4];
-        }
-        if opcode == 0x9 {
-            Ok(Ping)
+impl From<EncodeError> for Disconnect { // for the Sink and codec sides, which can only fail with a Disconnect. nothing was sent, and the connection is fine
+    fn from(e : EncodeError) -> Self {
+        Disconnect::Io (std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    }
+}
+
+
+impl Disconnect {
+    fn close_code(&self) -> Option<CloseCode> { // what we tell the client on our way out, when it's us hanging up on it
+        match self {
+            Disconnect::Poison (_) => Some(CloseCode::INVALID_PAYLOAD),
+            Disconnect::Oversize => Some(CloseCode::MESSAGE_TOO_BIG),
+            Disconnect::Protocol (_) => Some(CloseCode::PROTOCOL_ERROR),
+            Disconnect::Unsupported (_) => Some(CloseCode::UNSUPPORTED_DATA),
+            Disconnect::Closed { .. } | Disconnect::Io (_) | Disconnect::Timeout => None // already closed, or not listening
         }
-        else if opcode == 0xA {
-            Ok(Pong)
+    }
+}
+
+
+async fn write_frame(tx : &mut OwnedWriteHalf, opcode : u8, fin : bool, data : &[u8]) -> std::io::Result<()> { // one frame, server to client. not through WebSocketCodec, so big payloads aren't copied into a buffer first
+    tx.write_all(&header(opcode, fin, data.len(), None)).await?; // MASK always unset, this is outgoing
+    tx.write_all(data).await?;
+    Ok(())
+}
+
+
+impl Writer {
+    async fn run(mut self) {
+        let _ = self.serve().await; // a failed write means the connection is gone; dropping the receivers tells every sender so
+        let _ = self.tx.shutdown().await;
+    }
+
+    async fn serve(&mut self) -> std::io::Result<()> {
+        loop {
+            if let Some (payload) = self.closing.take() {
+                while let Ok ((opcode, data)) = self.messages.try_recv() { // sent before the close was asked for, so they still go out
+                    self.message(opcode, &data).await?;
+                }
+                return write_frame(&mut self.tx, 0x8, true, &payload).await; // after a Close, nothing else may go out
+            }
+            select! {
+                biased;
+                Some (control) = self.control.recv() => self.control(control).await?,
+                Some ((opcode, data)) = self.messages.recv() => self.message(opcode, &data).await?,
+                else => return Ok(()) // the reader and every sender are gone
+            }
         }
-        else if opcode == 0x2 || opcode == 0x0 {
-            if fin {
-                Ok(DataFin (payloadbuf))
+    }
+
+    async fn control(&mut self, control : Control) -> std::io::Result<()> {
+        match control {
+            Control::Frame (opcode, payload) => write_frame(&mut self.tx, opcode, true, &payload).await,
+            Control::Close (payload) => {
+                self.closing = Some(payload);
+                Ok(())
             }
-            else {
-                Ok(DataUnfin (payloadbuf))
+        }
+    }
+
+    async fn message(&mut self, opcode : u8, data : &[u8]) -> std::io::Result<()> { // a whole message, cut into continuation frames if it's over the fragment size
+        for (first, fin, fragment) in fragments(data, self.fragment_size) {
+            if !first {
+                while let Ok (control) = self.control.try_recv() { // a pong doesn't have to wait for the rest of a huge snapshot
+                    self.control(control).await?;
+                }
             }
+            write_frame(&mut self.tx, if first { opcode } else { 0x0 }, fin, fragment).await?;
         }
-        else if opcode == 0x8 {
-            Ok(Close)
+        Ok(())
+    }
+}
+
+
+impl<InProtocol : 'static + ProtocolFrame + Send, OutProtocol : ProtocolFrame> WebSocketClientStream<InProtocol, OutProtocol> {
+    fn new(rx : BufReader<OwnedReadHalf>, tx : OwnedWriteHalf, path : String, peer : SocketAddr, config : Arc<ServerConfig>) -> Self { // starts the writer task
+        let (control, control_rx) = mpsc::channel(32);
+        let (messages, messages_rx) = mpsc::channel(config.send_queue);
+        let buffered = rx.buffer().to_vec(); // anything the client sent straight after its request
+        let mut rx = FramedRead::new(rx.into_inner(), WebSocketCodec::new(Role::Server).max_frame_size(config.max_frame_size));
+        rx.read_buffer_mut().extend_from_slice(&buffered);
+        tokio::spawn(Writer { tx, control : control_rx, messages : messages_rx, fragment_size : config.fragment_size, closing : None }.run());
+        Self {
+            path   : path.clone(),
+            peer,
+            reader : ClientReader { path, peer, state : Some(ReaderState { rx, assembler : Assembler::new(config.max_message_size), pings : Keepalive::new(&config), config, control : control.clone() }), reading : None, rtt : None, control, closing : None, closed : false, ended : false },
+            sender : ClientSender { peer, messages, reserving : None, permit : None, protocol : PhantomData }
         }
-        else {
-            Err(Box::new(BadFrameError{})) // text ain't supported
+    }
+
+    pub fn split(self) -> (ClientReader<InProtocol>, ClientSender<OutProtocol>) {
+        (self.reader, self.sender)
+    }
+
+    pub async fn read(&mut self) -> Result<InProtocol, Disconnect> {
+        self.reader.read().await
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.reader.rtt()
+    }
+
+    pub async fn route<Handler>(&mut self, handler : &mut Handler) -> Disconnect where InProtocol : Dispatch<Handler> {
+        self.reader.route(handler).await
+    }
+
+    pub async fn send(&mut self, frame : OutProtocol) -> Result<(), Box<dyn std::error::Error>> {
+        self.sender.send(frame).await
+    }
+
+    pub async fn send_json(&mut self, frame : OutProtocol) -> Result<(), Box<dyn std::error::Error>> {
+        self.sender.send_json(frame).await
+    }
+
+    pub async fn close(&mut self, code : CloseCode, reason : &str) {
+        self.reader.close(code, reason).await
+    }
+
+    pub async fn shutdown(&mut self) {
+        self.reader.shutdown().await
+    }
+}
+
+
+impl<OutProtocol : ProtocolFrame> ClientSender<OutProtocol> {
+    pub async fn send(&self, frame : OutProtocol) -> Result<(), Box<dyn std::error::Error>> { // waits if the queue is full. a frame that can't be encoded is an EncodeError, and never queued
+        frame.encodable()?;
+        self.messages.send((0x2, frame.encode())).await.map_err(|_| gone())?;
+        Ok(())
+    }
+
+    pub async fn send_json(&self, frame : OutProtocol) -> Result<(), Box<dyn std::error::Error>> { // same frame, as a JSON text frame, for clients that speak JSON
+        frame.encodable()?;
+        self.messages.send((0x1, frame.to_json()?.into_bytes())).await.map_err(|_| gone())?;
+        Ok(())
+    }
+
+    pub fn try_send(&self, frame : OutProtocol) -> Result<(), Box<dyn std::error::Error>> { // for code that can't wait: fails with WouldBlock when the queue is full
+        frame.encodable()?;
+        self.messages.try_send((0x2, frame.encode())).map_err(|e| match e {
+            mpsc::error::TrySendError::Full (_) => std::io::Error::new(std::io::ErrorKind::WouldBlock, "the client's send queue is full"),
+            mpsc::error::TrySendError::Closed (_) => gone()
+        })?;
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.messages.is_closed()
+    }
+}
+
+
+impl<OutProtocol : ProtocolFrame> Sink<OutProtocol> for ClientSender<OutProtocol> {
+    type Error = Disconnect;
+
+    fn poll_ready(self : Pin<&mut Self>, cx : &mut Context<'_>) -> Poll<Result<(), Disconnect>> { // pending while the queue is full, which is the backpressure
+        let this = self.get_mut();
+        if this.permit.is_none() {
+            let messages = &this.messages;
+            let reserving = this.reserving.get_or_insert_with(|| Box::pin(messages.clone().reserve_owned()));
+            let permit = ready!(reserving.as_mut().poll(cx));
+            this.reserving = None;
+            this.permit = Some(permit.map_err(|_| Disconnect::Io (gone()))?);
         }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self : Pin<&mut Self>, frame : OutProtocol) -> Result<(), Disconnect> {
+        frame.encodable()?; // before the permit goes, so it's still there for the next frame
+        let permit = self.get_mut().permit.take().ok_or_else(|| Disconnect::Io (std::io::Error::other("start_send without poll_ready")))?;
+        permit.send((0x2, frame.encode()));
+        Ok(())
+    }
+
+    fn poll_flush(self : Pin<&mut Self>, _cx : &mut Context<'_>) -> Poll<Result<(), Disconnect>> { // queued is as flushed as it gets: the writer task owns it now, and it goes out in order
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self : Pin<&mut Self>, _cx : &mut Context<'_>) -> Poll<Result<(), Disconnect>> { // just this handle is done. the connection stays up for the other clones; closing it is the reader's (or the whole stream's) job
+        let this = self.get_mut();
+        this.reserving = None;
+        this.permit = None;
+        Poll::Ready(Ok(()))
     }
 }
 
 
-use IncomingWebSocketFrame::*;
+impl<InProtocol : 'static + ProtocolFrame + Send, OutProtocol> Stream for WebSocketClientStream<InProtocol, OutProtocol> {
+    type Item = Result<InProtocol, Disconnect>;
+
+    fn poll_next(mut self : Pin<&mut Self>, cx : &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.reader).poll_next(cx)
+    }
+}
+
+
+impl<InProtocol : 'static + ProtocolFrame + Send, OutProtocol> FusedStream for WebSocketClientStream<InProtocol, OutProtocol> {
+    fn is_terminated(&self) -> bool {
+        self.reader.ended
+    }
+}
+
+
+impl<InProtocol, OutProtocol : ProtocolFrame> Sink<OutProtocol> for WebSocketClientStream<InProtocol, OutProtocol> {
+    type Error = Disconnect;
+
+    fn poll_ready(mut self : Pin<&mut Self>, cx : &mut Context<'_>) -> Poll<Result<(), Disconnect>> {
+        Pin::new(&mut self.sender).poll_ready(cx)
+    }
 
+    fn start_send(mut self : Pin<&mut Self>, frame : OutProtocol) -> Result<(), Disconnect> {
+        Pin::new(&mut self.sender).start_send(frame)
+    }
+
+    fn poll_flush(mut self : Pin<&mut Self>, cx : &mut Context<'_>) -> Poll<Result<(), Disconnect>> {
+        Pin::new(&mut self.sender).poll_flush(cx)
+    }
+
+    fn poll_close(mut self : Pin<&mut Self>, cx : &mut Context<'_>) -> Poll<Result<(), Disconnect>> { // a normal Close, after everything already queued. keep polling the stream side for the client's answer, which ends it
+        self.reader.poll_send_close(cx, CloseCode::NORMAL, "")
+    }
+}
+
+
+fn gone() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::BrokenPipe, "the connection is closed")
+}
+
+
+impl<InProtocol : 'static + ProtocolFrame + Send> ClientReader<InProtocol> {
+    pub fn poll_read(&mut self, cx : &mut Context<'_>) -> Poll<Result<InProtocol, Disconnect>> {
+        let reading = match &mut self.reading {
+            Some (reading) => reading,
+            None => {
+                let mut state = self.state.take().expect("the reader's state is only away while a read is in flight");
+                self.reading.insert(Box::pin(async move {
+                    let result = state.read().await;
+                    (state, result)
+                }))
+            }
+        };
+        let (state, result) = ready!(reading.as_mut().poll(cx));
+        self.reading = None;
+        self.rtt = state.pings.rtt;
+        self.state = Some(state);
+        if result.is_err() { // whatever it was, the connection is over: any Close that was due has gone out
+            self.ended = true;
+            self.closed = true;
+        }
+        Poll::Ready(result)
+    }
+
+    pub async fn read(&mut self) -> Result<InProtocol, Disconnect> { // cancel safe: a read that's dropped partway picks up where it was next time
+        std::future::poll_fn(|cx| self.poll_read(cx)).await
+    }
+
+    pub fn rtt(&self) -> Option<Duration> { // smoothed round trip time from pings; None until the first pong comes back
+        self.rtt
+    }
 
-impl WebSocketClientStream {
-    pub async fn read<Protocol : ProtocolFrame>(&mut self) -> Option<Protocol> {
-        let mut final_data : Vec<u8> = vec![];
+    pub async fn route<Handler>(&mut self, handler : &mut Handler) -> Disconnect where InProtocol : Dispatch<Handler> {
+        // pump frames into the handler until the client goes away (or poisons, which is the same thing as far as we're concerned)
         loop {
-            let frame = IncomingWebSocketFrame::read_in(&mut self.rx).await.ok()?; // if the reader hits unexpected EOF, this will return None.
-            match frame {
-                Ping => {}
-                Pong => {}
-                Close => {
-                    self.closed = true;
-                    self.send_close().await; // complying websocket clients will close the actual TCP stream after receiving our return close message, so this can be safely ignored - the connection will be dropped all right and proper soon.
-                }
-                DataFin (mut data) => {
-                    final_data.append(&mut data);
-                    break;
-                }
-                DataUnfin (mut data) => {
-                    final_data.append(&mut data);
-                }
+            match self.read().await {
+                Ok (frame) => frame.dispatch(handler).await,
+                Err (disconnect) => return disconnect
             }
         }
-        match ProtocolFrame::decode(final_data.into()) {
-            Ok (result) => Some (result),
-            Err (_) => {
-                println!("Decode error! A client is poisoning!");
-                None
+    }
+
+    pub async fn close(&mut self, code : CloseCode, reason : &str) { // the reason is cut to fit in a control frame (123 bytes)
+        if !self.closed { // if it's already closed, do nothing.
+            let _ = std::future::poll_fn(|cx| self.poll_send_close(cx, code, reason)).await;
+            for _ in 0..10 { // read out 10 messages MAX after sending close before leaving; this is just giving the client a chance to handle the close frame if other data is being sent.
+                if self.read().await.is_err() {
+                    break; // their Close, or the read failed: therefore, the connection must be closed, if not properly.
+                }
             }
         }
     }
 
-    pub async fn send<Protocol : ProtocolFrame>(&mut self, frame : Protocol) -> Result<(), Box<dyn std::error::Error>> {
-        let data = frame.encode();
-        let ext_len = data.len() > 125;
-        let ext_len_2 = data.len() > 65535;
-        let mut headerbuf : Vec<u8> = vec![0; if ext_len_2 { 20 } else if ext_len { 4 } else { 2 }];
-        headerbuf[0] = 0b10000010; // FIN set, RSV ignored (as they should be), opcode 0x2
-        headerbuf[1] = if ext_len_2 { 127 } else if ext_len { 126 } else { data.len() as u8 }; // MASK always unset, this is outgoing
-        if ext_len_2 {
-            let bytes = (data.len() as u64).to_be_bytes();
-            for i in 0..8 {
-                headerbuf[2 + i] = bytes[i];
+    pub async fn shutdown(&mut self) {
+        self.close(CloseCode::NORMAL, "").await;
+    }
+}
+
+
+impl<InProtocol> ClientReader<InProtocol> {
+    fn poll_send_close(&mut self, cx : &mut Context<'_>, code : CloseCode, reason : &str) -> Poll<Result<(), Disconnect>> { // pending while the control queue is full: a dropped Close would leave the connection open for good
+        if !self.closed {
+            let control = &self.control;
+            let closing = self.closing.get_or_insert_with(|| Box::pin(control.clone().reserve_owned()));
+            let permit = ready!(closing.as_mut().poll(cx));
+            self.closing = None;
+            self.closed = true;
+            if let Ok (permit) = permit { // think about it - if there's no writer task to take it, the connection is already closed, so we should...
+                permit.send(Control::Close (close_payload(code, reason)));
             }
+            /****** do nothing ******/
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+impl<InProtocol : 'static + ProtocolFrame + Send> Stream for ClientReader<InProtocol> {
+    type Item = Result<InProtocol, Disconnect>;
+
+    fn poll_next(self : Pin<&mut Self>, cx : &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.ended {
+            return Poll::Ready(None);
         }
-        else if ext_len {
-            let bytes = (data.len() as u64).to_be_bytes();
-            for i in 0..4 {
-                headerbuf[2 + i] = bytes[i];
+        this.poll_read(cx).map(Some)
+    }
+}
+
+
+impl<InProtocol : 'static + ProtocolFrame + Send> FusedStream for ClientReader<InProtocol> {
+    fn is_terminated(&self) -> bool {
+        self.ended
+    }
+}
+
+
+impl ReaderState {
+    async fn read<InProtocol : ProtocolFrame>(&mut self) -> Result<InProtocol, Disconnect> {
+        let result = self.read_message().await;
+        if let Err (disconnect) = &result {
+            if let Some (code) = disconnect.close_code() {
+                self.send_close(code, &disconnect.to_string());
             }
         }
-        self.tx.write(headerbuf.as_slice()).await?;
-        self.tx.write(data.as_slice()).await?;
-        Ok(())
+        result
     }
 
-    async fn send_close(&mut self) {
-        let _ = self.tx.write(&[0x8, 0x0]).await; // think about it - if it fails to send, that means the connection is already closed, so we should...
-        /****** do nothing ******/
+    async fn read_message<InProtocol : ProtocolFrame>(&mut self) -> Result<InProtocol, Disconnect> {
+        loop {
+            let frame = self.next_frame().await?;
+            match self.assembler.push(frame)? { // control frames can come in between the fragments of a message, and are dealt with on the spot
+                Assembled::Partial => {}
+                Assembled::Message (text, data) => return decode_message(text, data, self.config.json),
+                Assembled::Control (WebSocketFrame::Ping (payload)) => {
+                    let _ = self.control.try_send(Control::Frame (0xA, payload)); // if the writer is that backed up, the client isn't reading anyway
+                }
+                Assembled::Control (WebSocketFrame::Pong (payload)) => self.pings.pong(&payload),
+                Assembled::Control (frame) => { // Close; the assembler hands back nothing else
+                    let (code, reason) = parse_close(frame.payload())?;
+                    self.send_close(code.unwrap_or(CloseCode::NORMAL), ""); // an echo of their code. complying websocket clients will close the actual TCP stream after receiving our return close message, so this can be safely ignored - the connection will be dropped all right and proper soon.
+                    return Err(Disconnect::Closed { code, reason });
+                }
+            }
+        }
     }
 
-    pub async fn shutdown(&mut self) {
-        if !self.closed { // if it's already closed, do nothing.
-            self.send_close().await;
-            let _ = self.tx.shutdown().await;
-            for _ in 0..10 { // read out 10 frames MAX after sending close before leaving; this is just giving the client a chance to handle the close frame if other data is being sent.
-                match IncomingWebSocketFrame::read_in(&mut self.rx).await {
-                    Err(_) => {
-                        break; // the read failed: therefore, the connection must be closed, if not properly.
+    async fn next_frame(&mut self) -> Result<WebSocketFrame, Disconnect> { // the next frame, sending pings while we wait
+        let config = self.config.clone();
+        let idle_deadline = config.idle_timeout.map(|timeout| Instant::now() + timeout);
+        let read = self.rx.next();
+        tokio::pin!(read);
+        loop {
+            let ping_deadline = self.pings.deadline(&config);
+            let wake = match (idle_deadline, ping_deadline) {
+                (Some (a), Some (b)) => Some(a.min(b)),
+                (a, b) => a.or(b)
+            };
+            select! {
+                frame = &mut read => return frame.unwrap_or_else(|| Err(Disconnect::Io (std::io::ErrorKind::UnexpectedEof.into()))),
+                _ = tokio::time::sleep_until(wake.unwrap_or_else(Instant::now)), if wake.is_some() => {
+                    let now = Instant::now();
+                    if idle_deadline.is_some_and(|deadline| now >= deadline) {
+                        return Err(Disconnect::Timeout); // a quiet client is as good as gone
+                    }
+                    if let Some ((_, sent_at)) = self.pings.unanswered {
+                        if now >= sent_at + config.pong_timeout {
+                            return Err(Disconnect::Timeout); // and so is one that stopped answering pings
+                        }
                     }
-                    Ok(Close) => {
-                        break;
+                    else if ping_deadline.is_some_and(|deadline| now >= deadline) {
+                        let number = self.pings.sent;
+                        self.pings.sent += 1;
+                        self.pings.unanswered = Some((number, now));
+                        self.pings.next = now + config.ping_interval.unwrap_or_default();
+                        let _ = self.control.try_send(Control::Frame (0x9, number.to_be_bytes().to_vec())); // a dropped ping goes unanswered, and times out like any other
                     }
-                    _ => {} // throw out
                 }
             }
         }
     }
+
+    fn send_close(&mut self, code : CloseCode, reason : &str) { // the writer task lets only the first Close through, and shuts the TCP write side after it
+        let _ = self.control.try_send(Control::Close (close_payload(code, reason)));
+    }
+}
+
+
+#[derive(Debug)]
+pub enum HandshakeError { // why a connection never made it to a WebSocketClientStream
+    Io (std::io::Error),
+    Timeout, // the whole request didn't arrive within ServerConfig::handshake_timeout
+    TooLarge, // the request head went over ServerConfig::max_request_size or max_headers
+    Malformed (String), // not HTTP as RFC 9112 knows it
+    MethodNotAllowed (String),
+    UnsupportedHttpVersion (String),
+    NotAnUpgrade, // a plain HTTP request, which gets the teapot
+    UnsupportedWebSocketVersion (Option<String>),
+    BadKey // Sec-WebSocket-Key missing, or not 16 bytes of base64
 }
 
 
-fn count_up_till<T : PartialEq>(vec : &Vec<T>, thing : T) -> Option<usize> {
-    let mut ret : usize = 0;
-    while vec[ret] != thing {
-        ret += 1;
-        if ret == vec.len() {
-            return None;
+impl std::error::Error for HandshakeError {
+    fn description(&self) -> &str {
+        "A client failed the WebSocket handshake!"
+    }
+}
+
+
+impl std::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HandshakeError::Io (e) => write!(f, "IO error: {}", e),
+            HandshakeError::Timeout => write!(f, "Handshake timed out"),
+            HandshakeError::TooLarge => write!(f, "Request head too large"),
+            HandshakeError::Malformed (what) => write!(f, "Malformed request: {}", what),
+            HandshakeError::MethodNotAllowed (method) => write!(f, "Method not allowed: {}", method),
+            HandshakeError::UnsupportedHttpVersion (version) => write!(f, "Unsupported HTTP version: {}", version),
+            HandshakeError::NotAnUpgrade => write!(f, "Not a WebSocket upgrade request"),
+            HandshakeError::UnsupportedWebSocketVersion (Some (version)) => write!(f, "Unsupported WebSocket version: {}", version),
+            HandshakeError::UnsupportedWebSocketVersion (None) => write!(f, "No WebSocket version"),
+            HandshakeError::BadKey => write!(f, "Missing or bad Sec-WebSocket-Key")
         }
     }
-    Some(ret)
 }
 
 
-impl WebSocketServer {
-    pub async fn new(port : u16, name : String) -> Self {
-        Self {
-            listener : TcpListener::bind(format!("0.0.0.0:{}", port)).await.unwrap(),
-            futures  : JoinSet::new(),
-            name
+impl From<std::io::Error> for HandshakeError {
+    fn from(e : std::io::Error) -> Self {
+        HandshakeError::Io (e)
+    }
+}
+
+
+pub enum ServerEvent<InProtocol, OutProtocol> { // everything that can come of a new connection
+    Connected (Box<WebSocketClientStream<InProtocol, OutProtocol>>), // boxed: a stream is much bigger than the other two
+    ManifestServed (SocketAddr), // someone fetched /manifest; the connection is closed afterwards
+    HandshakeFailed (SocketAddr, HandshakeError)
+}
+
+
+struct Request { // the head of an HTTP request: the bits a WebSocket upgrade cares about
+    method  : String,
+    target  : String,
+    version : String,
+    headers : HashMap<String, String> // lowercased names; repeats are joined with ", ", like RFC 9110 says lists can be
+}
+
+
+impl Request {
+    fn header(&self, name : &str) -> Option<&str> {
+        self.headers.get(name).map(|value| value.as_str())
+    }
+
+    fn has_token(&self, name : &str, token : &str) -> bool { // for comma-separated headers like Connection: keep-alive, Upgrade
+        self.header(name).is_some_and(|value| value.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    }
+}
+
+
+async fn read_line(rx : &mut BufReader<OwnedReadHalf>, budget : &mut u64) -> Result<String, HandshakeError> { // one CRLF (or bare LF) line, never reading past the budget
+    let mut line = vec![];
+    (&mut *rx).take(*budget).read_until(b'\n', &mut line).await?;
+    *budget -= line.len() as u64;
+    if line.last() != Some(&b'\n') {
+        return Err(if *budget == 0 { HandshakeError::TooLarge } else { HandshakeError::Malformed ("connection closed mid-request".to_string()) });
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| HandshakeError::Malformed ("request head isn't UTF-8".to_string()))
+}
+
+
+fn is_token(name : &str) -> bool { // RFC 9110 tchar
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+
+async fn read_request(rx : &mut BufReader<OwnedReadHalf>, config : &ServerConfig) -> Result<Request, HandshakeError> {
+    let mut budget = config.max_request_size;
+    let line = read_line(rx, &mut budget).await?;
+    let mut parts = line.split(' ');
+    let (Some (method), Some (target), Some (version), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(HandshakeError::Malformed (format!("bad request line {:?}", line)));
+    };
+    if method.is_empty() || target.is_empty() {
+        return Err(HandshakeError::Malformed (format!("bad request line {:?}", line)));
+    }
+    let mut request = Request { method : method.to_string(), target : target.to_string(), version : version.to_string(), headers : HashMap::new() };
+    let mut count = 0;
+    loop {
+        let line = read_line(rx, &mut budget).await?;
+        if line.is_empty() {
+            break;
+        }
+        count += 1;
+        if count > config.max_headers {
+            return Err(HandshakeError::TooLarge);
+        }
+        let Some ((name, value)) = line.split_once(':') else {
+            return Err(HandshakeError::Malformed (format!("header without a colon {:?}", line)));
+        };
+        if !is_token(name) { // this also catches whitespace before the colon, which RFC 9112 says to reject
+            return Err(HandshakeError::Malformed (format!("bad header name {:?}", name)));
+        }
+        let value = value.trim_matches([' ', '\t']);
+        request.headers.entry(name.to_lowercase()).and_modify(|joined| { *joined += ", "; *joined += value; }).or_insert_with(|| value.to_string());
+    }
+    Ok(request)
+}
+
+
+fn error_response(error : &HandshakeError) -> Option<&'static [u8]> { // what to tell the client on the way out, if the connection can still hear it
+    match error {
+        HandshakeError::Io (_) | HandshakeError::Timeout => None,
+        HandshakeError::TooLarge => Some(b"HTTP/1.1 431 Request Header Fields Too Large\r\nConnection: close\r\n\r\n"),
+        HandshakeError::Malformed (_) => Some(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\nBad Request: that wasn't HTTP.\n"),
+        HandshakeError::MethodNotAllowed (_) => Some(b"HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\nConnection: close\r\n\r\nWebSocket upgrades (and the manifest) are GET requests.\n"),
+        HandshakeError::UnsupportedHttpVersion (_) => Some(b"HTTP/1.1 400 Bad Request\r\n\r\nBad Request: This server is not equipped for http versions besides 1.1.\n"),
+        HandshakeError::NotAnUpgrade => Some(b"HTTP/1.1 418 I'm A Teapot\r\n\r\nThis server is not equipped for normal HTTP transactions; all it understands is websocket connections. Please set your connection header to upgrade and your upgrade header to websocket. Also set your WebSocket security headers. Thank you.\n"),
+        HandshakeError::UnsupportedWebSocketVersion (_) => Some(b"HTTP/1.1 426 Upgrade Required\r\nSec-WebSocket-Version: 13\r\n\r\nTruly this is an achievement, you have managed to fail at setting your websocket version.\n"),
+        HandshakeError::BadKey => Some(b"HTTP/1.1 400 I Hate Meddling Kids Like You\r\n\r\nIn fact if you don't have a proper sec-websocket-key I will be <i>VERY CROSS!</i>.\n")
+    }
+}
+
+
+async fn next_socket(listeners : &[TcpListener]) -> std::io::Result<(TcpStream, SocketAddr)> { // whichever listener has a connection waiting first
+    std::future::poll_fn(|cx| {
+        for listener in listeners {
+            if let Poll::Ready (result) = listener.poll_accept(cx) {
+                return Poll::Ready(result);
+            }
+        }
+        Poll::Pending
+    }).await
+}
+
+
+impl<InProtocol : 'static + ProtocolFrame + Send, OutProtocol : 'static + ProtocolFrame> WebSocketServer<InProtocol, OutProtocol> {
+    pub async fn new(config : ServerConfig) -> std::io::Result<Self> {
+        if config.addresses.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "the server config has no addresses to bind"));
         }
+        let mut listeners = vec![];
+        for address in &config.addresses {
+            let listener = TcpListener::bind(address.as_str()).await.map_err(|e| std::io::Error::new(e.kind(), format!("couldn't bind {}: {}", address, e)))?;
+            listeners.push(listener);
+        }
+        Ok(Self {
+            listeners,
+            futures : JoinSet::new(),
+            config  : Arc::new(config)
+        })
+    }
+
+    pub fn local_addrs(&self) -> Vec<SocketAddr> { // what the listeners actually got, which is the way to find out the port when binding port 0
+        self.listeners.iter().filter_map(|listener| listener.local_addr().ok()).collect()
     }
 
-    pub async fn accept<InProtocol : 'static + ProtocolFrame, OutProtocol : 'static + ProtocolFrame>(&mut self) -> WebSocketClientStream {
+    fn start_handshake(&mut self, socket : TcpStream, peer : SocketAddr) {
+        let config = self.config.clone();
+        self.futures.spawn(async move {
+            let _ = socket.set_nodelay(true); // this is meant for online games, like MMOSG. Nagle's algorithm will get in the way of proper performance. to compensate for the lack of Nagle, group together messages sanely.
+            let (rx, mut tx) = socket.into_split();
+            let mut rx = BufReader::new(rx);
+            let handshake = Self::handshake(&config, &mut rx, &mut tx);
+            let result = match tokio::time::timeout(config.handshake_timeout, handshake).await { // slowloris gets cut off here
+                Ok (result) => result,
+                Err (_) => Err(HandshakeError::Timeout)
+            };
+            match result {
+                Ok (Some (path)) => ServerEvent::Connected (Box::new(WebSocketClientStream::new(rx, tx, path, peer, config))),
+                Ok (None) => ServerEvent::ManifestServed (peer),
+                Err (error) => {
+                    if let Some (response) = error_response(&error) {
+                        let _ = tx.write_all(response).await;
+                    }
+                    ServerEvent::HandshakeFailed (peer, error)
+                }
+            }
+        });
+    }
+
+    pub async fn next_event(&mut self) -> ServerEvent<InProtocol, OutProtocol> { // like accept, but tells you about the connections that didn't work out too
         loop { // todo: handle this in a nicer way (the goal is never to self.futures.join_next() if self.futures is empty, because handling all those Nones can become quite expensive - 100% cpu utilization on at least one core)
-            if self.futures.len() > 0 {
+            if !self.futures.is_empty() {
                 select! {
-                    newclient = self.listener.accept() => {
+                    newclient = next_socket(&self.listeners) => {
                         match newclient {
-                            Ok ((socket, _)) => {
-                                self.futures.spawn(Self::handshake::<InProtocol, OutProtocol>(self.name.clone(), socket));
+                            Ok ((socket, peer)) => {
+                                self.start_handshake(socket, peer);
                             },
                             Err (_) => {
                                 println!("Socket accept failed. This is not critical.");
                             }
                         }
                     },
-                    websocket = self.futures.join_next() => {
-                        match websocket {
-                            Some (Ok(Some(websocket))) => {
-                                return websocket;
-                            }
-                            _ => {}
+                    event = self.futures.join_next() => {
+                        if let Some (Ok (event)) = event {
+                            return event;
                         }
                     }
                 }
             }
             else {
-                match self.listener.accept().await {
-                    Ok ((socket, _)) => {
-                        self.futures.spawn(Self::handshake::<InProtocol, OutProtocol>(self.name.clone(), socket));
+                match next_socket(&self.listeners).await {
+                    Ok ((socket, peer)) => {
+                        self.start_handshake(socket, peer);
                     },
                     Err (_) => {
                         println!("Socket accept failed. This is not critical.");
@@ -259,74 +937,41 @@ impl WebSocketServer {
         }
     }
 
-    async fn upgrade(mut headers : HashMap<String, String>, tx : OwnedWriteHalf, rx : BufReader<OwnedReadHalf>, uri : String) -> Option<WebSocketClientStream> {
-        if !headers.contains_key("connection") || !headers.contains_key("upgrade") || !headers["connection"].to_lowercase().contains("upgrade") || headers["upgrade"].to_lowercase() != "websocket" {
-            tx.try_write(b"HTTP/1.1 418 I'm A Teapot\r\n\r\nThis server is not equipped for normal HTTP transactions; all it understands is websocket connections. Please set your connection header to upgrade and your upgrade header to websocket. Also set your WebSocket security headers. Thank you.\n").unwrap();
-            println!("I'm a TEAPOT, PEOPLE!");
-            return None;
-        }
-        if match headers.get("sec-websocket-version") { Some(v) => { v != "13" } None => true} {
-            tx.try_write(b"HTTP/1.1 400 Get It Right, Goddamnit\r\n\r\nTruly this is an achievement, you have managed to fail at setting your websocket version.\nKill yourself.\n").unwrap();
-            println!("We have ourselves an idiot.");
-            return None;
-        }
-        if !headers.contains_key("sec-websocket-key") {
-            tx.try_write(b"HTTP/1.1 400 I Hate Meddling Kids Like You\r\n\r\nIn fact if you don't have a sec-websocket-key I will be <i>VERY CROSS!</i>. Get fucking BETTER, n00bsh1t!\n").unwrap();
-            println!("We have ourselves a really incompetent hacker.");
-            return None;
-        }
-        let keyconcated = headers.remove("sec-websocket-key").unwrap() + "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
-        let shaun = sha1_smol::Sha1::from(keyconcated).hexdigest();
-        let shaun_bytes = hex::decode(shaun).unwrap();
-        let b64sha1 = BASE64.encode(shaun_bytes);
-        tx.try_write(format!("HTTP/1.1 101 Upgrading\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {}\r\n\r\n", b64sha1).as_bytes()).unwrap();
-        Some(WebSocketClientStream { rx, tx, path : uri, closed : false })
-    }
-
-    async fn handshake<InProtocol : ProtocolFrame, OutProtocol : ProtocolFrame>(name : String, socket : TcpStream) -> Option<WebSocketClientStream> {
-        socket.set_nodelay(true).unwrap(); // this is meant for online games, like MMOSG. Nagle's algorithm will get in the way of proper performance. to compensate for the lack of Nagle, group together messages sanely.
-        let (rx, tx) = socket.into_split();
-        let mut rxbuf = BufReader::new(rx);
-        let mut method = Vec::<u8>::new();
-        let mut uri = Vec::<u8>::new();
-        let mut version = Vec::<u8>::new();
-        rxbuf.read_until(32, &mut method).await.unwrap();
-        rxbuf.read_until(32, &mut uri).await.unwrap();
-        rxbuf.read_until(10, &mut version).await.unwrap();
-        let method = String::from_utf8(method).unwrap().trim().to_string();
-        let uri = String::from_utf8(uri).unwrap().trim().to_string();
-        let version = String::from_utf8(version).unwrap().trim().to_string();
-        println!("Got [{}] request to [{}] with version [{}].", method, uri, version);
-        if version != "HTTP/1.1" {
-            tx.try_write(b"HTTP/1.1 400 Bad Request\r\n\r\nBad Request: This server is not equipped for http versions besides 1.1.\n").unwrap();
-            println!("Bad request header");
-            return None;
-        }
-        
-        let mut headers : HashMap<String, String> = HashMap::new();
-        let mut headbuf = Vec::<u8>::new();
+    pub async fn accept(&mut self) -> WebSocketClientStream<InProtocol, OutProtocol> {
         loop {
-            rxbuf.read_until(10, &mut headbuf).await.unwrap();
-            match count_up_till(&headbuf, 32) {
-                Some(colon) => {
-                    let hname = String::from_utf8(headbuf[0..colon - 1].to_vec()).unwrap().trim().to_string().to_lowercase();
-                    let hval = String::from_utf8(headbuf[colon..].to_vec()).unwrap().trim().to_string();
-                    headers.insert(hname, hval);
-                }
-                None => {
-                    break;
-                }
+            match self.next_event().await {
+                ServerEvent::Connected (client) => return *client,
+                ServerEvent::ManifestServed (peer) => println!("{} just wanted our manifest.", peer),
+                ServerEvent::HandshakeFailed (peer, error) => println!("Handshake with {} failed: {}", peer, error)
             }
-            headbuf.clear();
-        } // case ambiguity for compatibility
+        }
+    }
 
-        if uri == "/manifest" {
-            tx.try_write(format!("HTTP/1.1 200 Everything Is Ight, Cuh\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\n\r\n{{\"application_name\":\"{}\",\"incoming_protocol\":{},\"outgoing_protocol\":{}}}", name, InProtocol::manifest(), OutProtocol::manifest()).as_bytes()).unwrap();
-            println!("Client just wanted our manifest.");
-            return None; // kill the connection, the client will have to reconnect to get the websocket upgrade. TODO: fix this!
+    async fn handshake(config : &ServerConfig, rx : &mut BufReader<OwnedReadHalf>, tx : &mut OwnedWriteHalf) -> Result<Option<String>, HandshakeError> { // the path, for upgrades; None once the manifest is sent
+        let request = read_request(rx, config).await?;
+        if request.version != "HTTP/1.1" {
+            return Err(HandshakeError::UnsupportedHttpVersion (request.version));
+        }
+        if request.method != "GET" {
+            return Err(HandshakeError::MethodNotAllowed (request.method));
         }
-        else {
-            Self::upgrade(headers, tx, rxbuf, uri).await
+        if request.target == "/manifest" {
+            let manifest = ServerManifest::of::<InProtocol, OutProtocol>(&config.name).to_json().to_string(); // serde_json escapes the name, whatever's in it
+            tx.write_all(format!("HTTP/1.1 200 Everything Is Ight, Cuh\r\nContent-Type: application/json\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}", manifest).as_bytes()).await?;
+            return Ok(None); // kill the connection, the client will have to reconnect to get the websocket upgrade. TODO: fix this!
         }
+        if !request.has_token("connection", "upgrade") || !request.has_token("upgrade", "websocket") {
+            return Err(HandshakeError::NotAnUpgrade);
+        }
+        if request.header("sec-websocket-version") != Some("13") {
+            return Err(HandshakeError::UnsupportedWebSocketVersion (request.header("sec-websocket-version").map(|v| v.to_string())));
+        }
+        let key = request.header("sec-websocket-key").ok_or(HandshakeError::BadKey)?;
+        if BASE64.decode(key).map(|nonce| nonce.len()) != Ok(16) {
+            return Err(HandshakeError::BadKey);
+        }
+        let accept = BASE64.encode(sha1_smol::Sha1::from(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key)).digest().bytes());
+        tx.write_all(format!("HTTP/1.1 101 Upgrading\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept).as_bytes()).await?;
+        Ok(Some(request.target))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,116 @@
+// the protocol_v3 binary: decoding frames against a manifest, fetching manifests from a server, and the generators' subcommands.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::ProtocolFrame;
+use protocol_v3::codegen::vectors::VectorFile;
+use std::io::{Read, Write};
+use std::process::Command;
+
+
+#[allow(dead_code)]
+#[derive(ProtocolFrame)]
+enum Game {
+    Move(u16, #[protocol(max_len = 3)] String),
+    Ping,
+    Rename {
+        name : String
+    }
+}
+
+
+fn manifest_file(name : &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("protocol_v3_cli_{}_{}.json", name, std::process::id()));
+    std::fs::write(&path, Game::manifest()).unwrap();
+    path
+}
+
+
+fn cli(args : &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_protocol_v3")).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+
+#[test]
+fn decode_annotates_fields() {
+    let path = manifest_file("decode");
+    let manifest = path.to_str().unwrap();
+    let out = cli(&["decode", manifest, &hex::encode(Game::Move(258, "hi".to_string()).encode())]);
+    assert_eq!(out, "Move (opcode 0), 7 bytes
+  0..1     opcode               00
+  1..3     a0 : u16             01 02                    = 258
+  3..7     a1 : String          00 02 68 69              = \"hi\"
+");
+    let out = cli(&["decode", manifest, "0x01ff", "00:01"]);
+    assert!(out.contains("Ping (opcode 1), 2 bytes\n"), "{}", out);
+    assert!(out.contains("  1..2     trailing             ff\n"), "{}", out);
+    assert!(out.contains("  1..      a0 : u16             01                       error: "), "{}", out);
+    let out = cli(&["decode", manifest, "00 0100 0004 68696869", "07"]);
+    assert!(out.contains("  invalid: field 1 of Move failed validation: TooLong"), "{}", out);
+    assert!(out.contains("unknown opcode 7 in Game, 1 bytes\n"), "{}", out);
+    let out = cli(&["decode", manifest, "02 0002 6869"]);
+    assert!(out.contains("  1..5     name : String        00 02 68 69              = \"hi\"\n"), "{}", out); // named fields go by their names
+    std::fs::remove_file(&path).unwrap();
+}
+
+
+fn fetch_from(listener : std::net::TcpListener, address : &str) {
+    let server = std::thread::spawn(move || { // stands in for WebSocketServer's /manifest endpoint
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let len = socket.read(&mut request).unwrap();
+        let request = String::from_utf8_lossy(&request[..len]).to_string();
+        let manifest = format!("{{\"application_name\":\"test\",\"incoming_protocol\":{},\"outgoing_protocol\":{}}}", Game::manifest(), Game::manifest());
+        socket.write_all(format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{}", manifest).as_bytes()).unwrap();
+        request
+    });
+    let out = cli(&["manifest", address]);
+    assert!(server.join().unwrap().starts_with("GET /manifest HTTP/1.1\r\n"));
+    let json : serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["application_name"], "test");
+    assert_eq!(json["incoming_protocol"]["operations"][0]["name"], "Move");
+}
+
+
+#[test]
+fn manifest_is_fetched_from_a_server() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    fetch_from(listener, &address);
+    let Ok (listener) = std::net::TcpListener::bind("[::1]:0") else {
+        println!("no IPv6 loopback, skipping the [::1] case");
+        return;
+    };
+    let address = format!("http://{}/", listener.local_addr().unwrap());
+    fetch_from(listener, &address);
+    let output = Command::new(env!("CARGO_BIN_EXE_protocol_v3")).args(["manifest", "http://[::1]/"]).output().unwrap(); // no port: 80, not whatever follows the last colon
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("invalid"), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+
+#[test]
+fn docs_needs_a_manifest() {
+    for args in [&["docs"][..], &["docs", "--html"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_protocol_v3")).args(args).output().unwrap();
+        assert_eq!(output.status.code(), Some(2), "{:?}", args); // the usage message, not a panic
+        assert!(String::from_utf8_lossy(&output.stderr).contains("docs"));
+    }
+    let path = manifest_file("docs");
+    let html = cli(&["docs", "--html", path.to_str().unwrap()]);
+    assert!(html.contains("Move"), "{}", html);
+    std::fs::remove_file(&path).unwrap();
+}
+
+
+#[test]
+fn vectors_come_once_per_protocol() {
+    let one = manifest_file("vectors_one");
+    let both = std::env::temp_dir().join(format!("protocol_v3_cli_vectors_both_{}.json", std::process::id()));
+    std::fs::write(&both, format!("{{\"application_name\":\"test\",\"incoming_protocol\":{},\"outgoing_protocol\":{}}}", Game::manifest(), Game::manifest())).unwrap();
+    let alone = VectorFile::parse(&cli(&["vectors", one.to_str().unwrap()])).unwrap();
+    let twice = VectorFile::parse(&cli(&["vectors", both.to_str().unwrap()])).unwrap();
+    assert_eq!(twice, alone); // Game both ways has the same frames as Game on its own
+    std::fs::remove_file(&one).unwrap();
+    std::fs::remove_file(&both).unwrap();
+}
@@ -0,0 +1,93 @@
+// the tokio_util codecs on their own: framing byte by byte, both roles, ProtocolCodec over an in-memory pipe, and a Framed client against the real server.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::ProtocolFrame;
+use protocol_v3::codec::{WebSocketCodec, WebSocketFrame, Role, ProtocolCodec, Message};
+use protocol_v3::server::{ServerConfig, WebSocketServer, Disconnect, CloseCode};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+use futures::{StreamExt, SinkExt};
+use bytes::BytesMut;
+
+mod common;
+use common::connect;
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Game {
+    Move(u16, u16),
+    Say(String)
+}
+
+
+#[test]
+fn frames_round_trip() {
+    let frame = WebSocketFrame::Binary { fin : true, payload : Game::Say("x".repeat(200)).encode() };
+    let mut wire = BytesMut::new();
+    WebSocketCodec::new(Role::Client).encode(frame.clone(), &mut wire).unwrap();
+    assert_eq!(wire[1], 0x80 | 126); // masked, 16 bit length
+    let mut server = WebSocketCodec::new(Role::Server);
+    let mut src = BytesMut::new();
+    for (i, byte) in wire.iter().enumerate() { // a byte at a time: nothing until the last one
+        src.extend_from_slice(&[*byte]);
+        let decoded = server.decode(&mut src).unwrap();
+        assert_eq!(decoded.is_some(), i == wire.len() - 1);
+        if let Some (decoded) = decoded {
+            assert_eq!(decoded, frame);
+        }
+    }
+    assert!(src.is_empty());
+    let mut wire = BytesMut::new();
+    server.encode(WebSocketFrame::Ping (b"hi".to_vec()), &mut wire).unwrap();
+    assert_eq!(&wire[..], &[0x89, 2, b'h', b'i']); // unmasked
+    assert_eq!(WebSocketCodec::new(Role::Client).decode(&mut wire).unwrap(), Some(WebSocketFrame::Ping (b"hi".to_vec())));
+}
+
+
+#[test]
+fn roles_and_limits_are_enforced() {
+    let mut unmasked = BytesMut::from(&[0x82, 0x01, 0x00][..]);
+    assert!(matches!(WebSocketCodec::new(Role::Server).decode(&mut unmasked), Err(Disconnect::Protocol (_))));
+    let mut masked = BytesMut::new();
+    WebSocketCodec::new(Role::Client).encode(WebSocketFrame::Binary { fin : true, payload : vec![0] }, &mut masked).unwrap();
+    assert!(matches!(WebSocketCodec::new(Role::Client).decode(&mut masked), Err(Disconnect::Protocol (_))));
+    let mut big = BytesMut::from(&[0x82, 0x80 | 126, 0x01, 0x00, 0, 0, 0, 0][..]); // just the header of a 256 byte frame
+    assert!(matches!(WebSocketCodec::new(Role::Server).max_frame_size(255).decode(&mut big), Err(Disconnect::Oversize)));
+    let mut rsv = BytesMut::from(&[0xC2, 0x80, 0, 0, 0, 0][..]);
+    assert!(matches!(WebSocketCodec::new(Role::Server).decode(&mut rsv), Err(Disconnect::Protocol (_))));
+    let mut wire = BytesMut::new();
+    assert!(WebSocketCodec::new(Role::Server).encode(WebSocketFrame::Ping (vec![0; 126]), &mut wire).is_err());
+}
+
+
+#[tokio::test]
+async fn protocol_codec_over_a_pipe() {
+    let (a, b) = tokio::io::duplex(1 << 16);
+    let mut server = Framed::new(a, ProtocolCodec::<Game, Game>::new(Role::Server).fragment_size(Some(16)));
+    let mut client = Framed::new(b, ProtocolCodec::<Game, Game>::new(Role::Client));
+    client.send(Game::Move(1, 2)).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Frame (Game::Move(1, 2)));
+    let say = Game::Say("a long line, in 16 byte fragments".to_string());
+    server.send(say.clone()).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::Frame (say));
+    client.send(Message::Ping (b"rtt".to_vec())).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Ping (b"rtt".to_vec()));
+    server.send(Message::Close (Some(CloseCode::GOING_AWAY), "restarting".to_string())).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::Close (Some(CloseCode::GOING_AWAY), "restarting".to_string()));
+}
+
+
+#[tokio::test]
+async fn framed_client_against_the_server() {
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0")).await.unwrap();
+    let address = server.local_addrs()[0];
+    let (socket, mut stream) = tokio::join!(connect(address, "/"), server.accept());
+    let mut client = Framed::new(socket, ProtocolCodec::<Game, Game>::new(Role::Client));
+    client.send(Game::Move(5, 6)).await.unwrap();
+    assert_eq!(stream.read().await.ok(), Some(Game::Move(5, 6)));
+    stream.send(Game::Say("hello".to_string())).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::Frame (Game::Say("hello".to_string())));
+    client.send(Message::Close (Some(CloseCode::NORMAL), String::new())).await.unwrap();
+    assert!(matches!(stream.read().await, Err(Disconnect::Closed { code : Some (CloseCode::NORMAL), .. })));
+    assert_eq!(client.next().await.unwrap().unwrap(), Message::Close (Some(CloseCode::NORMAL), String::new()));
+    assert!(client.next().await.is_none()); // and the server hangs up
+}
@@ -0,0 +1,67 @@
+// the bare-bones WebSocket client the server and conformance suites share: written out by hand, so the tests control (and see) exactly the bytes on the wire.
+// also what the codegen suites do when a tool they check the output with (node, python3, tsc, luac) isn't installed.
+#![allow(dead_code)] // each test crate uses its own share of it
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use std::net::SocketAddr;
+
+
+pub async fn connect(address : SocketAddr, path : &str) -> TcpStream { // through the upgrade, ready for frames
+    let mut socket = TcpStream::connect(address).await.unwrap();
+    socket.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n", path).as_bytes()).await.unwrap();
+    let mut response = vec![];
+    while !response.ends_with(b"\r\n\r\n") {
+        response.push(socket.read_u8().await.unwrap());
+    }
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 101 "), "{}", response);
+    assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n")); // the example key and answer from RFC 6455
+    socket
+}
+
+
+pub fn raw(head : u8, payload : &[u8]) -> Vec<u8> { // a masked frame with the first header byte (FIN, RSV, opcode) given exactly, so it can be as wrong as a case needs
+    let mask = [0x37, 0xfa, 0x21, 0x3d];
+    let mut ret = vec![head];
+    if payload.len() < 126 {
+        ret.push(0x80 | payload.len() as u8);
+    }
+    else if payload.len() <= 0xffff {
+        ret.push(0x80 | 126);
+        ret.extend((payload.len() as u16).to_be_bytes());
+    }
+    else {
+        ret.push(0x80 | 127);
+        ret.extend((payload.len() as u64).to_be_bytes());
+    }
+    ret.extend(mask);
+    ret.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    ret
+}
+
+
+pub fn frame(opcode : u8, fin : bool, payload : &[u8]) -> Vec<u8> { // client frames are always masked
+    raw(if fin { 0x80 } else { 0 } | opcode, payload)
+}
+
+
+pub async fn server_frame(client : &mut TcpStream) -> std::io::Result<(u8, Vec<u8>)> { // the first header byte (FIN and opcode) and the payload of one unmasked frame
+    let head = client.read_u8().await?;
+    let len = match client.read_u8().await? {
+        126 => client.read_u16().await? as u64,
+        127 => client.read_u64().await?,
+        len => len as u64
+    };
+    let mut payload = vec![0; len as usize];
+    client.read_exact(&mut payload).await?;
+    Ok((head, payload))
+}
+
+
+pub fn missing(tool : &str) { // skips the check, unless PROTOCOL_V3_REQUIRE_TOOLS is set (as it should be wherever the tools are installed on purpose)
+    if std::env::var_os("PROTOCOL_V3_REQUIRE_TOOLS").is_some() {
+        panic!("{} isn't installed, and PROTOCOL_V3_REQUIRE_TOOLS says every check has to run", tool);
+    }
+    println!("{} isn't available, skipping", tool);
+}
@@ -0,0 +1,273 @@
+// RFC 6455 conformance, case by case, in the spirit of the Autobahn testsuite (and numbered after its sections where there's a match). each case
+// opens a fresh connection to an echo server, writes raw client frames, and checks what comes back down the wire: replies, pongs, and how it closes.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::ProtocolFrame;
+use protocol_v3::server::{ServerConfig, WebSocketServer, WebSocketClientStream, Disconnect};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use std::time::Duration;
+
+mod common;
+use common::{connect, raw, frame, server_frame};
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Request {
+    Echo(String),
+    Measure(String) // for the big ones: answered with just the length, so the replies stay small
+}
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Reply {
+    Echo(String),
+    Length(u32)
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expect {
+    Frame (u8, Vec<u8>), // first header byte and payload of a server frame
+    Fail (u16), // a Close with this code (and whatever reason), then the server hangs up
+    Closed (u16) // a clean Close with just this code, then the server hangs up
+}
+
+
+struct Case {
+    id : String,
+    send : Vec<Vec<u8>>,
+    expect : Vec<Expect>
+}
+
+
+fn case(id : impl Into<String>, send : Vec<Vec<u8>>, expect : Vec<Expect>) -> Case {
+    Case { id : id.into(), send, expect }
+}
+
+
+fn binary(request : Request) -> Vec<u8> {
+    frame(0x2, true, &request.encode())
+}
+
+
+fn text(json : &str) -> Vec<u8> {
+    frame(0x1, true, json.as_bytes())
+}
+
+
+fn close(code : u16, reason : &[u8]) -> Vec<u8> {
+    frame(0x8, true, &[&code.to_be_bytes()[..], reason].concat())
+}
+
+
+fn echoed(text : &str) -> Expect {
+    Expect::Frame (0x82, Reply::Echo(text.to_string()).encode())
+}
+
+
+fn measured(length : usize) -> Expect {
+    Expect::Frame (0x82, Reply::Length(length as u32).encode())
+}
+
+
+fn pong(payload : &[u8]) -> Expect {
+    Expect::Frame (0x8A, payload.to_vec())
+}
+
+
+async fn echo(mut stream : WebSocketClientStream<Request, Reply>) -> (WebSocketClientStream<Request, Reply>, Disconnect) { // hands the stream back so it outlives the client's checks; dropping it early could reset the connection before the Close arrives
+    loop {
+        let reply = match stream.read().await {
+            Ok (Request::Echo (text)) => Reply::Echo(text),
+            Ok (Request::Measure (text)) => Reply::Length(text.len() as u32),
+            Err (disconnect) => return (stream, disconnect)
+        };
+        stream.send(reply).await.unwrap();
+    }
+}
+
+
+async fn check(client : &mut TcpStream, case : &Case) -> Result<(), String> {
+    for bytes in &case.send {
+        client.write_all(bytes).await.map_err(|e| format!("couldn't send: {}", e))?;
+    }
+    for expect in &case.expect {
+        let (head, payload) = server_frame(client).await.map_err(|e| format!("expected {:?}, but the connection ended: {}", expect, e))?;
+        let good = match expect {
+            Expect::Frame (want_head, want) => head == *want_head && payload == *want,
+            Expect::Fail (code) => head == 0x88 && payload.len() >= 2 && payload[..2] == code.to_be_bytes(),
+            Expect::Closed (code) => head == 0x88 && payload == code.to_be_bytes()
+        };
+        if !good {
+            return Err(format!("expected {:?}, got {:#x} {:?}", expect, head, String::from_utf8_lossy(&payload)));
+        }
+        if matches!(expect, Expect::Fail (_) | Expect::Closed (_)) {
+            let mut rest = vec![];
+            client.read_to_end(&mut rest).await.map_err(|e| format!("after the Close: {}", e))?;
+            if !rest.is_empty() {
+                return Err(format!("{} more bytes after the Close", rest.len()));
+            }
+        }
+    }
+    Ok(())
+}
+
+
+async fn run(config : ServerConfig, cases : Vec<Case>) {
+    let mut server = WebSocketServer::<Request, Reply>::new(config.bind("127.0.0.1:0")).await.unwrap();
+    let address = server.local_addrs()[0];
+    let mut failures = vec![];
+    for case in cases {
+        let (mut client, stream) = tokio::join!(connect(address, "/"), server.accept());
+        let session = tokio::spawn(echo(stream));
+        match tokio::time::timeout(Duration::from_secs(5), check(&mut client, &case)).await {
+            Ok (Ok (())) => {}
+            Ok (Err (problem)) => failures.push(format!("{}: {}", case.id, problem)),
+            Err (_) => failures.push(format!("{}: timed out", case.id))
+        }
+        drop(client);
+        let _ = tokio::time::timeout(Duration::from_secs(5), session).await;
+    }
+    assert!(failures.is_empty(), "{} failed:\n{}", failures.len(), failures.join("\n"));
+}
+
+
+#[tokio::test]
+async fn framing() { // 1.x
+    let big = |len : usize| "a".repeat(len - 3); // an encoded Measure is 3 bytes longer than its text
+    run(ServerConfig::new("conformance").json(true).fragment_size(None), vec![
+        case("1.1.1 empty message", vec![binary(Request::Echo(String::new()))], vec![echoed("")]),
+        case("1.1.2 short message", vec![binary(Request::Echo("Hello, world!".to_string()))], vec![echoed("Hello, world!")]),
+        case("1.1.3 125 bytes, the longest 7 bit length", vec![binary(Request::Measure(big(125)))], vec![measured(122)]),
+        case("1.1.4 126 bytes, 16 bit length", vec![binary(Request::Measure(big(126)))], vec![measured(123)]),
+        case("1.1.5 65535 bytes, the longest 16 bit length", vec![binary(Request::Measure(big(65535)))], vec![measured(65532)]),
+        case("1.1.6 65536 bytes, 64 bit length", vec![binary(Request::Measure(big(65536)))], vec![measured(65533)]),
+        case("1.1.7 several messages back to back", vec![binary(Request::Echo("a".to_string())), binary(Request::Echo("b".to_string()))], vec![echoed("a"), echoed("b")]),
+        case("1.1.8 JSON text message", vec![text(r#"{"op":"Echo","args":["json"]}"#)], vec![echoed("json")]),
+        case("1.1.9 server frame with a 16 bit length", vec![binary(Request::Echo(big(300)))], vec![echoed(&big(300))]),
+        case("1.1.10 server frame with a 64 bit length", vec![binary(Request::Echo(big(65538)))], vec![echoed(&big(65538))]),
+        case("1.2.1 unmasked frame", vec![vec![0x82, 0x03, 0x00, 0x00, 0x00]], vec![Expect::Fail (1002)]),
+        case("1.2.2 64 bit length with the high bit set", vec![[&[0x82, 0xff][..], &(1u64 << 63).to_be_bytes(), &[0; 4]].concat()], vec![Expect::Fail (1002)])
+    ]).await;
+}
+
+
+#[tokio::test]
+async fn pings() { // 2.x
+    let all_bytes : Vec<u8> = (0..125).collect();
+    run(ServerConfig::new("conformance"), vec![
+        case("2.1 ping without a payload", vec![frame(0x9, true, &[])], vec![pong(&[])]),
+        case("2.2 ping with a text payload", vec![frame(0x9, true, b"Hello, world!")], vec![pong(b"Hello, world!")]),
+        case("2.3 ping with a binary payload", vec![frame(0x9, true, &[0x00, 0xff, 0xfe, 0xfd, 0xfc, 0xfb, 0x00, 0xff])], vec![pong(&[0x00, 0xff, 0xfe, 0xfd, 0xfc, 0xfb, 0x00, 0xff])]),
+        case("2.4 ping with 125 bytes", vec![frame(0x9, true, &all_bytes)], vec![pong(&all_bytes)]),
+        case("2.5 ping with 126 bytes", vec![frame(0x9, true, &[0xfe; 126])], vec![Expect::Fail (1002)]),
+        case("2.6 unsolicited pong", vec![frame(0xA, true, b"unasked"), binary(Request::Echo("after".to_string()))], vec![echoed("after")]),
+        case("2.7 ten pings in a row", (0..10u8).map(|i| frame(0x9, true, &[i])).collect(), (0..10u8).map(|i| pong(&[i])).collect())
+    ]).await;
+}
+
+
+#[tokio::test]
+async fn reserved_bits() { // 3.x
+    run(ServerConfig::new("conformance"), vec![
+        case("3.1 RSV1 on a message", vec![raw(0xC2, &Request::Echo("x".to_string()).encode())], vec![Expect::Fail (1002)]),
+        case("3.2 RSV2 after a good message", vec![binary(Request::Echo("x".to_string())), raw(0xA2, &Request::Echo("y".to_string()).encode())], vec![echoed("x"), Expect::Fail (1002)]),
+        case("3.3 RSV3 on a ping", vec![raw(0x99, b"ping")], vec![Expect::Fail (1002)]),
+        case("3.4 every RSV bit on a close", vec![raw(0xF8, &1000u16.to_be_bytes())], vec![Expect::Fail (1002)])
+    ]).await;
+}
+
+
+#[tokio::test]
+async fn opcodes() { // 4.x
+    let mut cases = vec![];
+    for opcode in [0x3, 0x4, 0x5, 0x6, 0x7] {
+        cases.push(case(format!("4.1 reserved data opcode {:#x}", opcode), vec![binary(Request::Echo("x".to_string())), frame(opcode, true, b"")], vec![echoed("x"), Expect::Fail (1002)]));
+    }
+    for opcode in [0xB, 0xC, 0xD, 0xE, 0xF] {
+        cases.push(case(format!("4.2 reserved control opcode {:#x}", opcode), vec![frame(opcode, true, b"control")], vec![Expect::Fail (1002)]));
+    }
+    run(ServerConfig::new("conformance"), cases).await;
+}
+
+
+#[tokio::test]
+async fn fragmentation() { // 5.x
+    let hello = Request::Echo("Hello, world!".to_string()).encode();
+    let one_byte_frames : Vec<Vec<u8>> = hello.iter().enumerate().map(|(i, b)| frame(if i == 0 { 0x2 } else { 0x0 }, i == hello.len() - 1, &[*b])).collect();
+    let json = r#"{"op":"Echo","args":["fragmented"]}"#.as_bytes();
+    run(ServerConfig::new("conformance").json(true), vec![
+        case("5.1 fragmented ping", vec![frame(0x9, false, b"frag"), frame(0x0, true, b"ment")], vec![Expect::Fail (1002)]),
+        case("5.2 fragmented pong", vec![frame(0xA, false, b"frag"), frame(0x0, true, b"ment")], vec![Expect::Fail (1002)]),
+        case("5.3 binary message in two fragments", vec![frame(0x2, false, &hello[..5]), frame(0x0, true, &hello[5..])], vec![echoed("Hello, world!")]),
+        case("5.4 binary message one byte per fragment", one_byte_frames, vec![echoed("Hello, world!")]),
+        case("5.5 text message in two fragments", vec![frame(0x1, false, &json[..10]), frame(0x0, true, &json[10..])], vec![echoed("fragmented")]),
+        case("5.6 ping between fragments", vec![frame(0x2, false, &hello[..5]), frame(0x9, true, b"ping"), frame(0x0, true, &hello[5..])], vec![pong(b"ping"), echoed("Hello, world!")]),
+        case("5.7 pong between fragments", vec![frame(0x1, false, &json[..10]), frame(0xA, true, b"pong"), frame(0x0, true, &json[10..])], vec![echoed("fragmented")]),
+        case("5.8 empty fragments", vec![frame(0x2, false, &[]), frame(0x0, false, &hello), frame(0x0, true, &[])], vec![echoed("Hello, world!")]),
+        case("5.9 continuation with nothing to continue", vec![frame(0x0, true, &hello)], vec![Expect::Fail (1002)]),
+        case("5.10 unfinished continuation with nothing to continue", vec![frame(0x0, false, &hello[..5]), frame(0x0, true, &hello[5..])], vec![Expect::Fail (1002)]),
+        case("5.11 continuation after a finished message", vec![binary(Request::Echo("x".to_string())), frame(0x0, true, &hello)], vec![echoed("x"), Expect::Fail (1002)]),
+        case("5.12 new binary message in the middle of another", vec![frame(0x2, false, &hello[..5]), frame(0x2, true, &hello)], vec![Expect::Fail (1002)]),
+        case("5.13 new text message in the middle of a binary one", vec![frame(0x2, false, &hello[..5]), frame(0x1, true, json)], vec![Expect::Fail (1002)]),
+        case("5.14 close between fragments", vec![frame(0x2, false, &hello[..5]), close(1000, b"")], vec![Expect::Closed (1000)])
+    ]).await;
+}
+
+
+#[tokio::test]
+async fn utf8() { // 6.x
+    let json = |text : &[u8]| [&br#"{"op":"Echo","args":[""#[..], text, br#""]}"#].concat();
+    let kosme = json("κόσμε".as_bytes());
+    let split = kosme.iter().position(|b| *b == 0xce).unwrap() + 1; // in the middle of the first two byte character
+    run(ServerConfig::new("conformance").json(true), vec![
+        case("6.1 valid UTF-8", vec![frame(0x1, true, &kosme)], vec![echoed("κόσμε")]),
+        case("6.2 valid UTF-8, fragmented in the middle of a character", vec![frame(0x1, false, &kosme[..split]), frame(0x0, true, &kosme[split..])], vec![echoed("κόσμε")]),
+        case("6.3 four byte characters", vec![frame(0x1, true, &json("𝄞𐍈".as_bytes()))], vec![echoed("𝄞𐍈")]),
+        case("6.4 invalid byte", vec![frame(0x1, true, &json(b"\xff"))], vec![Expect::Fail (1007)]),
+        case("6.5 truncated character", vec![frame(0x1, true, &json(b"\xce"))], vec![Expect::Fail (1007)]),
+        case("6.6 overlong encoding", vec![frame(0x1, true, &json(b"\xc0\xaf"))], vec![Expect::Fail (1007)]),
+        case("6.7 UTF-16 surrogate", vec![frame(0x1, true, &json(b"\xed\xa0\x80"))], vec![Expect::Fail (1007)]),
+        case("6.8 past U+10FFFF", vec![frame(0x1, true, &json(b"\xf4\x90\x80\x80"))], vec![Expect::Fail (1007)]),
+        case("6.9 invalid byte in a later fragment", vec![frame(0x1, false, &kosme[..split]), frame(0x0, true, b"\xff\"]}")], vec![Expect::Fail (1007)])
+    ]).await;
+    run(ServerConfig::new("conformance"), vec![
+        case("6.10 text message with JSON off", vec![frame(0x1, true, &kosme)], vec![Expect::Fail (1003)])
+    ]).await;
+}
+
+
+#[tokio::test]
+async fn closing() { // 7.x
+    let mut cases = vec![
+        case("7.1.1 close with a normal code", vec![close(1000, b"")], vec![Expect::Closed (1000)]),
+        case("7.1.2 frames after the close are ignored", vec![close(1000, b""), binary(Request::Echo("late".to_string())), frame(0x9, true, b"late")], vec![Expect::Closed (1000)]),
+        case("7.1.3 message and then close", vec![binary(Request::Echo("x".to_string())), close(1000, b"")], vec![echoed("x"), Expect::Closed (1000)]),
+        case("7.1.4 close in the middle of a fragmented ping", vec![frame(0x9, false, b"frag"), close(1000, b"")], vec![Expect::Fail (1002)]),
+        case("7.3.1 close without a payload", vec![frame(0x8, true, &[])], vec![Expect::Closed (1000)]),
+        case("7.3.2 close with a one byte payload", vec![frame(0x8, true, &[0x03])], vec![Expect::Fail (1002)]),
+        case("7.3.3 close with a reason", vec![close(1000, b"goodbye")], vec![Expect::Closed (1000)]),
+        case("7.3.4 close with the longest reason", vec![close(1000, &[b'*'; 123])], vec![Expect::Closed (1000)]),
+        case("7.3.5 close payload over 125 bytes", vec![close(1000, &[b'*'; 124])], vec![Expect::Fail (1002)]),
+        case("7.5.1 close reason that isn't UTF-8", vec![close(1000, b"\xce\xba\xe1\xbd\xb9\xcf\x83\xce\xbc\xce\xb5\xed\xa0\x80")], vec![Expect::Fail (1002)])
+    ];
+    for code in [1000, 1001, 1002, 1003, 1007, 1008, 1009, 1010, 1011, 3000, 3999, 4000, 4999] {
+        cases.push(case(format!("7.7 valid close code {}", code), vec![close(code, b"")], vec![Expect::Closed (code)]));
+    }
+    for code in [0, 999, 1004, 1005, 1006, 1016, 1100, 2000, 2999, 5000, 65535] {
+        cases.push(case(format!("7.9 invalid close code {}", code), vec![close(code, b"")], vec![Expect::Fail (1002)]));
+    }
+    run(ServerConfig::new("conformance"), cases).await;
+}
+
+
+#[tokio::test]
+async fn limits() { // 9.x, scaled down to limits a test can reach quickly
+    let long = Request::Echo("x".repeat(100)).encode(); // 103 bytes
+    run(ServerConfig::new("conformance").max_frame_size(64).max_message_size(128), vec![
+        case("9.1 frame over the frame limit", vec![frame(0x2, true, &long)], vec![Expect::Fail (1009)]),
+        case("9.2 message over the message limit, in frames under the frame limit", vec![frame(0x2, false, &long[..60]), frame(0x0, false, &long[60..]), frame(0x0, true, &long[..60])], vec![Expect::Fail (1009)]),
+        case("9.3 message under the message limit, in frames under the frame limit", vec![frame(0x2, false, &long[..60]), frame(0x0, true, &long[60..])], vec![echoed(&"x".repeat(100))])
+    ]).await;
+}
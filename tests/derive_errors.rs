@@ -0,0 +1,14 @@
+// compile-fail cases for #[derive(ProtocolFrame)] (and the typed server streams): every one of these should produce an error pointing at the offending item
+// regenerate the expected output with TRYBUILD=overwrite cargo test --test derive_errors, with and without --features serde
+
+
+#[test]
+fn derive_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+    if cfg!(feature = "serde") { // the serde feature adds a ProtocolSegment impl, which changes the impls the compiler lists
+        t.compile_fail("tests/ui/serde/*.rs");
+    } else {
+        t.compile_fail("tests/ui/default/*.rs");
+    }
+}
@@ -0,0 +1,137 @@
+// reference docs: variant doc comments and field names make it from the derive through the manifest into the generated Markdown and HTML.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::{ProtocolFrame, Dispatch};
+use protocol_v3::manifest::{Manifest, ServerManifest};
+use protocol_v3::codegen::docs;
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+#[protocol(handler)]
+enum Lobby {
+    /// Join the lobby under a display name.
+    ///
+    /// Names are "trimmed" by the server.
+    Join { #[protocol(max_len = 16)] name : String, team : u8 },
+    /// Move to a new position.
+    Move(f32, f32),
+    Ready { #[protocol(skip)] local_only : u32, r#ready : bool },
+    Leave
+}
+
+
+#[derive(Default)]
+struct Recorder {
+    joins : Vec<(String, u8)>
+}
+
+
+impl LobbyHandler for Recorder {
+    async fn on_join(&mut self, name : String, team : u8) {
+        self.joins.push((name, team));
+    }
+}
+
+
+#[test]
+fn named_fields_round_trip() {
+    let frames = vec![
+        Lobby::Join { name : "ann".to_string(), team : 2 },
+        Lobby::Move(1.0, -1.0),
+        Lobby::Ready { local_only : 0, ready : true },
+        Lobby::Leave
+    ];
+    for frame in frames {
+        assert_eq!(Lobby::decode(frame.encode().into()).unwrap(), frame);
+    }
+    assert_eq!(Lobby::Ready { local_only : 9, ready : true }.encode(), vec![2, 1]); // skipped fields stay off the wire, named or not
+    let mut recorder = Recorder::default();
+    tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(Lobby::Join { name : "bo".to_string(), team : 1 }.dispatch(&mut recorder));
+    assert_eq!(recorder.joins, vec![("bo".to_string(), 1)]);
+}
+
+
+#[test]
+fn manifest_carries_docs_and_names() {
+    let manifest = Manifest::of::<Lobby>();
+    let join = manifest.operation_named("Join").unwrap();
+    assert_eq!(join.doc.as_deref(), Some("Join the lobby under a display name.\n\nNames are \"trimmed\" by the server."));
+    assert_eq!(join.args[0].name.as_deref(), Some("name"));
+    assert_eq!(join.args[1].name.as_deref(), Some("team"));
+    let ready = manifest.operation_named("Ready").unwrap();
+    assert_eq!(ready.args.len(), 1);
+    assert_eq!(ready.args[0].name.as_deref(), Some("ready"));
+    let moves = manifest.operation_named("Move").unwrap();
+    assert_eq!(moves.doc.as_deref(), Some("Move to a new position."));
+    assert_eq!(moves.args[0].name, None);
+    assert_eq!(manifest.operation_named("Leave").unwrap().doc, None);
+}
+
+
+#[test]
+fn markdown_has_layouts() {
+    let markdown = docs::generate(&ServerManifest::of::<Lobby, Lobby>("Lobby"));
+    assert!(markdown.starts_with("# Lobby protocol reference\n"));
+    assert!(markdown.contains("## Incoming: `Lobby`\n"));
+    assert!(markdown.contains("## Outgoing: `Lobby`\n"));
+    assert!(markdown.contains("| 0 | `Join` | name: String, team: u8 |\n"));
+    assert!(markdown.contains("### `Join` (opcode 0)\n\nJoin the lobby under a display name.\n\nNames are \"trimmed\" by the server.\n\n"));
+    assert!(markdown.contains("| 0 | 1 | opcode | `u8` | always 0 |\n| 1 | 2 | name length | `u16` |  |\n| 3 | len(name) | name | `UTF-8` | at most 16 bytes |\n| 3 + len(name) | 1 | team | `u8` |  |\n\nTotal: 4 + len(name) bytes.\n"));
+    assert!(markdown.contains("| 1 | 4 | a0 | `f32` |  |\n| 5 | 4 | a1 | `f32` |  |\n\nTotal: 9 bytes.\n"));
+}
+
+
+#[test]
+fn html_is_escaped() {
+    let html = docs::generate_html_protocol(&Manifest::of::<Lobby>());
+    assert!(html.starts_with("<!DOCTYPE html>\n"));
+    assert!(html.contains("<h3 id=\"protocol-0\"><code>Join</code> (opcode 0)</h3>\n<p>Join the lobby under a display name.</p>\n<p>Names are &quot;trimmed&quot; by the server.</p>\n"));
+    assert!(html.contains("<tr><td>3 + len(name)</td><td>1</td><td>team</td><td><code>u8</code></td><td></td></tr>\n"));
+}
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Switch {
+    Set {
+        /// Either "on" | "off".
+        #[protocol(meta(values = "on|off"))]
+        state : String
+    }
+}
+
+
+#[test]
+fn pipes_stay_inside_their_cell() {
+    let markdown = docs::generate_protocol(&Manifest::of::<Switch>());
+    assert!(markdown.contains("| 3 | len(state) | state | `UTF-8` | Either \"on\" \\| \"off\", `values` = \"on\\|off\" |\n"));
+}
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+#[protocol(handler)]
+#[allow(non_camel_case_types)]
+enum Raw {
+    r#type(u8),
+    r#Quit
+}
+
+
+struct Quitter(bool);
+
+
+impl RawHandler for Quitter {
+    async fn on_quit(&mut self) {
+        self.0 = true;
+    }
+}
+
+
+#[test]
+fn raw_variant_names_lose_their_prefix() {
+    let manifest = Manifest::of::<Raw>();
+    assert_eq!(manifest.operations.iter().map(|op| op.name.as_str()).collect::<Vec<_>>(), vec!["type", "Quit"]);
+    assert!(!Raw::manifest().contains("r#"));
+    let mut quitter = Quitter(false);
+    tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(Raw::r#Quit.dispatch(&mut quitter));
+    assert!(quitter.0);
+}
@@ -0,0 +1,141 @@
+// checks the Rust encoding against the golden vectors in vectors/golden.json, and that the file itself is still what the generator makes.
+// after an intentional change to the wire format, rewrite the file with PROTOCOL_V3_BLESS=1 cargo test --test golden_vectors golden_file_is_current.
+// if node is around, protocol.js gets checked against the same file.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::ProtocolFrame;
+use protocol_v3::manifest::Manifest;
+use protocol_v3::dynamic::{DynamicFrame, DynamicValue, DynamicError};
+use protocol_v3::codegen::vectors::{self, VectorFile};
+use std::process::Command;
+
+mod common;
+
+
+const GOLDEN : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/vectors/golden.json");
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Golden {
+    Empty,
+    Small(u8, bool),
+    Numbers(u16, u32, u64, i32),
+    Float(f32),
+    Text(String),
+    Limited(#[protocol(range = 1..=200)] u8, #[protocol(max_len = 5)] String),
+    Mixed(String, f32, u64, bool)
+}
+
+
+fn golden() -> VectorFile {
+    VectorFile::parse(&std::fs::read_to_string(GOLDEN).expect("vectors/golden.json is missing")).expect("vectors/golden.json is not a vector file")
+}
+
+
+#[test]
+fn golden_file_is_current() {
+    let generated = vectors::generate(&[&Manifest::of::<Golden>()]).unwrap().to_json();
+    if std::env::var_os("PROTOCOL_V3_BLESS").is_some() {
+        std::fs::write(GOLDEN, &generated).unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(GOLDEN).unwrap(), generated, "vectors/golden.json is out of date; rerun with PROTOCOL_V3_BLESS=1 if the change is intentional");
+}
+
+
+#[test]
+fn segments_match_vectors() {
+    let file = golden();
+    assert_eq!(file.format, vectors::FORMAT);
+    for ty in vectors::TYPES {
+        assert!(file.segments.iter().any(|v| v.ty == ty), "no vectors for {}", ty);
+    }
+    let failures = vectors::verify_segments(&file);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+
+#[test]
+fn frames_match_vectors() {
+    let file = golden();
+    let manifest = Manifest::of::<Golden>();
+    for op in &manifest.operations {
+        assert!(file.frames.iter().any(|f| f.operation == op.name), "no vectors for {}", op.name);
+    }
+    let failures = vectors::verify_frames::<Golden>(&file);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+
+#[test]
+fn dynamic_codec_matches_vectors() {
+    let file = golden();
+    let manifest = Manifest::of::<Golden>();
+    for vector in &file.frames {
+        let bytes = hex::decode(&vector.bytes).unwrap();
+        let frame = DynamicFrame::decode(&manifest, &bytes).unwrap();
+        assert_eq!(frame.operation, vector.operation);
+        assert_eq!(frame.encode(), bytes, "{} didn't round-trip", vector.bytes);
+        let derived = Golden::decode(bytes.iter().copied().collect()).unwrap();
+        assert_eq!(DynamicFrame::new(&manifest, &frame.operation, frame.args.clone()).unwrap().encode(), derived.encode());
+    }
+    let too_long = DynamicFrame::new(&manifest, "Limited", vec![DynamicValue::U8 (1), DynamicValue::String ("too long".to_string())]);
+    assert!(matches!(too_long, Err(DynamicError::Invalid { field : 1, .. })));
+    let out_of_range = Golden::Limited(0, String::new()).encode();
+    assert!(matches!(DynamicFrame::decode(&manifest, &out_of_range), Err(DynamicError::Invalid { field : 0, .. })));
+    let wrong = DynamicFrame::new(&manifest, "Small", vec![DynamicValue::Bool (true), DynamicValue::U8 (1)]);
+    assert!(matches!(wrong, Err(DynamicError::WrongArguments { .. })));
+}
+
+
+#[test]
+fn json_round_trips_vectors() {
+    for vector in &golden().frames {
+        let frame = Golden::decode(hex::decode(&vector.bytes).unwrap().into()).unwrap();
+        let json = frame.to_json().unwrap();
+        let value : serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["op"], vector.operation.as_str());
+        assert_eq!(value["args"].as_array().unwrap().len(), vector.args.len());
+        assert_eq!(Golden::from_json(&json).unwrap(), frame, "{} didn't round-trip", json);
+    }
+    assert_eq!(Golden::Float(0.1).to_json().unwrap(), r#"{"args":[0.1],"op":"Float"}"#);
+    assert_eq!(Golden::from_json(r#"{"op":"Numbers","args":[1,2,3,-4]}"#).unwrap(), Golden::Numbers(1, 2, 3, -4)); // u64 as a plain number is fine too
+    assert_eq!(Golden::from_json(r#"{"op":"Empty"}"#).unwrap(), Golden::Empty);
+    assert!(Golden::from_json(r#"{"op":"Small","args":[256,true]}"#).is_err());
+    assert!(Golden::from_json(r#"{"op":"Limited","args":[0,""]}"#).is_err());
+}
+
+
+const PROTOCOL_JS_CHECK : &str = r#"
+const fs = require("fs");
+eval(fs.readFileSync(process.argv[1], "utf8") + "\nglobalThis.protocol = protocol;");
+const file = JSON.parse(fs.readFileSync(process.argv[2], "utf8"));
+const failures = [];
+const hex = bytes => bytes.map(b => b.toString(16).padStart(2, "0")).join("");
+for (const vector of file.segments) {
+    const type = protocol.defaultConfig.types[vector.type];
+    if (!type) {
+        continue; // protocol.js doesn't know every type
+    }
+    const bytes = vector.bytes.match(/../g) || [];
+    const encoded = hex(type.encode(vector.value));
+    if (encoded != vector.bytes) {
+        failures.push(vector.type + " " + JSON.stringify(vector.value) + ": encoded to " + encoded + ", expected " + vector.bytes);
+    }
+    const decoded = type.decode(bytes.map(b => parseInt(b, 16)));
+    if (decoded != vector.value) {
+        failures.push(vector.type + " " + vector.bytes + ": decoded to " + JSON.stringify(decoded) + ", expected " + JSON.stringify(vector.value));
+    }
+}
+console.log(failures.join("\n"));
+process.exit(failures.length == 0 ? 0 : 1);
+"#;
+
+
+#[test]
+fn protocol_js_matches_vectors() {
+    let script = concat!(env!("CARGO_MANIFEST_DIR"), "/protocol.js");
+    match Command::new("node").arg("-e").arg(PROTOCOL_JS_CHECK).arg(script).arg(GOLDEN).output() {
+        Ok (output) => assert!(output.status.success(), "protocol.js disagrees with the vectors:\n{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)),
+        Err (_) => common::missing("node")
+    }
+}
@@ -0,0 +1,82 @@
+// #[protocol(handler)]: the generated FooHandler trait, Dispatch sending each variant to its method, and route pumping a real connection into a handler.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::{ProtocolFrame, Dispatch};
+use protocol_v3::server::{ServerConfig, WebSocketServer, Disconnect, CloseCode};
+use tokio::io::AsyncWriteExt;
+
+mod common;
+use common::{connect, frame};
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+#[protocol(handler)]
+enum Game {
+    Join,
+    Move(u16, u16),
+    Say {
+        room : String,
+        text : String
+    },
+    Tagged(#[protocol(skip)] u32, u8), // the skipped field isn't a parameter
+    Leave // not overridden: the default does nothing
+}
+
+
+#[derive(Default)]
+struct Recorder {
+    seen : Vec<String>
+}
+
+
+impl GameHandler for Recorder {
+    async fn on_join(&mut self) {
+        self.seen.push("join".to_string());
+    }
+
+    async fn on_move(&mut self, a0 : u16, a1 : u16) {
+        self.seen.push(format!("move {} {}", a0, a1));
+    }
+
+    async fn on_say(&mut self, room : String, text : String) {
+        self.seen.push(format!("say {} {}", room, text));
+    }
+
+    async fn on_tagged(&mut self, a1 : u8) {
+        self.seen.push(format!("tagged {}", a1));
+    }
+}
+
+
+fn frames() -> Vec<Game> {
+    vec![Game::Join, Game::Move(1, 2), Game::Say { room : "lobby".to_string(), text : "hi".to_string() }, Game::Tagged(0, 7), Game::Leave]
+}
+
+
+const SEEN : [&str; 4] = ["join", "move 1 2", "say lobby hi", "tagged 7"];
+
+
+#[tokio::test]
+async fn dispatch_calls_the_right_method() {
+    let mut recorder = Recorder::default();
+    for frame in frames() {
+        frame.dispatch(&mut recorder).await;
+    }
+    assert_eq!(recorder.seen, SEEN);
+}
+
+
+#[tokio::test]
+async fn route_runs_until_the_client_leaves() {
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0")).await.unwrap();
+    let address = server.local_addrs()[0];
+    let (mut client, mut stream) = tokio::join!(connect(address, "/"), server.accept());
+    for game in frames() {
+        client.write_all(&frame(0x2, true, &game.encode())).await.unwrap();
+    }
+    client.write_all(&frame(0x8, true, &CloseCode::GOING_AWAY.0.to_be_bytes())).await.unwrap();
+    let mut recorder = Recorder::default();
+    let disconnect = tokio::time::timeout(std::time::Duration::from_secs(5), stream.route(&mut recorder)).await.unwrap();
+    assert!(matches!(disconnect, Disconnect::Closed { code : Some (CloseCode::GOING_AWAY), .. }), "{:?}", disconnect);
+    assert_eq!(recorder.seen, SEEN);
+}
@@ -0,0 +1,73 @@
+// doc comments and #[protocol(meta(...))] on the frame enum, its variants and its fields all land in the manifest, and from there in the
+// generated clients and docs.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::ProtocolFrame;
+use protocol_v3::manifest::Manifest;
+use protocol_v3::codegen::{docs, typescript, python};
+use serde_json::json;
+
+
+/// Chat messages, client to server.
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+#[protocol(meta(version = 2, owner = "chat team"))]
+enum Chat {
+    /// Say something in a room.
+    #[protocol(meta(rate_limit = 5, since = "1.2"))]
+    Say {
+        /// Room to say it in.
+        room : u16,
+        /// What to say.
+        #[protocol(max_len = 200, meta(markdown))]
+        text : String
+    },
+    #[protocol(meta(deprecated, priority = -1, weight = 0.5))]
+    Shout(String),
+    Ping
+}
+
+
+#[test]
+fn manifest_carries_meta() {
+    let manifest = Manifest::of::<Chat>();
+    assert_eq!(manifest.doc.as_deref(), Some("Chat messages, client to server."));
+    assert_eq!(manifest.meta.get("version"), Some(&json!(2)));
+    assert_eq!(manifest.meta.get("owner"), Some(&json!("chat team")));
+    let say = manifest.operation_named("Say").unwrap();
+    assert_eq!(say.doc.as_deref(), Some("Say something in a room."));
+    assert_eq!(say.meta.get("rate_limit"), Some(&json!(5)));
+    assert_eq!(say.args[0].doc.as_deref(), Some("Room to say it in."));
+    assert!(say.args[0].meta.is_empty());
+    assert_eq!(say.args[1].doc.as_deref(), Some("What to say."));
+    assert_eq!(say.args[1].meta.get("markdown"), Some(&json!(true)));
+    assert_eq!(say.args[1].max_len, Some(200));
+    let shout = manifest.operation_named("Shout").unwrap();
+    assert_eq!(shout.doc, None);
+    assert_eq!(shout.meta.get("deprecated"), Some(&json!(true)));
+    assert_eq!(shout.meta.get("priority"), Some(&json!(-1)));
+    assert_eq!(shout.meta.get("weight"), Some(&json!(0.5)));
+    let ping = manifest.operation_named("Ping").unwrap();
+    assert!(ping.doc.is_none() && ping.meta.is_empty());
+    assert!(!Chat::manifest().contains("\"Ping\",\"opcode\":2,\"meta\"")); // nothing is written out for undecorated variants
+}
+
+
+#[test]
+fn generators_use_meta() {
+    let manifest = Manifest::of::<Chat>();
+    let ts = typescript::generate_protocol(&manifest).unwrap();
+    assert!(ts.contains("/**\n * Chat messages, client to server.\n * owner: \"chat team\"\n * version: 2\n */\nexport type Chat =\n"));
+    assert!(ts.contains("    /**\n     * Say something in a room.\n     * rate_limit: 5\n     * since: \"1.2\"\n     * args[0] (room): Room to say it in.\n     * args[1] (text): What to say; markdown: true\n     */\n    | { op : \"Say\", args : [number, string] }\n"));
+    assert!(ts.contains("    /**\n     * deprecated: true\n     * priority: -1\n     * weight: 0.5\n     */\n    | { op : \"Shout\", args : [string] }\n    | { op : \"Ping\", args : [] };\n")); // undocumented operations get no comment at all
+    let py = python::generate_protocol(&manifest).unwrap();
+    assert!(py.contains("class ChatSay:\n    \"\"\"\n    Say something in a room.\n    rate_limit: 5\n    since: \"1.2\"\n    a0 (room): Room to say it in.\n    a1 (text): What to say; markdown: true\n    \"\"\"\n\n    OPCODE : ClassVar[int] = 0\n"));
+    assert!(py.contains("class ChatPing:\n    OPCODE"));
+    assert!(py.contains("# Chat messages, client to server.\n# owner: \"chat team\"\n# version: 2\nChat = Union["));
+    let markdown = docs::generate_protocol(&manifest);
+    assert!(markdown.contains("Every operation, in opcode order.\n\nChat messages, client to server.\n\nMeta: `owner` = \"chat team\", `version` = 2\n\n"));
+    assert!(markdown.contains("Say something in a room.\n\nMeta: `rate_limit` = 5, `since` = \"1.2\"\n\n"));
+    assert!(markdown.contains("| 1 | 2 | room | `u16` | Room to say it in. |\n"));
+    assert!(markdown.contains("| 5 | len(text) | text | `UTF-8` | What to say, at most 200 bytes, `markdown` = true |\n"));
+    let html = docs::generate_html_protocol(&manifest);
+    assert!(html.contains("<p>Meta: <code>deprecated</code> = true, <code>priority</code> = -1, <code>weight</code> = 0.5</p>\n"));
+}
@@ -0,0 +1,99 @@
+// round trip: frames encoded by the derived Rust impls are decoded and re-encoded by the generated Python module, and have to come back
+// byte-for-byte identical (and decode on the Rust side to the same value). skipped if there's no python3, unless PROTOCOL_V3_REQUIRE_TOOLS is set.
+
+use protocol_v3::protocol::{ProtocolFrame, ProtocolSegment};
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::manifest::ServerManifest;
+use protocol_v3::codegen::python;
+use std::process::Command;
+
+mod common;
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Client {
+    Hello,
+    Move(f32, f32),
+    Speed(#[protocol(range = 0..=100)] u8),
+    Chat(#[protocol(max_len = 64)] String),
+    Everything(u8, u16, u32, u64, i32, f32, bool, String)
+}
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Server<T : ProtocolSegment> {
+    Welcome(String, T),
+    Kick
+}
+
+
+fn python_roundtrip(module : &str, decoder : &str, frames : &[Vec<u8>]) -> Option<Vec<Vec<u8>>> {
+    let dir = std::env::temp_dir().join(format!("protocol_v3_python_{}_{}", std::process::id(), decoder));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("generated.py"), module).unwrap();
+    let script = format!("import sys\nimport generated\nfor line in sys.stdin.read().split():\n    print(generated.{}(bytes.fromhex(line)).encode().hex())\n", decoder);
+    let input : Vec<String> = frames.iter().map(hex::encode).collect();
+    let output = Command::new("python3")
+        .arg("-c")
+        .arg(script)
+        .current_dir(&dir)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            child.stdin.take().unwrap().write_all(input.join("\n").as_bytes())?;
+            child.wait_with_output()
+        });
+    let _ = std::fs::remove_dir_all(&dir);
+    match output {
+        Ok (output) => {
+            assert!(output.status.success(), "python failed: {}", String::from_utf8_lossy(&output.stderr));
+            Some(String::from_utf8(output.stdout).unwrap().lines().map(|line| hex::decode(line).unwrap()).collect())
+        }
+        Err (_) => {
+            common::missing("python3");
+            None
+        }
+    }
+}
+
+
+#[test]
+fn python_roundtrips_rust_frames() {
+    let manifest = ServerManifest::of::<Client, Server<u32>>("roundtrip");
+    let module = python::generate(&manifest).unwrap();
+
+    let client = vec![
+        Client::Hello,
+        Client::Move(1.5, -2.25),
+        Client::Speed(100),
+        Client::Chat("hé, wörld 🎮".to_string()),
+        Client::Everything(255, 65535, 4000000000, u64::MAX, -42, f32::MIN_POSITIVE, true, String::new())
+    ];
+    let encoded : Vec<Vec<u8>> = client.iter().map(|frame| frame.encode()).collect();
+    if let Some (back) = python_roundtrip(&module, "decode_client", &encoded) {
+        assert_eq!(back, encoded);
+        for (bytes, frame) in back.into_iter().zip(&client) {
+            assert_eq!(&Client::decode(bytes.into()).unwrap(), frame);
+        }
+    }
+
+    let server = [Server::Welcome("player one".to_string(), 7u32), Server::Kick];
+    let encoded : Vec<Vec<u8>> = server.iter().map(|frame| frame.encode()).collect();
+    if let Some (back) = python_roundtrip(&module, "decode_server_u32", &encoded) {
+        assert_eq!(back, encoded);
+    }
+}
+
+
+#[test]
+fn same_protocol_both_ways() {
+    let module = python::generate(&ServerManifest::of::<Client, Client>("game")).unwrap();
+    for declaration in ["class ClientHello:", "class ClientEverything:", "Client = Union[", "def encode_client(", "def decode_client("] {
+        assert_eq!(module.matches(declaration).count(), 1, "{}", declaration);
+    }
+    let module = python::generate(&ServerManifest::of::<Client, Server<u8>>("game")).unwrap();
+    assert_eq!(module.matches("def decode_client(").count(), 1);
+    assert_eq!(module.matches("def decode_server_u8(").count(), 1);
+}
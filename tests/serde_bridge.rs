@@ -0,0 +1,199 @@
+// the serde bridge: serde types as frame arguments, byte for byte, and their schemas in the manifest. needs --features serde.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::ProtocolFrame;
+use protocol_v3::manifest::{Manifest, ServerManifest};
+use protocol_v3::dynamic::DynamicError;
+use protocol_v3::codegen::{CodegenError, typescript, python};
+use protocol_v3::serde_bridge::{self, Serde, Schema};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::process::Command;
+
+mod common;
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Position {
+    x     : f32,
+    y     : f32,
+    tags  : Vec<String>,
+    owner : Option<u32>
+}
+
+
+impl Schema for Position {
+    fn schema() -> Value {
+        json!({
+            "struct" : "Position",
+            "fields" : [
+                { "name" : "x", "type" : f32::schema() },
+                { "name" : "y", "type" : f32::schema() },
+                { "name" : "tags", "type" : Vec::<String>::schema() },
+                { "name" : "owner", "type" : Option::<u32>::schema() }
+            ]
+        })
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum Shape {
+    Dot,
+    Circle (f32),
+    Rect { w : u16, h : u16 },
+    Group (Vec<Shape>) // recursive through a Vec
+}
+
+
+impl Schema for Shape {
+    fn schema() -> Value {
+        json!({
+            "enum" : "Shape",
+            "variants" : [
+                { "name" : "Dot", "fields" : [] },
+                { "name" : "Circle", "fields" : [{ "type" : "f32" }] },
+                { "name" : "Rect", "fields" : [{ "name" : "w", "type" : "u16" }, { "name" : "h", "type" : "u16" }] },
+                { "name" : "Group", "fields" : [{ "type" : { "seq" : { "enum" : "Shape" } } }] }
+            ]
+        })
+    }
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Note (String);
+
+
+impl Schema for Note {} // the default: opaque
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Game {
+    Place(u8, Serde<Position>, Serde<Shape>, bool),
+    Scores(Serde<BTreeMap<String, i64>>)
+}
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Notes {
+    Write(Serde<Note>)
+}
+
+
+#[test]
+fn encoding_is_exact() {
+    let position = Position { x : 1.5, y : -2.0, tags : vec!["a".to_string(), "bc".to_string()], owner : Some(7) };
+    let bytes = serde_bridge::to_bytes(&position).unwrap();
+    assert_eq!(hex::encode(&bytes), "3fc00000c000000000020001610002626301 00000007".replace(' ', ""));
+    assert_eq!(serde_bridge::from_bytes::<Position>(&mut bytes.into()).unwrap(), position);
+    assert_eq!(serde_bridge::to_bytes(&Shape::Rect { w : 3, h : 4 }).unwrap(), vec![2, 0, 3, 0, 4]);
+    assert_eq!(serde_bridge::to_bytes(&Option::<u8>::None).unwrap(), vec![0]);
+}
+
+
+#[test]
+fn frames_round_trip() {
+    let frames = vec![
+        Game::Place(3, Serde(Position { x : 0.0, y : 1.0, tags : vec![], owner : None }), Serde(Shape::Group(vec![Shape::Dot, Shape::Circle(2.5)])), true),
+        Game::Place(4, Serde(Position { x : 0.0, y : 1.0, tags : vec!["x".to_string()], owner : Some(1) }), Serde(Shape::Rect { w : 1, h : 2 }), false),
+        Game::Scores(Serde(BTreeMap::from([("alice".to_string(), 10), ("bob".to_string(), -3)])))
+    ];
+    for frame in frames {
+        let bytes = frame.encode();
+        assert_eq!(Game::decode(bytes.into()).unwrap(), frame);
+    }
+    let mut truncated = Game::Scores(Serde(BTreeMap::from([("alice".to_string(), 10)]))).encode();
+    truncated.pop();
+    assert!(Game::decode(truncated.into()).is_err());
+}
+
+
+#[test]
+fn manifest_has_schemas() {
+    let manifest = Manifest::of::<Game>();
+    let place = &manifest.operation_named("Place").unwrap().args;
+    assert_eq!(place[1].ty, "Position");
+    assert_eq!(place[1].schema, Some(json!({
+        "struct" : "Position",
+        "fields" : [
+            { "name" : "x", "type" : "f32" },
+            { "name" : "y", "type" : "f32" },
+            { "name" : "tags", "type" : { "seq" : "String" } },
+            { "name" : "owner", "type" : { "option" : "u32" } }
+        ]
+    })));
+    assert_eq!(place[2].ty, "Shape");
+    let shape = place[2].schema.as_ref().unwrap();
+    assert_eq!(shape["enum"], "Shape");
+    assert_eq!(shape["variants"][0], json!({ "name" : "Dot", "fields" : [] }));
+    assert_eq!(shape["variants"][1], json!({ "name" : "Circle", "fields" : [{ "type" : "f32" }] }));
+    assert_eq!(shape["variants"][2], json!({ "name" : "Rect", "fields" : [{ "name" : "w", "type" : "u16" }, { "name" : "h", "type" : "u16" }] }));
+    assert_eq!(shape["variants"][3], json!({ "name" : "Group", "fields" : [{ "type" : { "seq" : { "enum" : "Shape" } } }] }));
+    assert_eq!(place[0].schema, None); // built-ins don't need one
+    let scores = &manifest.operation_named("Scores").unwrap().args[0];
+    assert_eq!(scores.schema, Some(json!({ "map" : ["String", "i64"] })));
+}
+
+
+#[test]
+fn schema_defaults_to_opaque() {
+    assert_eq!(Note::schema(), json!({ "opaque" : "Note" }));
+    assert_eq!(Option::<Vec<Note>>::schema(), json!({ "option" : { "seq" : { "opaque" : "Note" } } }));
+    assert_eq!(serde_bridge::to_bytes(&Note("hi".to_string())).unwrap(), vec![0, 2, b'h', b'i']); // the wire format doesn't care either way
+}
+
+
+#[test]
+fn json_follows_the_schema() {
+    let frame = Game::Place(3, Serde(Position { x : 0.0, y : 1.5, tags : vec!["x".to_string()], owner : None }), Serde(Shape::Group(vec![Shape::Dot, Shape::Circle(2.5)])), true);
+    let json : Value = serde_json::from_str(&frame.to_json().unwrap()).unwrap();
+    assert_eq!(json, json!({ "op" : "Place", "args" : [3, { "x" : 0.0, "y" : 1.5, "tags" : ["x"], "owner" : null }, { "Group" : [["Dot", { "Circle" : [2.5] }]] }, true] }));
+    assert_eq!(Game::from_json(&json.to_string()).unwrap(), frame);
+    let frame = Game::Scores(Serde(BTreeMap::from([("alice".to_string(), 10), ("bob".to_string(), -3)])));
+    assert_eq!(frame.to_json().unwrap(), r#"{"args":[[["alice","10"],["bob","-3"]]],"op":"Scores"}"#); // i64s are strings, like u64s
+    assert_eq!(Game::from_json(r#"{"op":"Scores","args":[[["alice",10],["bob","-3"]]]}"#).unwrap(), frame);
+    assert!(matches!(Game::from_json(r#"{"op":"Place","args":[3,{"x":0,"y":1,"tags":[],"owner":null},"Square",true]}"#), Err(DynamicError::Json (_))));
+    assert!(matches!(Notes::Write(Serde(Note("hi".to_string()))).to_json(), Err(DynamicError::UnsupportedType (ty)) if ty == "Note"));
+}
+
+
+#[test]
+fn typescript_follows_the_schema() {
+    let ts = typescript::generate(&ServerManifest::of::<Game, Game>("game")).unwrap();
+    assert!(ts.contains("export type Position = { \"x\" : number, \"y\" : number, \"tags\" : Array<string>, \"owner\" : (number | null) };"));
+    assert!(ts.contains("export type Shape = \"Dot\" | { \"Circle\" : [number] } | { \"Rect\" : { \"w\" : number, \"h\" : number } } | { \"Group\" : [Array<Shape>] };"));
+    assert!(ts.contains("{ op : \"Place\", args : [number, Position, Shape, boolean] }"));
+    assert!(ts.contains("{ op : \"Scores\", args : [Array<[string, bigint]>] }"));
+    assert_eq!(ts.matches("export function writeSchema(").count(), 1);
+    assert!(ts.contains(&format!("writeSchema(w, {}, frame.args[1]);", Position::schema())));
+    assert!(ts.contains(&format!("readSchema(r, {})", Shape::schema())));
+    assert!(matches!(typescript::generate(&ServerManifest::of::<Notes, Notes>("notes")), Err(CodegenError::Opaque (ty)) if ty == "Note"));
+    assert!(matches!(python::generate(&ServerManifest::of::<Game, Game>("game")), Err(CodegenError::SchemaType (ty)) if ty == "Position"));
+}
+
+
+#[test]
+fn too_big_to_encode() {
+    let scores = Game::Scores(Serde((0..70000).map(|i| (i.to_string(), i)).collect())); // more entries than a u16 count can say
+    let e = scores.encodable().unwrap_err();
+    assert_eq!((e.operation, e.field), ("Scores", 0));
+    assert!(e.reason.contains("65535"), "{}", e.reason);
+    scores.encode(); // doesn't panic, whatever it says
+    assert!(Game::Scores(Serde(BTreeMap::new())).encodable().is_ok());
+}
+
+
+#[test]
+fn typescript_type_checks() { // the schema runtime and aliases under tsc --strict, when tsc is installed
+    let dir = std::env::temp_dir().join(format!("protocol_v3_serde_typescript_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("game.ts"), typescript::generate(&ServerManifest::of::<Game, Game>("game")).unwrap()).unwrap();
+    let output = Command::new("tsc").args(["--noEmit", "--strict", "--target", "es2020", "--lib", "es2020,dom", "game.ts"]).current_dir(&dir).output();
+    let _ = std::fs::remove_dir_all(&dir);
+    match output {
+        Ok (output) => assert!(output.status.success(), "tsc rejected the module:\n{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)),
+        Err (_) => common::missing("tsc")
+    }
+}
@@ -0,0 +1,360 @@
+// the server end to end, over real sockets: the bare-bones WebSocket client in common/ is written out by hand, so the tests see exactly the bytes on the wire.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::{ProtocolFrame, EncodeError};
+use protocol_v3::manifest::ServerManifest;
+use protocol_v3::server::{ServerConfig, WebSocketServer, WebSocketClientStream, ServerEvent, HandshakeError, Disconnect, CloseCode};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use futures::{StreamExt, SinkExt};
+use std::time::Duration;
+
+mod common;
+use common::{connect, frame, server_frame};
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Game {
+    Move(u16, u16),
+    Say(String)
+}
+
+
+async fn open(server : &mut WebSocketServer<Game, Game>, path : &str) -> (TcpStream, WebSocketClientStream<Game, Game>) { // the server only handshakes while something is waiting in accept
+    let address = server.local_addrs()[0];
+    tokio::join!(connect(address, path), server.accept())
+}
+
+
+#[tokio::test]
+async fn config_binds_and_limits() {
+    let config = ServerConfig::new("test").bind("127.0.0.1:0").max_frame_size(16).max_message_size(24);
+    let mut server = WebSocketServer::<Game, Game>::new(config).await.unwrap();
+    let (mut client, mut stream) = open(&mut server, "/play").await;
+    assert_eq!(stream.path, "/play");
+    client.write_all(&frame(0x2, true, &Game::Move(3, 4).encode())).await.unwrap();
+    assert_eq!(stream.read().await.ok(), Some(Game::Move(3, 4)));
+    let say = Game::Say("twenty bytes of text".to_string()).encode(); // 23 bytes, in two frames under the frame limit
+    client.write_all(&frame(0x2, false, &say[..12])).await.unwrap();
+    client.write_all(&frame(0x0, true, &say[12..])).await.unwrap();
+    assert_eq!(stream.read().await.ok(), Some(Game::Say("twenty bytes of text".to_string())));
+    client.write_all(&frame(0x2, false, &say[..12])).await.unwrap();
+    client.write_all(&frame(0x0, false, &say[..12])).await.unwrap(); // 24 so far: still fine
+    client.write_all(&frame(0x0, true, &[0])).await.unwrap(); // 25: over the message limit
+    assert!(matches!(stream.read().await, Err(Disconnect::Oversize)));
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x88, [&1009u16.to_be_bytes()[..], b"Frame or message too big"].concat()));
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    client.write_all(&frame(0x2, true, &say)).await.unwrap(); // 23 bytes in one frame: over the frame limit
+    assert!(matches!(stream.read().await, Err(Disconnect::Oversize)));
+}
+
+
+#[tokio::test]
+async fn config_errors_are_returned() {
+    assert_eq!(WebSocketServer::<Game, Game>::new(ServerConfig::new("test")).await.err().unwrap().kind(), std::io::ErrorKind::InvalidInput);
+    let taken = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0")).await.unwrap();
+    let address = taken.local_addrs()[0].to_string();
+    let err = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind(address.clone())).await.err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+    assert!(err.to_string().contains(&address));
+}
+
+
+#[tokio::test]
+async fn several_addresses_and_timeouts() {
+    let config = ServerConfig::new("test").bind("127.0.0.1:0").bind("127.0.0.1:0").handshake_timeout(Duration::from_millis(100)).idle_timeout(Some(Duration::from_millis(100)));
+    let mut server = WebSocketServer::<Game, Game>::new(config).await.unwrap();
+    let addresses = server.local_addrs();
+    assert_eq!(addresses.len(), 2);
+    let clients = async {
+        let mut slow = TcpStream::connect(addresses[1]).await.unwrap();
+        slow.write_all(b"GET / HTTP/1.1\r\n").await.unwrap(); // and then nothing: the handshake times out and the socket gets dropped
+        let mut rest = vec![];
+        assert_eq!(tokio::time::timeout(Duration::from_secs(5), slow.read_to_end(&mut rest)).await.unwrap().unwrap(), 0);
+        connect(addresses[1], "/quiet").await
+    };
+    let (_client, mut stream) = tokio::join!(clients, server.accept());
+    assert_eq!(stream.path, "/quiet");
+    assert!(matches!(tokio::time::timeout(Duration::from_secs(5), stream.read()).await.unwrap(), Err(Disconnect::Timeout))); // idle for longer than the timeout
+}
+
+
+async fn respond(server : &mut WebSocketServer<Game, Game>, request : &[u8]) -> (String, ServerEvent<Game, Game>) { // sends a raw request, and returns whatever the server said back along with the event it raised
+    let address = server.local_addrs()[0];
+    let client = async {
+        let mut socket = TcpStream::connect(address).await.unwrap();
+        socket.write_all(request).await.unwrap();
+        let mut response = vec![];
+        let _ = socket.read_to_end(&mut response).await; // the server hangs up after answering
+        String::from_utf8_lossy(&response).to_string()
+    };
+    tokio::join!(client, server.next_event())
+}
+
+
+#[tokio::test]
+async fn handshake_failures_are_events() {
+    let config = ServerConfig::new("test").bind("127.0.0.1:0").max_headers(5).max_request_size(256).handshake_timeout(Duration::from_millis(200));
+    let mut server = WebSocketServer::<Game, Game>::new(config).await.unwrap();
+    let upgrade = "Connection: keep-alive, Upgrade\r\nUpgrade: WebSocket\r\nSec-WebSocket-Version: 13\r\n";
+    let (response, event) = respond(&mut server, b"GET / HTTP/1.1\r\nHost : localhost\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 400 "));
+    assert!(matches!(event, ServerEvent::HandshakeFailed (_, HandshakeError::Malformed (_))));
+    let (response, event) = respond(&mut server, b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\nF: 6\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 431 "));
+    assert!(matches!(event, ServerEvent::HandshakeFailed (_, HandshakeError::TooLarge)));
+    let (response, event) = respond(&mut server, format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(300)).as_bytes()).await;
+    assert!(response.starts_with("HTTP/1.1 431 "));
+    assert!(matches!(event, ServerEvent::HandshakeFailed (_, HandshakeError::TooLarge)));
+    let (response, event) = respond(&mut server, b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 418 "));
+    assert!(matches!(event, ServerEvent::HandshakeFailed (_, HandshakeError::NotAnUpgrade)));
+    let (response, event) = respond(&mut server, b"POST / HTTP/1.1\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 405 "));
+    assert!(matches!(event, ServerEvent::HandshakeFailed (_, HandshakeError::MethodNotAllowed (method)) if method == "POST"));
+    let (response, event) = respond(&mut server, format!("GET / HTTP/1.1\r\n{}Sec-WebSocket-Key: dG9vIHNob3J0\r\n\r\n", upgrade).as_bytes()).await;
+    assert!(response.starts_with("HTTP/1.1 400 "));
+    assert!(matches!(event, ServerEvent::HandshakeFailed (_, HandshakeError::BadKey)));
+    let (response, event) = respond(&mut server, format!("GET / HTTP/1.1\r\n{}\r\n", upgrade.replace("13", "8")).as_bytes()).await;
+    assert!(response.starts_with("HTTP/1.1 426 ") && response.contains("Sec-WebSocket-Version: 13\r\n"));
+    assert!(matches!(event, ServerEvent::HandshakeFailed (_, HandshakeError::UnsupportedWebSocketVersion (Some (version))) if version == "8"));
+    let (response, event) = respond(&mut server, b"GET / HTTP/1.1\r\nHost: loc").await; // slowloris: never finishes the request
+    assert_eq!(response, "");
+    assert!(matches!(event, ServerEvent::HandshakeFailed (_, HandshakeError::Timeout)));
+    let (response, event) = respond(&mut server, b"GET /manifest HTTP/1.1\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 200 ") && response.contains("\"application_name\":\"test\""));
+    assert!(matches!(event, ServerEvent::ManifestServed (_)));
+    let address = server.local_addrs()[0];
+    let (client, event) = tokio::join!(connect(address, "/lobby?room=1"), server.next_event());
+    match event {
+        ServerEvent::Connected (stream) => {
+            assert_eq!(stream.path, "/lobby?room=1");
+            assert_eq!(stream.peer, client.local_addr().unwrap());
+        }
+        _ => panic!("the upgrade should have worked")
+    }
+}
+
+
+#[tokio::test]
+async fn manifest_is_json_whatever_the_name() {
+    let name = "say \"hi\" \\ then\nbye";
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new(name).bind("127.0.0.1:0")).await.unwrap();
+    let (response, _) = respond(&mut server, b"GET /manifest HTTP/1.1\r\n\r\n").await;
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    let manifest = ServerManifest::parse(body).unwrap();
+    assert_eq!(manifest, ServerManifest::of::<Game, Game>(name));
+}
+
+
+#[tokio::test]
+async fn pings_and_pongs() {
+    let config = ServerConfig::new("test").bind("127.0.0.1:0").ping_interval(Some(Duration::from_millis(50)));
+    let mut server = WebSocketServer::<Game, Game>::new(config).await.unwrap();
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    assert_eq!(stream.rtt(), None);
+    let script = async {
+        let (head, payload) = server_frame(&mut client).await.unwrap(); // the server's first ping, after 50ms of reading
+        assert_eq!((head, payload.clone()), (0x89, 0u64.to_be_bytes().to_vec()));
+        client.write_all(&frame(0xA, true, &payload)).await.unwrap();
+        client.write_all(&frame(0x9, true, b"hi")).await.unwrap();
+        assert_eq!(server_frame(&mut client).await.unwrap(), (0x8A, b"hi".to_vec())); // pongs echo the ping's payload
+        client.write_all(&frame(0x2, true, &Game::Move(1, 2).encode())).await.unwrap();
+    };
+    let ((), frame) = tokio::join!(script, stream.read());
+    assert_eq!(frame.ok(), Some(Game::Move(1, 2)));
+    assert!(stream.rtt().is_some());
+}
+
+
+#[tokio::test]
+async fn silent_clients_are_dead() {
+    let config = ServerConfig::new("test").bind("127.0.0.1:0").ping_interval(Some(Duration::from_millis(50))).pong_timeout(Duration::from_millis(50));
+    let mut server = WebSocketServer::<Game, Game>::new(config).await.unwrap();
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    assert!(matches!(tokio::time::timeout(Duration::from_secs(5), stream.read()).await.unwrap(), Err(Disconnect::Timeout))); // pinged, never answered
+    assert_eq!(server_frame(&mut client).await.unwrap().0, 0x89);
+}
+
+
+#[tokio::test]
+async fn close_codes_and_reasons() {
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0")).await.unwrap();
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    client.write_all(&frame(0x8, true, &[&4001u16.to_be_bytes()[..], "bye ✌".as_bytes()].concat())).await.unwrap();
+    match stream.read().await {
+        Err (Disconnect::Closed { code, reason }) => assert_eq!((code, reason.as_str()), (Some(CloseCode (4001)), "bye ✌")),
+        other => panic!("{:?}", other)
+    }
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x88, 4001u16.to_be_bytes().to_vec())); // the code is echoed back
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    client.write_all(&frame(0x8, true, &[])).await.unwrap();
+    assert!(matches!(stream.read().await, Err(Disconnect::Closed { code : None, .. })));
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x88, 1000u16.to_be_bytes().to_vec()));
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    client.write_all(&frame(0x2, true, &[9])).await.unwrap();
+    assert!(matches!(stream.read().await, Err(Disconnect::Poison (_))));
+    assert_eq!(server_frame(&mut client).await.unwrap().1[..2], 1007u16.to_be_bytes());
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    let reply = async {
+        let (head, payload) = server_frame(&mut client).await.unwrap();
+        client.write_all(&frame(0x8, true, &payload[..2])).await.unwrap();
+        (head, payload)
+    };
+    let long = "é".repeat(100); // 200 bytes, cut to 122 so it stays whole characters
+    let ((head, payload), ()) = tokio::join!(reply, stream.close(CloseCode (4000), &long));
+    assert_eq!(head, 0x88);
+    assert_eq!(payload, [&4000u16.to_be_bytes()[..], "é".repeat(61).as_bytes()].concat());
+    for reserved in [1005, 1006, 1015, 0, 5000] { // codes that can't be sent go out as NORMAL
+        let (mut client, mut stream) = open(&mut server, "/").await;
+        let reply = async {
+            let (head, payload) = server_frame(&mut client).await.unwrap();
+            client.write_all(&frame(0x8, true, &payload[..2])).await.unwrap();
+            (head, payload)
+        };
+        let ((head, payload), ()) = tokio::join!(reply, stream.close(CloseCode (reserved), "reserved"));
+        assert_eq!((head, payload), (0x88, [&1000u16.to_be_bytes()[..], b"reserved"].concat()), "{}", reserved);
+    }
+    let (client, mut stream) = open(&mut server, "/").await;
+    drop(client); // gone without a Close
+    assert!(matches!(stream.read().await, Err(Disconnect::Io (_))));
+}
+
+
+#[tokio::test]
+async fn big_messages_and_fragments() {
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0").fragment_size(None)).await.unwrap();
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    for len in [126, 65535, 65536] { // the edges of each length encoding, whole frames
+        let say = Game::Say("s".repeat(len - 3));
+        stream.send(say.clone()).await.unwrap();
+        assert_eq!(server_frame(&mut client).await.unwrap(), (0x82, say.encode().to_vec()));
+    }
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0").fragment_size(Some(100))).await.unwrap();
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    let say = Game::Say("s".repeat(247)); // 250 bytes: 100, 100 and 50
+    stream.send(say.clone()).await.unwrap();
+    let say = say.encode();
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x02, say[..100].to_vec()));
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x00, say[100..200].to_vec()));
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x80, say[200..].to_vec()));
+    stream.send(Game::Move(1, 2)).await.unwrap(); // small ones are left alone
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x82, Game::Move(1, 2).encode().to_vec()));
+}
+
+
+#[tokio::test]
+async fn split_senders() {
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0")).await.unwrap();
+    let (mut client, stream) = open(&mut server, "/split").await;
+    let (mut reader, sender) = stream.split();
+    assert_eq!(reader.path, "/split");
+    let tasks : Vec<_> = (0..3u16).map(|i| { // game systems in their own tasks, each with a clone
+        let sender = sender.clone();
+        tokio::spawn(async move { sender.send(Game::Move(i, i)).await.unwrap() })
+    }).collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let mut got = vec![];
+    for _ in 0..3 {
+        got.push(server_frame(&mut client).await.unwrap());
+    }
+    got.sort();
+    assert_eq!(got, (0..3u16).map(|i| (0x82, Game::Move(i, i).encode().to_vec())).collect::<Vec<_>>());
+    client.write_all(&frame(0x2, true, &Game::Say("hi".to_string()).encode())).await.unwrap();
+    assert_eq!(reader.read().await.ok(), Some(Game::Say("hi".to_string())));
+    sender.send(Game::Say("last".to_string())).await.unwrap();
+    let reply = async {
+        assert_eq!(server_frame(&mut client).await.unwrap(), (0x82, Game::Say("last".to_string()).encode().to_vec())); // queued before the close, so it still gets out ahead of it
+        let (head, payload) = server_frame(&mut client).await.unwrap();
+        assert_eq!(head, 0x88);
+        client.write_all(&frame(0x8, true, &payload)).await.unwrap();
+    };
+    tokio::join!(reply, reader.close(CloseCode::GOING_AWAY, "restarting"));
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !sender.is_closed() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.unwrap();
+    assert!(sender.send(Game::Move(0, 0)).await.is_err());
+    assert!(sender.try_send(Game::Move(0, 0)).is_err());
+}
+
+
+#[tokio::test]
+async fn unencodable_frames_are_refused() {
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0")).await.unwrap();
+    let (mut client, stream) = open(&mut server, "/").await;
+    let (_reader, mut sender) = stream.split();
+    let long = Game::Say("x".repeat(70000)); // a u16 can't say how long that is
+    let e = long.encodable().unwrap_err();
+    assert_eq!((e.operation, e.field), ("Say", 0));
+    for e in [sender.send(long.clone()).await.unwrap_err(), sender.try_send(long.clone()).unwrap_err(), sender.send_json(long.clone()).await.unwrap_err()] {
+        assert!(e.downcast_ref::<EncodeError>().is_some(), "{}", e);
+    }
+    assert!(matches!(SinkExt::send(&mut sender, long).await, Err(Disconnect::Io (e)) if e.kind() == std::io::ErrorKind::InvalidInput));
+    sender.send(Game::Move(1, 2)).await.unwrap(); // and nothing of the refused ones went out ahead of it
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x82, Game::Move(1, 2).encode()));
+}
+
+
+#[tokio::test]
+async fn streams_and_sinks() {
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0")).await.unwrap();
+    let (mut alice, stream) = open(&mut server, "/alice").await;
+    let (mut bob, other) = open(&mut server, "/bob").await;
+    let (alice_reader, alice_sender) = stream.split();
+    let (bob_reader, _) = other.split();
+    let mut everyone = futures::stream::select_all([alice_reader, bob_reader]);
+    alice.write_all(&frame(0x2, true, &Game::Move(1, 1).encode())).await.unwrap();
+    bob.write_all(&frame(0x2, true, &Game::Move(2, 2).encode())).await.unwrap();
+    let mut got = vec![everyone.next().await.unwrap().unwrap(), everyone.next().await.unwrap().unwrap()];
+    got.sort_by_key(|frame| format!("{:?}", frame));
+    assert_eq!(got, vec![Game::Move(1, 1), Game::Move(2, 2)]);
+    futures::stream::iter([Game::Say("a".to_string()), Game::Say("b".to_string())]).map(Ok).forward(alice_sender.clone()).await.unwrap();
+    assert_eq!(server_frame(&mut alice).await.unwrap(), (0x82, Game::Say("a".to_string()).encode()));
+    assert_eq!(server_frame(&mut alice).await.unwrap(), (0x82, Game::Say("b".to_string()).encode()));
+    assert!(!alice_sender.is_closed()); // forward closed its own clone of the sender, not the connection
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    let move_frame = frame(0x2, true, &Game::Move(3, 3).encode());
+    client.write_all(&move_frame[..4]).await.unwrap(); // half a frame, then the read gets dropped
+    assert!(tokio::time::timeout(Duration::from_millis(50), stream.read()).await.is_err());
+    client.write_all(&move_frame[4..]).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().ok(), Some(Game::Move(3, 3))); // picks up where the dropped read left off
+    stream.feed(Game::Say("bye".to_string())).await.unwrap();
+    SinkExt::close(&mut stream).await.unwrap(); // a normal Close, after what's queued
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x82, Game::Say("bye".to_string()).encode()));
+    assert_eq!(server_frame(&mut client).await.unwrap(), (0x88, 1000u16.to_be_bytes().to_vec()));
+    client.write_all(&frame(0x8, true, &1000u16.to_be_bytes())).await.unwrap();
+    assert!(matches!(stream.next().await, Some(Err(Disconnect::Closed { code : Some (CloseCode::NORMAL), .. }))));
+    assert!(stream.next().await.is_none());
+}
+
+
+#[tokio::test]
+async fn close_waits_for_room() {
+    let mut server = WebSocketServer::<Game, Game>::new(ServerConfig::new("test").bind("127.0.0.1:0").send_queue(2)).await.unwrap();
+    let (mut client, mut stream) = open(&mut server, "/").await;
+    let big = Game::Say("x".repeat(60000));
+    while tokio::time::timeout(Duration::from_millis(100), stream.send(big.clone())).await.is_ok() {} // the client isn't reading: the socket fills, the writer task gets stuck, then the queue fills up behind it
+    for i in 0..40u8 { // and the pongs fill the control queue
+        client.write_all(&frame(0x9, true, &[i])).await.unwrap();
+    }
+    assert!(tokio::time::timeout(Duration::from_millis(200), stream.read()).await.is_err());
+    assert!(tokio::time::timeout(Duration::from_millis(100), SinkExt::close(&mut stream)).await.is_err()); // no room for the Close yet
+    let drain = async {
+        let mut pongs = 0;
+        loop {
+            match server_frame(&mut client).await.unwrap() {
+                (0x88, payload) => return (pongs, payload),
+                (0x8A, _) => pongs += 1,
+                _ => {}
+            }
+        }
+    };
+    let (closed, (pongs, payload)) = tokio::time::timeout(Duration::from_secs(10), async { tokio::join!(SinkExt::close(&mut stream), drain) }).await.expect("the Close never went out");
+    closed.unwrap();
+    assert!(pongs > 0);
+    assert_eq!(payload, 1000u16.to_be_bytes().to_vec());
+}
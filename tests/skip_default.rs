@@ -0,0 +1,57 @@
+// #[protocol(skip)] and #[protocol(default = "...")]: the fields stay off the wire and out of the manifest, and decode as Default::default() or the expression.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::ProtocolFrame;
+use protocol_v3::manifest::Manifest;
+
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Session { // server-side bookkeeping with no ProtocolSegment impl, which is fine since it's never encoded
+    seen : u32
+}
+
+
+fn origin() -> (u16, u16) {
+    (100, 200)
+}
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Game {
+    Move(#[protocol(skip)] Session, u16, #[protocol(default = "42")] u32),
+    Say {
+        #[protocol(default = "String::from(\"lobby\")")]
+        room : String,
+        text : String,
+        #[protocol(skip)]
+        session : Session
+    },
+    Spawn(#[protocol(default = "origin().0")] u16, #[protocol(default = "origin().1")] u16)
+}
+
+
+#[test]
+fn skipped_fields_stay_off_the_wire() {
+    let frame = Game::Move(Session { seen : 9 }, 0x0102, 7);
+    assert_eq!(frame.encode(), vec![0, 1, 2]);
+    assert_eq!(Game::decode(frame.encode().into()).unwrap(), Game::Move(Session::default(), 0x0102, 42));
+    let frame = Game::Say { room : "kitchen".to_string(), text : "hi".to_string(), session : Session { seen : 3 } };
+    assert_eq!(frame.encode(), vec![1, 0, 2, b'h', b'i']);
+    assert_eq!(Game::decode(frame.encode().into()).unwrap(), Game::Say { room : "lobby".to_string(), text : "hi".to_string(), session : Session::default() });
+    assert_eq!(Game::Spawn(1, 2).encode(), vec![2]);
+    assert_eq!(Game::decode(vec![2].into()).unwrap(), Game::Spawn(100, 200));
+}
+
+
+#[test]
+fn skipped_fields_are_not_in_the_manifest() {
+    let manifest = Manifest::of::<Game>();
+    let moves = manifest.operation_named("Move").unwrap();
+    assert_eq!(moves.args.len(), 1);
+    assert_eq!(moves.args[0].ty, "u16");
+    let say = manifest.operation_named("Say").unwrap();
+    assert_eq!(say.args.len(), 1);
+    assert_eq!(say.args[0].name.as_deref(), Some("text"));
+    assert!(manifest.operation_named("Spawn").unwrap().args.is_empty());
+    assert!(!Game::manifest().contains("room") && !Game::manifest().contains("session"));
+}
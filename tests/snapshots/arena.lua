@@ -0,0 +1,146 @@
+-- generated by protocol_v3 from the Arena manifest. don't edit this by hand, regenerate it.
+-- load it with wireshark -X lua_script:this_file.lua, or put it in your personal Lua plugins folder.
+
+local proto = Proto("arena", "Arena (protocol_v3)")
+proto.prefs.port = Pref.uint("Server port", 8080, "TCP port the protocol_v3 server listens on; frames sent to it are incoming")
+
+local f_opcode = ProtoField.uint8("arena.opcode", "Opcode", base.DEC)
+local f_length = ProtoField.uint16("arena.length", "String length", base.DEC)
+local e_malformed = ProtoExpert.new("arena.malformed", "Malformed frame", expert.group.MALFORMED, expert.severity.ERROR)
+local e_invalid = ProtoExpert.new("arena.invalid", "Value fails validation", expert.group.PROTOCOL, expert.severity.WARN)
+
+local SIZES = { u8 = 1, u16 = 2, u32 = 4, u64 = 8, i32 = 4, f32 = 4, bool = 1 }
+
+local function read(range, ty)
+    if ty == "u64" then
+        return range:uint64():tonumber()
+    elseif ty == "i32" then
+        return range:int()
+    elseif ty == "f32" then
+        return range:float()
+    else
+        return range:uint()
+    end
+end
+
+local function dissect_frame(tvb, pinfo, tree, protocol, operations)
+    local subtree = tree:add(proto, tvb(), protocol)
+    if tvb:len() < 1 then
+        subtree:add_proto_expert_info(e_malformed, "empty frame")
+        return
+    end
+    local opcode = tvb(0, 1):uint()
+    subtree:add(f_opcode, tvb(0, 1))
+    local op = operations[opcode]
+    if op == nil then
+        subtree:add_proto_expert_info(e_malformed, "unknown opcode " .. opcode)
+        pinfo.cols.info:set(protocol .. " unknown opcode " .. opcode)
+        return
+    end
+    subtree:append_text(": " .. op.name)
+    pinfo.cols.info:set(protocol .. "." .. op.name)
+    local offset = 1
+    for _, arg in ipairs(op.args) do
+        local size = SIZES[arg.type]
+        local start = offset
+        if arg.type == "String" then
+            if offset + 2 > tvb:len() then
+                subtree:add_proto_expert_info(e_malformed, "frame is truncated")
+                return
+            end
+            size = tvb(offset, 2):uint()
+            subtree:add(f_length, tvb(offset, 2))
+            start = offset + 2
+        end
+        if start + size > tvb:len() then
+            subtree:add_proto_expert_info(e_malformed, "frame is truncated")
+            return
+        end
+        local item = subtree:add(arg.field, tvb(start, size))
+        if arg.check then
+            local value = size
+            if arg.type ~= "String" then
+                value = read(tvb(start, size), arg.type)
+            end
+            local problem = arg.check(value)
+            if problem then
+                item:add_proto_expert_info(e_invalid, problem)
+            end
+        end
+        offset = start + size
+    end
+    if offset < tvb:len() then
+        subtree:add_proto_expert_info(e_malformed, (tvb:len() - offset) .. " trailing bytes")
+    end
+end
+
+
+local incoming = { -- Client
+    [0] = { name = "Join", args = {
+        { type = "String", field = ProtoField.string("arena.in.join.a0", "a0 (String)"), check = function(value) if not (value <= 16) then return "must be at most 16 bytes" end end },
+    } },
+    [1] = { name = "Move", args = {
+        { type = "f32", field = ProtoField.float("arena.in.move.a0", "a0 (f32)") },
+        { type = "f32", field = ProtoField.float("arena.in.move.a1", "a1 (f32)") },
+    } },
+    [2] = { name = "Aim", args = {
+        { type = "u16", field = ProtoField.uint16("arena.in.aim.a0", "a0 (u16)", base.DEC), check = function(value) if not (value >= 0) then return "must be at least 0" end if not (value < 360) then return "must be less than 360" end end },
+    } },
+    [3] = { name = "Fire", args = {
+    } },
+    [4] = { name = "Everything", args = {
+        { type = "u8", field = ProtoField.uint8("arena.in.everything.a0", "a0 (u8)", base.DEC) },
+        { type = "u16", field = ProtoField.uint16("arena.in.everything.a1", "a1 (u16)", base.DEC) },
+        { type = "u32", field = ProtoField.uint32("arena.in.everything.a2", "a2 (u32)", base.DEC) },
+        { type = "u64", field = ProtoField.uint64("arena.in.everything.a3", "a3 (u64)", base.DEC) },
+        { type = "i32", field = ProtoField.int32("arena.in.everything.a4", "a4 (i32)", base.DEC) },
+        { type = "f32", field = ProtoField.float("arena.in.everything.a5", "a5 (f32)") },
+        { type = "bool", field = ProtoField.bool("arena.in.everything.a6", "a6 (bool)") },
+        { type = "String", field = ProtoField.string("arena.in.everything.a7", "a7 (String)") },
+    } },
+}
+
+local outgoing = { -- Server<u64>
+    [0] = { name = "Welcome", args = {
+        { type = "u32", field = ProtoField.uint32("arena.out.welcome.a0", "a0 (u32)", base.DEC) },
+        { type = "u64", field = ProtoField.uint64("arena.out.welcome.a1", "a1 (u64)", base.DEC) },
+    } },
+    [1] = { name = "Hit", args = {
+        { type = "u32", field = ProtoField.uint32("arena.out.hit.a0", "a0 (u32)", base.DEC) },
+        { type = "i32", field = ProtoField.int32("arena.out.hit.a1", "a1 (i32)", base.DEC), check = function(value) if not (value >= -100) then return "must be at least -100" end if not (value <= 100) then return "must be at most 100" end end },
+    } },
+    [2] = { name = "Kick", args = {
+        { type = "String", field = ProtoField.string("arena.out.kick.a0", "a0 (String)") },
+    } },
+}
+
+local fields = { f_opcode, f_length }
+for _, operations in ipairs({ incoming, outgoing }) do
+    for _, op in pairs(operations) do
+        for _, arg in ipairs(op.args) do
+            table.insert(fields, arg.field)
+        end
+    end
+end
+proto.fields = fields
+proto.experts = { e_malformed, e_invalid }
+
+function proto.dissector(tvb, pinfo, tree)
+    pinfo.cols.protocol:set("Arena")
+    if pinfo.dst_port == proto.prefs.port then
+        dissect_frame(tvb, pinfo, tree, "Client", incoming)
+    else
+        dissect_frame(tvb, pinfo, tree, "Server<u64>", outgoing)
+    end
+    return tvb:len()
+end
+
+local ws_port = DissectorTable.get("ws.port")
+local registered_port = proto.prefs.port
+ws_port:add(registered_port, proto)
+
+function proto.prefs_changed()
+    ws_port:remove(registered_port, proto)
+    registered_port = proto.prefs.port
+    ws_port:add(registered_port, proto)
+end
@@ -0,0 +1,68 @@
+// the generated TypeScript module: each protocol declared once, whether the server uses two enums or the same one both ways, a header that
+// stays a comment whatever the application is called, and output that type-checks under tsc --strict (when tsc is installed).
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::manifest::ServerManifest;
+use protocol_v3::codegen::typescript;
+use std::process::Command;
+
+mod common;
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Game {
+    Move(u16, u16),
+    Say(String)
+}
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Lobby {
+    Join(String),
+    Leave
+}
+
+
+#[test]
+fn same_protocol_both_ways() {
+    let ts = typescript::generate(&ServerManifest::of::<Game, Game>("game")).unwrap();
+    for export in ["export type Game =", "export function encodeGame(", "export function decodeGame(", "export class Connection"] {
+        assert_eq!(ts.matches(export).count(), 1, "{}", export);
+    }
+    assert!(ts.contains("send(frame : Game) {\n        this.socket.send(encodeGame(frame));"));
+    assert!(ts.contains("listener(decodeGame(new Uint8Array(event.data)));"));
+}
+
+
+#[test]
+fn two_protocols() {
+    let ts = typescript::generate(&ServerManifest::of::<Lobby, Game>("game")).unwrap();
+    for export in ["export type Game =", "export function encodeGame(", "export function decodeGame(", "export type Lobby =", "export function encodeLobby(", "export function decodeLobby("] {
+        assert_eq!(ts.matches(export).count(), 1, "{}", export);
+    }
+    assert!(ts.contains("send(frame : Lobby) {\n        this.socket.send(encodeLobby(frame));"));
+    assert!(ts.contains("listener(decodeGame(new Uint8Array(event.data)));"));
+}
+
+
+#[test]
+fn header_stays_a_comment() {
+    let ts = typescript::generate(&ServerManifest::of::<Game, Game>("evil */ name\nexport const x = 1;")).unwrap();
+    let header = ts.lines().next().unwrap();
+    assert_eq!(header, "// generated by protocol_v3 from the evil_name_export_const_x_1 manifest. don't edit this by hand, regenerate it.");
+    assert!(!ts.contains("export const x"));
+}
+
+
+#[test]
+fn output_type_checks() {
+    let dir = std::env::temp_dir().join(format!("protocol_v3_typescript_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("lobby.ts"), typescript::generate(&ServerManifest::of::<Lobby, Game>("game")).unwrap()).unwrap();
+    let output = Command::new("tsc").args(["--noEmit", "--strict", "--target", "es2020", "--lib", "es2020,dom", "lobby.ts"]).current_dir(&dir).output();
+    let _ = std::fs::remove_dir_all(&dir);
+    match output {
+        Ok (output) => assert!(output.status.success(), "tsc rejected the module:\n{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)),
+        Err (_) => common::missing("tsc")
+    }
+}
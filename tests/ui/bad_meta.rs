@@ -0,0 +1,13 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+enum Client {
+    #[protocol(meta(owner = "net", owner = "chat"))]
+    Move(f32, f32),
+    #[protocol(meta(since = b'x'))]
+    Stop,
+    #[protocol(handler)]
+    Chat(#[protocol(meta(a::b = 1))] String)
+}
+
+fn main() {}
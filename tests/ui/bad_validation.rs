@@ -0,0 +1,9 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+enum Client {
+    Speed(#[protocol(range = 100)] u8),
+    Chat(#[protocol(skip, max_len = 32)] String)
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(Clone)]
+struct Position {
+    x : f32,
+    y : f32
+}
+
+#[derive(ProtocolFrame)]
+enum Client {
+    Chat(String),
+    Move(Position)
+}
+
+fn main() {}
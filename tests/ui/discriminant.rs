@@ -0,0 +1,9 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+enum Client {
+    Hello,
+    Goodbye = 7
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+enum Client<'a> {
+    Chat(std::borrow::Cow<'a, str>)
+}
+
+fn main() {}
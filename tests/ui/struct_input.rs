@@ -0,0 +1,9 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+struct Position {
+    x : f32,
+    y : f32
+}
+
+fn main() {}
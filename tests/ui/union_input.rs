@@ -0,0 +1,9 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+union Bits {
+    int : u32,
+    float : f32
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+#[protocol(handlers)]
+enum Client {
+    Hello
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+enum Client {
+    Move(f32, #[protocol(skipped)] f32),
+    Chat(#[protocol(default = 5)] String)
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+
+#[derive(ProtocolFrame)]
+enum Client {
+    Name(&'static str),
+    Key([u8; 4]),
+    Position((f32, f32))
+}
+
+fn main() {}
@@ -0,0 +1,22 @@
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::server::{WebSocketClientStream, ClientSender};
+
+#[derive(ProtocolFrame)]
+enum Up {
+    Hello(u32)
+}
+
+#[derive(ProtocolFrame)]
+enum Down {
+    Welcome(u32)
+}
+
+fn echo(stream : &mut WebSocketClientStream<Up, Down>) { // what came in can't go back out the same way
+    let _ = stream.send(Up::Hello(1));
+}
+
+fn push(sender : &ClientSender<Down>) {
+    let _ = sender.try_send(Up::Hello(2));
+}
+
+fn main() {}
@@ -0,0 +1,91 @@
+// range, max_len and validate on derived frames: every failure kind, at and just past the limits, and field numbers that line up with the manifest.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::{ProtocolFrame, DecodeError, ValidationError, ValidationFailure};
+use protocol_v3::manifest::Manifest;
+use protocol_v3::dynamic::{DynamicFrame, DynamicError};
+
+
+fn no_shouting(text : &String) -> Result<(), String> {
+    if text.chars().any(|c| c.is_uppercase()) {
+        return Err(format!("{:?} is shouting", text));
+    }
+    Ok(())
+}
+
+
+#[derive(ProtocolFrame, Debug, Clone, PartialEq)]
+enum Rules {
+    Speed(#[protocol(range = 10..=20)] u8),
+    Name(u8, #[protocol(max_len = 4)] String),
+    Chat(#[protocol(validate = "no_shouting")] String),
+    Hidden(#[protocol(skip)] u32, #[protocol(range = 0..=5)] u8)
+}
+
+
+fn decode(frame : &Rules) -> Result<Rules, DecodeError> {
+    Rules::decode(frame.encode().into())
+}
+
+
+fn invalid(frame : &Rules) -> ValidationError {
+    match decode(frame) {
+        Err(DecodeError::Invalid (e)) => e,
+        other => panic!("{:?} should have failed validation, got {:?}", frame, other)
+    }
+}
+
+
+#[test]
+fn range() {
+    for speed in [10, 15, 20] {
+        assert_eq!(decode(&Rules::Speed(speed)).unwrap(), Rules::Speed(speed));
+    }
+    for speed in [0, 9, 21, 255] {
+        let e = invalid(&Rules::Speed(speed));
+        assert_eq!((e.operation, e.field), ("Speed", 0));
+        assert!(matches!(e.failure, ValidationFailure::OutOfRange));
+    }
+}
+
+
+#[test]
+fn max_len() {
+    for name in ["", "abcd", "éé"] { // the limit is in bytes, and é is two of them
+        assert_eq!(decode(&Rules::Name(1, name.to_string())).unwrap(), Rules::Name(1, name.to_string()));
+    }
+    let e = invalid(&Rules::Name(1, "abcde".to_string()));
+    assert_eq!((e.operation, e.field), ("Name", 1));
+    assert!(matches!(e.failure, ValidationFailure::TooLong { max : 4, len : 5 }));
+    let e = invalid(&Rules::Name(1, "ééé".to_string()));
+    assert!(matches!(e.failure, ValidationFailure::TooLong { max : 4, len : 6 }));
+}
+
+
+#[test]
+fn validate_fn() {
+    assert_eq!(decode(&Rules::Chat("hello".to_string())).unwrap(), Rules::Chat("hello".to_string()));
+    let e = invalid(&Rules::Chat("HELLO".to_string()));
+    assert_eq!((e.operation, e.field), ("Chat", 0));
+    match e.failure {
+        ValidationFailure::Rejected (reason) => assert_eq!(reason, "\"HELLO\" is shouting"),
+        other => panic!("{:?}", other)
+    }
+}
+
+
+#[test]
+fn fields_are_numbered_on_the_wire() {
+    assert_eq!(decode(&Rules::Hidden(7, 5)).unwrap(), Rules::Hidden(0, 5));
+    let frame = Rules::Hidden(7, 6);
+    let e = invalid(&frame);
+    assert_eq!((e.operation, e.field), ("Hidden", 0)); // the skipped field doesn't count
+    let manifest = Manifest::of::<Rules>();
+    let hidden = manifest.operation_named("Hidden").unwrap();
+    assert_eq!(hidden.args.len(), 1);
+    assert!(hidden.args[e.field].range.is_some());
+    match DynamicFrame::decode(&manifest, &frame.encode()) {
+        Err(DynamicError::Invalid { field, .. }) => assert_eq!(field, e.field),
+        other => panic!("{:?}", other)
+    }
+}
@@ -0,0 +1,56 @@
+// snapshot of the generated Wireshark dissector for a sample application. after an intentional change to the generator, rewrite the snapshot
+// with PROTOCOL_V3_BLESS=1 cargo test --test wireshark_dissector. if luac is around, the snapshot also has to compile.
+
+use protocol_v3::protocol_v3_macro::ProtocolFrame;
+use protocol_v3::protocol::ProtocolSegment;
+use protocol_v3::manifest::{Manifest, ServerManifest};
+use protocol_v3::codegen::wireshark;
+use std::process::Command;
+
+mod common;
+
+
+const SNAPSHOT : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots/arena.lua");
+
+
+#[allow(dead_code)]
+#[derive(ProtocolFrame)]
+enum Client {
+    Join(#[protocol(max_len = 16)] String),
+    Move(f32, f32),
+    Aim(#[protocol(range = 0..360)] u16),
+    Fire,
+    Everything(u8, u16, u32, u64, i32, f32, bool, String)
+}
+
+
+#[allow(dead_code)]
+#[derive(ProtocolFrame)]
+enum Server<T : ProtocolSegment> {
+    Welcome(u32, T),
+    Hit(u32, #[protocol(range = -100..=100)] i32),
+    Kick(String)
+}
+
+
+#[test]
+fn dissector_matches_snapshot() {
+    let generated = wireshark::generate(&ServerManifest::of::<Client, Server<u64>>("Arena")).unwrap();
+    if std::env::var_os("PROTOCOL_V3_BLESS").is_some() {
+        std::fs::write(SNAPSHOT, &generated).unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(SNAPSHOT).unwrap(), generated, "tests/snapshots/arena.lua is out of date; rerun with PROTOCOL_V3_BLESS=1 if the change is intentional");
+    match Command::new("luac").arg("-p").arg(SNAPSHOT).output() {
+        Ok (output) => assert!(output.status.success(), "the dissector doesn't compile:\n{}", String::from_utf8_lossy(&output.stderr)),
+        Err (_) => common::missing("luac")
+    }
+}
+
+
+#[test]
+fn single_protocol_dissector() {
+    let generated = wireshark::generate_protocol(&Manifest::of::<Client>()).unwrap();
+    assert!(generated.contains("local proto = Proto(\"client\", \"Client (protocol_v3)\")\n"));
+    assert!(generated.contains("ProtoField.string(\"client.join.a0\", \"a0 (String)\"), check = function(value) if not (value <= 16) then return \"must be at most 16 bytes\" end end"));
+    assert!(generated.contains("    dissect_frame(tvb, pinfo, tree, \"Client\", operations)\n"));
+}